@@ -1,9 +1,13 @@
 //! Initialize behavior config boilerplate of RMK
 //!
 
-use crate::config::{OneShotConfig, TapHoldConfig, TriLayerConfig};
+use crate::config::{
+    AutocorrectConfig, DoubleTapConfig, DoubleTapLockConfig, LayerBehaviorConfig, MagicConfig,
+    ModLayerConfig, OneShotConfig, PositionRemapConfig, TapHoldConfig, TriLayerConfig,
+};
 use crate::keyboard_config::KeyboardConfig;
-use quote::quote;
+use crate::layout::parse_modifiers;
+use quote::{format_ident, quote};
 
 fn expand_tri_layer(tri_layer: &Option<TriLayerConfig>) -> proc_macro2::TokenStream {
     match tri_layer {
@@ -21,16 +25,23 @@ fn expand_one_shot(one_shot: &Option<OneShotConfig>) -> proc_macro2::TokenStream
     let default = quote! {::rmk::config::OneShotConfig::default()};
     match one_shot {
         Some(one_shot) => {
-            let millis = match &one_shot.timeout {
-                Some(t) => t.0,
-                None => return default,
+            let timeout = match &one_shot.timeout {
+                Some(t) => {
+                    let millis = t.0;
+                    quote! { timeout: ::embassy_time::Duration::from_millis(#millis), }
+                }
+                None => quote! {},
+            };
+            let cancel_on_repeat = match one_shot.cancel_on_repeat {
+                Some(enable) => quote! { cancel_on_repeat: #enable, },
+                None => quote! {},
             };
-
-            let timeout = quote! {::embassy_time::Duration::from_millis(#millis)};
 
             quote! {
                 ::rmk::config::OneShotConfig {
-                    timeout: #timeout,
+                    #timeout
+                    #cancel_on_repeat
+                    ..Default::default()
                 }
             }
         }
@@ -38,6 +49,20 @@ fn expand_one_shot(one_shot: &Option<OneShotConfig>) -> proc_macro2::TokenStream
     }
 }
 
+fn expand_double_tap(double_tap: &Option<DoubleTapConfig>) -> proc_macro2::TokenStream {
+    match double_tap.as_ref().and_then(|d| d.timeout.as_ref()) {
+        Some(t) => {
+            let millis = t.0;
+            quote! {
+                ::rmk::config::DoubleTapConfig {
+                    timeout: ::embassy_time::Duration::from_millis(#millis),
+                }
+            }
+        }
+        None => quote! {::rmk::config::DoubleTapConfig::default()},
+    }
+}
+
 fn expand_tap_hold(tap_hold: &Option<TapHoldConfig>) -> proc_macro2::TokenStream {
     let default = quote! {::rmk::config::TapHoldConfig::default()};
     match tap_hold {
@@ -67,6 +92,14 @@ fn expand_tap_hold(tap_hold: &Option<TapHoldConfig>) -> proc_macro2::TokenStream
                 }
                 None => quote! {},
             };
+            let eager_hold = match tap_hold.eager_hold {
+                Some(enable) => quote! { eager_hold: #enable, },
+                None => quote! {},
+            };
+            let disable_during_modifier = match tap_hold.disable_during_modifier {
+                Some(enable) => quote! { disable_during_modifier: #enable, },
+                None => quote! {},
+            };
 
             quote! {
                 ::rmk::config::TapHoldConfig {
@@ -74,6 +107,8 @@ fn expand_tap_hold(tap_hold: &Option<TapHoldConfig>) -> proc_macro2::TokenStream
                     #prior_idle_time
                     #post_wait_time
                     #hold_timeout
+                    #eager_hold
+                    #disable_during_modifier
                     ..Default::default()
                 }
             }
@@ -82,16 +117,223 @@ fn expand_tap_hold(tap_hold: &Option<TapHoldConfig>) -> proc_macro2::TokenStream
     }
 }
 
+fn expand_host_layout(host_layout: &Option<String>) -> proc_macro2::TokenStream {
+    match host_layout.as_deref() {
+        Some("uk") => quote! {::rmk::config::HostLayout::Uk},
+        Some("de") => quote! {::rmk::config::HostLayout::De},
+        Some("fr") => quote! {::rmk::config::HostLayout::Fr},
+        _ => quote! {::rmk::config::HostLayout::Us},
+    }
+}
+
+fn expand_idle_layer_reset(seconds: &Option<u32>) -> proc_macro2::TokenStream {
+    match seconds {
+        Some(seconds) if *seconds > 0 => {
+            let seconds = *seconds as u64;
+            quote! {::core::option::Option::Some(::embassy_time::Duration::from_secs(#seconds))}
+        }
+        _ => quote! {::core::option::Option::None},
+    }
+}
+
+fn expand_option_millis(millis: &Option<u32>) -> proc_macro2::TokenStream {
+    match millis {
+        Some(millis) if *millis > 0 => {
+            let millis = *millis as u64;
+            quote! {::core::option::Option::Some(::embassy_time::Duration::from_millis(#millis))}
+        }
+        _ => quote! {::core::option::Option::None},
+    }
+}
+
+fn expand_option_u8(value: &Option<u8>) -> proc_macro2::TokenStream {
+    match value {
+        Some(value) => quote! {::core::option::Option::Some(#value)},
+        None => quote! {::core::option::Option::None},
+    }
+}
+
+fn expand_layer_base_modifiers(
+    layers: &Option<Vec<LayerBehaviorConfig>>,
+) -> Vec<proc_macro2::TokenStream> {
+    layers
+        .iter()
+        .flatten()
+        .filter_map(|l| {
+            let layer = l.layer;
+            l.base_modifiers.as_ref().map(|m| {
+                let modifiers = parse_modifiers(m);
+                quote! { behavior_config.layer_base_modifiers.push((#layer, #modifiers)).ok(); }
+            })
+        })
+        .collect()
+}
+
+fn expand_layer_mouse(layers: &Option<Vec<LayerBehaviorConfig>>) -> Vec<proc_macro2::TokenStream> {
+    layers
+        .iter()
+        .flatten()
+        .filter(|l| l.mouse_speed.is_some() || l.scroll_invert.is_some())
+        .map(|l| {
+            let layer = l.layer;
+            let speed_percent = (l.mouse_speed.unwrap_or(1.0) * 100.0).round() as u16;
+            let scroll_invert = l.scroll_invert.unwrap_or(false);
+            quote! {
+                behavior_config.layer_mouse.push((#layer, ::rmk::config::LayerMouseConfig {
+                    speed_percent: #speed_percent,
+                    scroll_invert: #scroll_invert,
+                })).ok();
+            }
+        })
+        .collect()
+}
+
+fn expand_mod_layers(mod_layers: &Option<Vec<ModLayerConfig>>) -> Vec<proc_macro2::TokenStream> {
+    mod_layers
+        .iter()
+        .flatten()
+        .map(|m| {
+            let layer = m.layer;
+            let modifiers = parse_modifiers(&m.modifiers);
+            quote! { behavior_config.mod_layers.push((#modifiers, #layer)).ok(); }
+        })
+        .collect()
+}
+
+fn expand_double_tap_lock(
+    double_tap_lock: &Option<Vec<DoubleTapLockConfig>>,
+) -> Vec<proc_macro2::TokenStream> {
+    double_tap_lock
+        .iter()
+        .flatten()
+        .map(|l| {
+            let modifier = format_ident!("{}", l.modifier);
+            let output = format_ident!("{}", l.output);
+            quote! {
+                behavior_config.double_tap_lock.push((
+                    ::rmk::keycode::KeyCode::#modifier,
+                    ::rmk::keycode::KeyCode::#output,
+                )).ok();
+            }
+        })
+        .collect()
+}
+
+fn expand_position_remap(
+    position_remap: &Option<Vec<PositionRemapConfig>>,
+) -> Vec<proc_macro2::TokenStream> {
+    position_remap
+        .iter()
+        .flatten()
+        .map(|r| {
+            let [from_row, from_col] = r.from;
+            let [to_row, to_col] = r.to;
+            quote! {
+                behavior_config.position_remap.push(((#from_row, #from_col), (#to_row, #to_col))).ok();
+            }
+        })
+        .collect()
+}
+
+fn expand_magic(magic: &Option<MagicConfig>) -> proc_macro2::TokenStream {
+    match magic {
+        Some(m) => {
+            let swap_ctrl_caps = m.swap_ctrl_caps;
+            let capslock_as_ctrl = m.capslock_as_ctrl;
+            let swap_lalt_lgui = m.swap_lalt_lgui;
+            let swap_ralt_rgui = m.swap_ralt_rgui;
+            let no_gui = m.no_gui;
+            let swap_grave_esc = m.swap_grave_esc;
+            let swap_backslash_backspace = m.swap_backslash_backspace;
+            let swap_lctl_lgui = m.swap_lctl_lgui;
+            let swap_rctl_rgui = m.swap_rctl_rgui;
+            let swap_escape_caps = m.swap_escape_caps;
+            quote! {
+                ::rmk::config::MagicConfig {
+                    swap_ctrl_caps: #swap_ctrl_caps,
+                    capslock_as_ctrl: #capslock_as_ctrl,
+                    swap_lalt_lgui: #swap_lalt_lgui,
+                    swap_ralt_rgui: #swap_ralt_rgui,
+                    no_gui: #no_gui,
+                    swap_grave_esc: #swap_grave_esc,
+                    swap_backslash_backspace: #swap_backslash_backspace,
+                    swap_lctl_lgui: #swap_lctl_lgui,
+                    swap_rctl_rgui: #swap_rctl_rgui,
+                    swap_escape_caps: #swap_escape_caps,
+                }
+            }
+        }
+        None => quote! {::rmk::config::MagicConfig::default()},
+    }
+}
+
+fn expand_autocorrect(autocorrect: &Option<AutocorrectConfig>) -> proc_macro2::TokenStream {
+    match autocorrect {
+        Some(autocorrect) => {
+            let words = autocorrect
+                .words
+                .iter()
+                .map(|(typo, correction)| quote! { (#typo, #correction) });
+            quote! {
+                behavior_config.autocorrect = ::rmk::config::AutocorrectConfig {
+                    words: &[#(#words),*],
+                };
+            }
+        }
+        None => quote! {},
+    }
+}
+
 pub(crate) fn expand_behavior_config(keyboard_config: &KeyboardConfig) -> proc_macro2::TokenStream {
     let tri_layer = expand_tri_layer(&keyboard_config.behavior.tri_layer);
     let tap_hold = expand_tap_hold(&keyboard_config.behavior.tap_hold);
     let one_shot = expand_one_shot(&keyboard_config.behavior.one_shot);
+    let double_tap = expand_double_tap(&keyboard_config.behavior.double_tap);
+    let host_layout = expand_host_layout(&keyboard_config.behavior.host_layout);
+    let idle_layer_reset =
+        expand_idle_layer_reset(&keyboard_config.behavior.idle_layer_reset_seconds);
+    let default_layer_usb = expand_option_u8(&keyboard_config.behavior.default_layer_usb);
+    let default_layer_ble = expand_option_u8(&keyboard_config.behavior.default_layer_ble);
+    let ble_consumer_repeat =
+        expand_option_millis(&keyboard_config.behavior.ble_consumer_repeat_ms);
+    let on_connect_macro = expand_option_u8(&keyboard_config.behavior.on_connect_macro);
+    let per_key_min_interval =
+        expand_option_millis(&keyboard_config.behavior.per_key_min_interval_ms);
+    let layer_base_modifiers = expand_layer_base_modifiers(&keyboard_config.behavior.layer);
+    let mod_layers = expand_mod_layers(&keyboard_config.behavior.mod_layers);
+    let double_tap_lock = expand_double_tap_lock(&keyboard_config.behavior.double_tap_lock);
+    let position_remap = expand_position_remap(&keyboard_config.behavior.position_remap);
+    let layer_mouse = expand_layer_mouse(&keyboard_config.behavior.layer);
+    let magic = expand_magic(&keyboard_config.behavior.magic);
+    let autocorrect = expand_autocorrect(&keyboard_config.behavior.autocorrect);
 
     quote! {
-        let behavior_config = ::rmk::config::BehaviorConfig {
+        #[allow(unused_mut)]
+        let mut behavior_config = ::rmk::config::BehaviorConfig {
             tri_layer: #tri_layer,
             tap_hold: #tap_hold,
             one_shot: #one_shot,
+            double_tap: #double_tap,
+            host_layout: #host_layout,
+            idle_layer_reset: #idle_layer_reset,
+            default_layer_usb: #default_layer_usb,
+            default_layer_ble: #default_layer_ble,
+            ble_consumer_repeat: #ble_consumer_repeat,
+            on_connect_macro: #on_connect_macro,
+            per_key_min_interval: #per_key_min_interval,
+            layer_base_modifiers: ::core::default::Default::default(),
+            mod_layers: ::core::default::Default::default(),
+            double_tap_lock: ::core::default::Default::default(),
+            position_remap: ::core::default::Default::default(),
+            layer_mouse: ::core::default::Default::default(),
+            magic: #magic,
+            ..::core::default::Default::default()
         };
+        #(#layer_base_modifiers)*
+        #(#mod_layers)*
+        #(#double_tap_lock)*
+        #(#position_remap)*
+        #(#layer_mouse)*
+        #autocorrect
     }
 }
@@ -63,6 +63,12 @@ pub(crate) fn bind_interrupt_default(keyboard_config: &KeyboardConfig) -> TokenS
                     charge_led: _,
                     adc_divider_measured: _,
                     adc_divider_total: _,
+                    battery_saver_percent: _,
+                    default_tx_power: _,
+                    adaptive_tx_power: _,
+                    tx_power_min: _,
+                    tx_power_max: _,
+                    profile: _,
                 }) = keyboard_config.communication.get_ble_config()
                 {
                     Some(quote! {
@@ -2,6 +2,7 @@ use proc_macro2::TokenStream as TokenStream2;
 use quote::{format_ident, quote};
 
 use crate::{
+    config::BleConfig,
     keyboard_config::{CommunicationConfig, KeyboardConfig},
     ChipSeries,
 };
@@ -132,20 +133,32 @@ pub(crate) fn expand_ble_config(keyboard_config: &KeyboardConfig) -> (TokenStrea
                         let ble_battery_config = ::rmk::config::BleBatteryConfig::new(is_charging_pin, charging_state_low_active, charge_led_pin, charge_led_low_active, saadc_option, adc_divider_measured, adc_divider_total);
                     }
                 );
+                if let Some(percent) = ble.battery_saver_percent {
+                    ble_config_tokens.extend(quote! {
+                        let ble_battery_config = ::rmk::config::BleBatteryConfig {
+                            battery_saver_percent: ::core::option::Option::Some(#percent),
+                            ..ble_battery_config
+                        };
+                    });
+                }
+                ble_config_tokens.extend(expand_tx_power_config(ble));
 
                 (
                     ble_config_tokens,
                     quote! {
                         ble_battery_config,
+                        ble_tx_power_config,
                     },
                 )
             } else {
                 (
                     quote! {
                         let ble_battery_config = ::rmk::config::BleBatteryConfig::default();
+                        let ble_tx_power_config = ::rmk::config::BleTxPowerConfig::default();
                     },
                     quote! {
                         ble_battery_config,
+                        ble_tx_power_config,
                     },
                 )
             }
@@ -153,3 +166,28 @@ pub(crate) fn expand_ble_config(keyboard_config: &KeyboardConfig) -> (TokenStrea
         _ => (quote! {}, quote! {}),
     }
 }
+
+// Tx power config of nRF BLE, fields which are not set in `keyboard.toml` use the default values in RMK
+fn expand_tx_power_config(ble: &BleConfig) -> TokenStream2 {
+    let default_tx_power = ble.default_tx_power.unwrap_or(4);
+    let adaptive_tx_power = ble.adaptive_tx_power.unwrap_or(false);
+    let tx_power_min = ble.tx_power_min.unwrap_or(-20);
+    let tx_power_max = ble.tx_power_max.unwrap_or(4);
+    // Profile numbers are validated when the config is loaded
+    let mut profile_tx_power = [None; 8];
+    for (profile, config) in ble.profile.iter().flatten() {
+        if let Ok(p) = profile.parse::<usize>() {
+            profile_tx_power[p] = config.tx_power;
+        }
+    }
+    let profile_tx_power = profile_tx_power.iter().map(|p| match p {
+        Some(power) => quote! { ::core::option::Option::Some(#power) },
+        None => quote! { ::core::option::Option::None },
+    });
+    quote! {
+        let ble_tx_power_config = ::rmk::config::BleTxPowerConfig {
+            profile_tx_power: [#(#profile_tx_power),*],
+            ..::rmk::config::BleTxPowerConfig::new(#default_tx_power, #adaptive_tx_power, #tx_power_min, #tx_power_max)
+        };
+    }
+}
@@ -90,6 +90,43 @@ pub(crate) fn usb_config_default(keyboard_config: &KeyboardConfig) -> TokenStrea
     }
 }
 
+/// Expand `host_config` field of `RmkConfig`
+pub(crate) fn expand_host_config(keyboard_config: &KeyboardConfig) -> TokenStream2 {
+    let report_coalesce = match keyboard_config.host.report_coalesce_ms {
+        Some(ms) if ms > 0 => quote! {
+            report_coalesce: ::embassy_time::Duration::from_millis(#ms),
+        },
+        _ => quote! {},
+    };
+    let primary_device = match keyboard_config.host.primary_device.as_deref() {
+        Some("consumer") => quote! {
+            primary_device: ::rmk::config::UsbPrimaryDevice::Consumer,
+        },
+        _ => quote! {},
+    };
+    let consumer_max_hold = match keyboard_config.host.consumer_max_hold_ms {
+        Some(0) => quote! {
+            consumer_max_hold: ::core::option::Option::None,
+        },
+        Some(ms) => quote! {
+            consumer_max_hold: ::core::option::Option::Some(::embassy_time::Duration::from_millis(#ms)),
+        },
+        None => quote! {},
+    };
+    if report_coalesce.is_empty() && primary_device.is_empty() && consumer_max_hold.is_empty() {
+        return quote! {};
+    }
+
+    quote! {
+        host_config: ::rmk::config::HostConfig {
+            #report_coalesce
+            #primary_device
+            #consumer_max_hold
+            ..Default::default()
+        },
+    }
+}
+
 fn override_usb_init(item_fn: &ItemFn) -> TokenStream2 {
     let initialization = item_fn.block.to_token_stream();
     quote! {
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use serde::de;
 use serde_derive::Deserialize;
 
@@ -26,6 +28,8 @@ pub struct KeyboardTomlConfig {
     pub split: Option<SplitConfig>,
     /// Input device config
     pub input_device: Option<InputDeviceConfig>,
+    /// Host config
+    pub host: Option<HostConfig>,
 }
 
 /// Configurations for keyboard info
@@ -70,6 +74,16 @@ pub struct MatrixConfig {
     pub direct_pin_low_active: bool,
     #[serde(default = "default_false")]
     pub row2col: bool,
+    /// Matrix positions `[row, col]` which are never scanned
+    pub disabled_positions: Option<Vec<[u8; 2]>>,
+    /// Scan interval in ms while any key is pressed
+    pub scan_fast_ms: Option<u32>,
+    /// Scan interval in ms while all keys are released
+    pub scan_idle_ms: Option<u32>,
+    /// Time in ms to wait for the pins to settle before the first scan
+    pub boot_settle_ms: Option<u32>,
+    /// Time in us to discharge the matrix lines after an output pin is scanned
+    pub discharge_us: Option<u32>,
 }
 
 /// Config for storage
@@ -80,6 +94,9 @@ pub struct StorageConfig {
     pub start_addr: Option<usize>,
     // Number of sectors used for storage, >= 2.
     pub num_sectors: Option<u8>,
+    // Size of a storage sector in bytes, MUST BE a multiple of the flash's erase size.
+    // The erase size of the flash is used if it's not set.
+    pub sector_size: Option<usize>,
     #[serde(default = "default_true")]
     pub enabled: bool,
     // Clear on the storage at reboot, set this to true if you want to reset the keymap
@@ -94,6 +111,25 @@ pub struct BleConfig {
     pub charge_led: Option<PinConfig>,
     pub adc_divider_measured: Option<u32>,
     pub adc_divider_total: Option<u32>,
+    /// Turn on the battery saver when the battery level drops below this percentage
+    pub battery_saver_percent: Option<u8>,
+    /// Tx power in dBm, used when adaptive tx power is disabled
+    pub default_tx_power: Option<i8>,
+    /// Adjust tx power according to the RSSI of the connection
+    pub adaptive_tx_power: Option<bool>,
+    /// Lower bound of the adaptive tx power, in dBm
+    pub tx_power_min: Option<i8>,
+    /// Upper bound of the adaptive tx power, in dBm
+    pub tx_power_max: Option<i8>,
+    /// Per-profile configs, `[ble.profile.N]`, the key is the profile number
+    pub profile: Option<HashMap<String, BleProfileConfig>>,
+}
+
+/// Config of a BLE profile
+#[derive(Clone, Default, Debug, Deserialize)]
+pub struct BleProfileConfig {
+    /// Tx power in dBm of the profile, overrides `default_tx_power`
+    pub tx_power: Option<i8>,
 }
 
 /// Config for lights
@@ -102,6 +138,10 @@ pub struct LightConfig {
     pub capslock: Option<PinConfig>,
     pub scrolllock: Option<PinConfig>,
     pub numslock: Option<PinConfig>,
+    /// Show the active layer on the lock LEDs: "lock_first" or "layer_first"
+    pub layer_indicator: Option<String>,
+    /// Show the game mode on a lock LED: "capslock", "numslock" or "scrolllock"
+    pub game_mode_indicator: Option<String>,
 }
 
 /// Config for a single pin
@@ -111,6 +151,23 @@ pub struct PinConfig {
     pub low_active: bool,
 }
 
+/// Configurations for the host interfaces
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct HostConfig {
+    /// Add a gamepad hid interface, requires `gamepad` feature of RMK
+    #[serde(default)]
+    pub gamepad: bool,
+    /// Report the battery level via a USB hid battery interface, requires `usb_battery` feature of RMK
+    #[serde(default)]
+    pub usb_battery: bool,
+    /// Coalesce keyboard reports generated within this window(ms) into one transmission, 0 or unset to send every report immediately
+    pub report_coalesce_ms: Option<u64>,
+    /// Primary hid device exposed via USB, "keyboard" or "consumer"
+    pub primary_device: Option<String>,
+    /// Send the release of a consumer or system control key again if it's released after being pressed for this long(ms), 0 to disable, default is 10000
+    pub consumer_max_hold_ms: Option<u64>,
+}
+
 /// Configurations for dependencies
 #[derive(Clone, Debug, Deserialize)]
 pub struct DependencyConfig {
@@ -140,6 +197,92 @@ pub struct BehaviorConfig {
     pub tri_layer: Option<TriLayerConfig>,
     pub tap_hold: Option<TapHoldConfig>,
     pub one_shot: Option<OneShotConfig>,
+    pub double_tap: Option<DoubleTapConfig>,
+    /// Keyboard layout of the host, used to type `Text` in macros: "us", "uk", "de" or "fr"
+    pub host_layout: Option<String>,
+    /// Reset to the default layer after the keyboard is idle for this many seconds, 0 or unset to disable
+    pub idle_layer_reset_seconds: Option<u32>,
+    /// Default layer which is applied when the keyboard is connected via USB
+    pub default_layer_usb: Option<u8>,
+    /// Default layer which is applied when the keyboard is connected via BLE
+    pub default_layer_ble: Option<u8>,
+    /// Repeat held consumer keys at this interval(ms) over BLE, 0 or unset to disable
+    pub ble_consumer_repeat_ms: Option<u32>,
+    /// Index of the macro which is run once when the keyboard is connected to the host
+    pub on_connect_macro: Option<u8>,
+    /// Suppress a press of a key within this interval(ms) after the last press of the same key, 0 or unset to disable
+    pub per_key_min_interval_ms: Option<u32>,
+    /// Per-layer behaviors, `[[behavior.layer]]`
+    pub layer: Option<Vec<LayerBehaviorConfig>>,
+    /// Layers which are active while modifiers are held, `[[behavior.mod_layers]]`
+    pub mod_layers: Option<Vec<ModLayerConfig>>,
+    /// Double tapping a modifier taps another key, `[[behavior.double_tap_lock]]`
+    pub double_tap_lock: Option<Vec<DoubleTapLockConfig>>,
+    /// Matrix positions which are treated as other positions, `[[behavior.position_remap]]`
+    pub position_remap: Option<Vec<PositionRemapConfig>>,
+    pub magic: Option<MagicConfig>,
+    pub autocorrect: Option<AutocorrectConfig>,
+}
+
+/// Initial state of magic keycodes
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct MagicConfig {
+    pub swap_ctrl_caps: bool,
+    pub capslock_as_ctrl: bool,
+    pub swap_lalt_lgui: bool,
+    pub swap_ralt_rgui: bool,
+    pub no_gui: bool,
+    pub swap_grave_esc: bool,
+    pub swap_backslash_backspace: bool,
+    pub swap_lctl_lgui: bool,
+    pub swap_rctl_rgui: bool,
+    pub swap_escape_caps: bool,
+}
+
+/// Configurations for autocorrect, requires `autocorrect` feature of RMK
+#[derive(Clone, Debug, Deserialize)]
+pub struct AutocorrectConfig {
+    /// List of `["typo", "correction"]`
+    pub words: Vec<(String, String)>,
+}
+
+/// Configurations of a layer's behavior
+#[derive(Clone, Debug, Deserialize)]
+pub struct LayerBehaviorConfig {
+    pub layer: u8,
+    /// Modifiers applied to the keys of the layer while it's active, in types of "LCtrl | LShift"
+    pub base_modifiers: Option<String>,
+    /// Cursor speed of mouse keys while the layer is active, relative to the default speed, e.g. 0.5
+    pub mouse_speed: Option<f32>,
+    /// Invert the direction of mouse wheel keys while the layer is active
+    pub scroll_invert: Option<bool>,
+}
+
+/// Configurations of a layer which is active while the modifiers are held
+#[derive(Clone, Debug, Deserialize)]
+pub struct ModLayerConfig {
+    /// Modifiers which activate the layer, in types of "LGui" or "LCtrl | LShift"
+    pub modifiers: String,
+    pub layer: u8,
+}
+
+/// Configurations of a modifier which taps another key when it's double tapped
+#[derive(Clone, Debug, Deserialize)]
+pub struct DoubleTapLockConfig {
+    /// Modifier key, such as "LShift"
+    pub modifier: String,
+    /// Key which is tapped when the modifier is double tapped, such as "CapsLock"
+    pub output: String,
+}
+
+/// Configurations of a remapped matrix position
+#[derive(Clone, Debug, Deserialize)]
+pub struct PositionRemapConfig {
+    /// Physical position, `[row, col]`
+    pub from: [u8; 2],
+    /// Position whose keymap entries are used, `[row, col]`
+    pub to: [u8; 2],
 }
 
 /// Configurations for tap hold
@@ -149,6 +292,8 @@ pub struct TapHoldConfig {
     pub prior_idle_time: Option<DurationMillis>,
     pub post_wait_time: Option<DurationMillis>,
     pub hold_timeout: Option<DurationMillis>,
+    pub eager_hold: Option<bool>,
+    pub disable_during_modifier: Option<bool>,
 }
 
 /// Configurations for tri layer
@@ -163,6 +308,13 @@ pub struct TriLayerConfig {
 #[derive(Clone, Debug, Deserialize)]
 pub struct OneShotConfig {
     pub timeout: Option<DurationMillis>,
+    pub cancel_on_repeat: Option<bool>,
+}
+
+/// Configurations for double tap
+#[derive(Clone, Debug, Deserialize)]
+pub struct DoubleTapConfig {
+    pub timeout: Option<DurationMillis>,
 }
 
 /// Configurations for split keyboards
@@ -171,6 +323,9 @@ pub struct SplitConfig {
     pub connection: String,
     pub central: SplitBoardConfig,
     pub peripheral: Vec<SplitBoardConfig>,
+    /// Forward the led indicator state from the host to peripherals, whose indicator LEDs are set in `[split.peripheral.light]`
+    #[serde(default)]
+    pub mirror_indicators: bool,
 }
 
 /// Configurations for each split board
@@ -195,6 +350,8 @@ pub struct SplitBoardConfig {
     pub matrix: MatrixConfig,
     /// Input device config for the split
     pub input_device: Option<InputDeviceConfig>,
+    /// Indicator LEDs on the peripheral, which mirror the central's led indicator state
+    pub light: Option<LightConfig>,
 }
 
 /// Serial port config
@@ -259,6 +416,8 @@ pub struct EncoderConfig {
     pub resolution: Option<u8>,
     pub clockwise_pos: (u8, u8),
     pub counter_clockwise_pos: (u8, u8),
+    // Key which is tapped on every detent, regardless of the direction
+    pub tick_key: Option<String>,
 }
 
 /// Pointing device config
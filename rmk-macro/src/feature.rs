@@ -1,6 +1,11 @@
 //! Utilities of check cargo feature
 //!
 
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+
+use crate::config::KeyboardTomlConfig;
+
 /// Get enabled RMK features list
 pub(crate) fn get_rmk_features() -> Option<Vec<String>> {
     match cargo_toml::Manifest::from_path("./Cargo.toml") {
@@ -24,3 +29,40 @@ pub(crate) fn is_feature_enabled(feature_list: &Option<Vec<String>>, feature: &s
     }
     false
 }
+
+/// Check that the RMK features required by `keyboard.toml` are enabled, returns the compile error if not.
+///
+/// The checks are shared by all entry points, add the checks of new features here.
+pub(crate) fn expand_feature_checks(
+    config: &KeyboardTomlConfig,
+    rmk_features: &Option<Vec<String>>,
+) -> Result<(), TokenStream2> {
+    if config.host.as_ref().is_some_and(|h| h.gamepad)
+        && !is_feature_enabled(rmk_features, "gamepad")
+    {
+        return Err(quote! {
+            compile_error!("\"gamepad\" feature of RMK should be enabled when `gamepad = true` in [host] section");
+        });
+    }
+
+    if config
+        .behavior
+        .as_ref()
+        .is_some_and(|b| b.autocorrect.is_some())
+        && !is_feature_enabled(rmk_features, "autocorrect")
+    {
+        return Err(quote! {
+            compile_error!("\"autocorrect\" feature of RMK should be enabled when [behavior.autocorrect] is set");
+        });
+    }
+
+    if config.host.as_ref().is_some_and(|h| h.usb_battery)
+        && !is_feature_enabled(rmk_features, "usb_battery")
+    {
+        return Err(quote! {
+            compile_error!("\"usb_battery\" feature of RMK should be enabled when `usb_battery = true` in [host] section");
+        });
+    }
+
+    Ok(())
+}
@@ -8,9 +8,9 @@ use crate::{
     bind_interrupt::expand_bind_interrupt,
     ble::expand_ble_config,
     chip_init::expand_chip_init,
-    comm::expand_usb_init,
+    comm::{expand_host_config, expand_usb_init},
     entry::expand_rmk_entry,
-    feature::{get_rmk_features, is_feature_enabled},
+    feature::{expand_feature_checks, get_rmk_features, is_feature_enabled},
     flash::expand_flash_init,
     import::expand_imports,
     keyboard_config::{
@@ -18,7 +18,7 @@ use crate::{
     },
     layout::expand_layout_init,
     light::expand_light_config,
-    matrix::expand_matrix_config,
+    matrix::{expand_matrix_config, expand_rmk_matrix_config},
     ChipSeries,
 };
 
@@ -40,7 +40,12 @@ pub(crate) fn parse_keyboard_mod(item_mod: ItemMod) -> TokenStream2 {
         Err(e) => return e,
     };
 
+    if let Err(e) = expand_feature_checks(&toml_config, &rmk_features) {
+        return e;
+    }
+
     if let Some(m) = toml_config.clone().matrix {
+        // Both normal and direct pin matrices follow the `col2row` feature, the direct pin matrix uses it to orient the debouncer
         if m.row2col {
             eprintln!("row2col is enabled, please ensure that you have updated your Cargo.toml, disabled default features(col2row is enabled as default feature)");
         }
@@ -123,8 +128,10 @@ fn expand_main(
     let light_config = expand_light_config(keyboard_config);
     let behavior_config = expand_behavior_config(keyboard_config);
     let matrix_config = expand_matrix_config(keyboard_config, async_matrix);
+    let set_matrix_config = expand_rmk_matrix_config(keyboard_config);
     let run_rmk = expand_rmk_entry(keyboard_config, &item_mod);
     let (ble_config, set_ble_config) = expand_ble_config(keyboard_config);
+    let set_host_config = expand_host_config(keyboard_config);
 
     let main_function_sig = if keyboard_config.chip.series == ChipSeries::Esp32 {
         quote! {
@@ -173,7 +180,9 @@ fn expand_main(
                 light_config,
                 storage_config,
                 behavior_config,
+                #set_matrix_config
                 #set_ble_config
+                #set_host_config
                 ..Default::default()
             };
 
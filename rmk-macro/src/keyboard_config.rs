@@ -4,8 +4,8 @@ use serde::Deserialize;
 use std::fs;
 
 use crate::config::{
-    BehaviorConfig, BleConfig, DependencyConfig, KeyboardInfo, KeyboardTomlConfig, LayoutConfig,
-    LightConfig, MatrixConfig, MatrixType, SplitConfig, StorageConfig,
+    BehaviorConfig, BleConfig, DependencyConfig, HostConfig, KeyboardInfo, KeyboardTomlConfig,
+    LayoutConfig, LightConfig, MatrixConfig, MatrixType, SplitConfig, StorageConfig,
 };
 use crate::{
     default_config::{
@@ -16,6 +16,9 @@ use crate::{
     ChipModel, ChipSeries,
 };
 
+/// Tx power levels in dBm which are supported by RMK on all nRF52 chips, same as `TX_POWER_LEVELS` in `rmk/src/ble/nrf/tx_power.rs`
+const BLE_TX_POWER_LEVELS: [i8; 8] = [-40, -20, -16, -12, -8, -4, 0, 4];
+
 macro_rules! rmk_compile_error {
     ($msg:expr) => {
         Err(syn::Error::new_spanned(quote! {}, $msg).to_compile_error())
@@ -79,6 +82,8 @@ pub(crate) struct KeyboardConfig {
     pub(crate) storage: StorageConfig,
     // Dependency config
     pub(crate) dependency: DependencyConfig,
+    // Host config
+    pub(crate) host: HostConfig,
 }
 
 #[derive(Clone, Debug)]
@@ -166,6 +171,9 @@ impl KeyboardConfig {
         // Layout config
         config.layout = Self::get_layout_from_toml(toml_config.layout)?;
 
+        // Check disabled matrix positions
+        Self::check_disabled_positions(&config.board, &config.layout)?;
+
         // Behavior config
         config.behavior =
             Self::get_behavior_from_toml(config.behavior, toml_config.behavior, &config.layout)?;
@@ -179,6 +187,17 @@ impl KeyboardConfig {
         // Dependency config
         config.dependency = toml_config.dependency.unwrap_or_default();
 
+        // Host config
+        config.host = toml_config.host.unwrap_or_default();
+        if let Some(primary_device) = config.host.primary_device.as_mut() {
+            *primary_device = primary_device.to_lowercase();
+            if !matches!(primary_device.as_str(), "keyboard" | "consumer") {
+                return rmk_compile_error!(
+                    "keyboard.toml: Unsupported primary_device in [host], should be one of \"keyboard\" and \"consumer\""
+                );
+            }
+        }
+
         Ok(config)
     }
 
@@ -314,6 +333,44 @@ impl KeyboardConfig {
             (_, c) => c,
         };
 
+        if let Some(ble) = &ble_config {
+            let profiles = ble.profile.iter().flatten();
+            for (profile, _) in profiles.clone() {
+                if !profile.parse::<u8>().is_ok_and(|p| p < 8) {
+                    return rmk_compile_error!(format!(
+                        "keyboard.toml: ble profile {} is invalid, it should be a number between 0 and 7",
+                        profile
+                    ));
+                }
+            }
+            for power in [ble.default_tx_power, ble.tx_power_min, ble.tx_power_max]
+                .into_iter()
+                .chain(profiles.map(|(_, p)| p.tx_power))
+                .flatten()
+            {
+                if !BLE_TX_POWER_LEVELS.contains(&power) {
+                    return rmk_compile_error!(format!(
+                        "keyboard.toml: ble tx power {} is not supported, it should be one of {:?} dBm",
+                        power, BLE_TX_POWER_LEVELS
+                    ));
+                }
+            }
+            if let (Some(min), Some(max)) = (ble.tx_power_min, ble.tx_power_max) {
+                if min > max {
+                    return rmk_compile_error!(
+                        "keyboard.toml: ble tx_power_min should not be larger than tx_power_max"
+                            .to_string()
+                    );
+                }
+            }
+            if ble.battery_saver_percent.is_some_and(|p| p > 100) {
+                return rmk_compile_error!(
+                    "keyboard.toml: ble battery_saver_percent should not be larger than 100"
+                        .to_string()
+                );
+            }
+        }
+
         match (usb_info, ble_config) {
             (Some(usb_info), None) => Ok(CommunicationConfig::Usb(usb_info)),
             (Some(usb_info), Some(ble_config)) => {
@@ -367,6 +424,36 @@ impl KeyboardConfig {
         }
     }
 
+    fn check_disabled_positions(
+        board: &BoardConfig,
+        layout: &LayoutConfig,
+    ) -> Result<(), TokenStream2> {
+        let disabled_positions = match board {
+            BoardConfig::Normal(m) => m.disabled_positions.as_ref(),
+            BoardConfig::DirectPin(m) => {
+                if m.disabled_positions.is_some() {
+                    return rmk_compile_error!(
+                        "keyboard.toml: `disabled_positions` is only available for normal matrix"
+                            .to_string()
+                    );
+                }
+                None
+            }
+            _ => None,
+        };
+        if let Some(positions) = disabled_positions {
+            for [row, col] in positions {
+                if *row >= layout.rows || *col >= layout.cols {
+                    return rmk_compile_error!(format!(
+                        "keyboard.toml: Disabled position [{}, {}] is out of the matrix",
+                        row, col
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
     // Layout is a mandatory field in toml, so we mainly check the sizes
     fn get_layout_from_toml(mut layout: LayoutConfig) -> Result<LayoutConfig, TokenStream2> {
         if layout.keymap.len() <= layout.layers as usize {
@@ -440,8 +527,138 @@ impl KeyboardConfig {
                     None => default.tri_layer,
                 };
 
+                if let Some(layers) = &behavior.layer {
+                    if layers.iter().any(|l| l.layer >= layout.layers) {
+                        return rmk_compile_error!(
+                            "keyboard.toml: Layer in [[behavior.layer]] is larger than [layout.layers]"
+                        );
+                    }
+                    if layers.iter().filter(|l| l.base_modifiers.is_some()).count() > 8 {
+                        return rmk_compile_error!(
+                            "keyboard.toml: At most 8 layers can have base_modifiers"
+                        );
+                    }
+                    if layers
+                        .iter()
+                        .filter_map(|l| l.mouse_speed)
+                        .any(|s| !(s > 0.0 && s <= 10.0))
+                    {
+                        return rmk_compile_error!(
+                            "keyboard.toml: mouse_speed in [[behavior.layer]] should be larger than 0 and at most 10"
+                        );
+                    }
+                    if layers
+                        .iter()
+                        .filter(|l| l.mouse_speed.is_some() || l.scroll_invert.is_some())
+                        .count()
+                        > 8
+                    {
+                        return rmk_compile_error!(
+                            "keyboard.toml: At most 8 layers can have mouse_speed or scroll_invert"
+                        );
+                    }
+                }
+
+                if [behavior.default_layer_usb, behavior.default_layer_ble]
+                    .iter()
+                    .flatten()
+                    .any(|&l| l >= layout.layers)
+                {
+                    return rmk_compile_error!(
+                        "keyboard.toml: default_layer_usb or default_layer_ble is larger than [layout.layers]"
+                    );
+                }
+
+                if let Some(mod_layers) = &behavior.mod_layers {
+                    if mod_layers.iter().any(|m| m.layer >= layout.layers) {
+                        return rmk_compile_error!(
+                            "keyboard.toml: Layer in [[behavior.mod_layers]] is larger than [layout.layers]"
+                        );
+                    }
+                    if mod_layers.len() > 8 {
+                        return rmk_compile_error!(
+                            "keyboard.toml: At most 8 [[behavior.mod_layers]] are supported"
+                        );
+                    }
+                }
+
+                if let Some(double_tap_lock) = &behavior.double_tap_lock {
+                    const MODIFIERS: [&str; 8] = [
+                        "LCtrl", "LShift", "LAlt", "LGui", "RCtrl", "RShift", "RAlt", "RGui",
+                    ];
+                    if double_tap_lock
+                        .iter()
+                        .any(|l| !MODIFIERS.contains(&l.modifier.as_str()))
+                    {
+                        return rmk_compile_error!(
+                            "keyboard.toml: modifier in [[behavior.double_tap_lock]] should be one of LCtrl, LShift, LAlt, LGui, RCtrl, RShift, RAlt and RGui"
+                        );
+                    }
+                    if double_tap_lock.len() > 8 {
+                        return rmk_compile_error!(
+                            "keyboard.toml: At most 8 [[behavior.double_tap_lock]] are supported"
+                        );
+                    }
+                }
+
+                if let Some(position_remap) = &behavior.position_remap {
+                    if position_remap.iter().any(|r| {
+                        [r.from, r.to]
+                            .iter()
+                            .any(|&[row, col]| row >= layout.rows || col >= layout.cols)
+                    }) {
+                        return rmk_compile_error!(
+                            "keyboard.toml: Position in [[behavior.position_remap]] is out of [layout] rows or cols"
+                        );
+                    }
+                    if position_remap.len() > 16 {
+                        return rmk_compile_error!(
+                            "keyboard.toml: At most 16 [[behavior.position_remap]] are supported"
+                        );
+                    }
+                }
+
+                if behavior.on_connect_macro.is_some_and(|m| m >= 8) {
+                    return rmk_compile_error!(
+                        "keyboard.toml: on_connect_macro should be a macro index smaller than 8"
+                    );
+                }
+
+                if let Some(autocorrect) = &behavior.autocorrect {
+                    if autocorrect.words.iter().any(|(typo, _)| {
+                        typo.is_empty()
+                            || !typo.chars().all(|c| c.is_ascii_lowercase() || c == '\'')
+                    }) {
+                        return rmk_compile_error!(
+                            "keyboard.toml: Typos in [behavior.autocorrect] can only contain lowercase letters and '"
+                        );
+                    }
+                    if autocorrect
+                        .words
+                        .iter()
+                        .any(|(_, correction)| !correction.is_ascii())
+                    {
+                        return rmk_compile_error!(
+                            "keyboard.toml: Corrections in [behavior.autocorrect] can only contain ascii chars"
+                        );
+                    }
+                }
+
                 behavior.tap_hold = behavior.tap_hold.or(default.tap_hold);
                 behavior.one_shot = behavior.one_shot.or(default.one_shot);
+                behavior.double_tap = behavior.double_tap.or(default.double_tap);
+
+                behavior.host_layout = match behavior.host_layout {
+                    Some(host_layout) => match host_layout.to_lowercase().as_str() {
+                        "us" | "uk" | "de" | "fr" => Some(host_layout.to_lowercase()),
+                        _ => {
+                            return rmk_compile_error!(
+                                "keyboard.toml: Unsupported host_layout, should be one of \"us\", \"uk\", \"de\" and \"fr\""
+                            );
+                        }
+                    },
+                    None => default.host_layout,
+                };
 
                 Ok(behavior)
             }
@@ -456,6 +673,11 @@ impl KeyboardConfig {
                 light_config.capslock = light_config.capslock.or(default.capslock);
                 light_config.numslock = light_config.numslock.or(default.numslock);
                 light_config.scrolllock = light_config.scrolllock.or(default.scrolllock);
+                light_config.layer_indicator =
+                    light_config.layer_indicator.or(default.layer_indicator);
+                light_config.game_mode_indicator = light_config
+                    .game_mode_indicator
+                    .or(default.game_mode_indicator);
                 light_config
             }
             None => default,
@@ -467,6 +689,7 @@ impl KeyboardConfig {
             // Use default setting if the corresponding field is not set
             storage.start_addr = storage.start_addr.or(default.start_addr);
             storage.num_sectors = storage.num_sectors.or(default.num_sectors);
+            storage.sector_size = storage.sector_size.or(default.sector_size);
             storage.clear_storage = storage.clear_storage.or(default.clear_storage);
             storage
         } else {
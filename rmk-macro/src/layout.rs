@@ -74,7 +74,7 @@ impl quote::ToTokens for ModifierCombinationMacro {
 }
 
 /// Get modifier combination, in types of mod1 | mod2 | ...
-fn parse_modifiers(modifiers_str: &str) -> ModifierCombinationMacro {
+pub(crate) fn parse_modifiers(modifiers_str: &str) -> ModifierCombinationMacro {
     let mut combination = ModifierCombinationMacro::new();
     let tokens = modifiers_str.split_terminator("|");
     tokens.for_each(|w| {
@@ -160,6 +160,18 @@ fn parse_key(key: String) -> TokenStream2 {
                 ::rmk::osl!(#layer)
             }
         }
+        "MOT" => {
+            let layer = get_layer(key, "MOTG(", ")");
+            quote! {
+                ::rmk::motg!(#layer)
+            }
+        }
+        "SL(" => {
+            let layer = get_layer(key, "SL(", ")");
+            quote! {
+                ::rmk::sl!(#layer)
+            }
+        }
         "OSM" => {
             if let Some(internal) = key.trim_start_matches("OSM(").strip_suffix(")") {
                 let modifiers = parse_modifiers(internal);
@@ -280,6 +292,18 @@ fn parse_key(key: String) -> TokenStream2 {
                 };
             }
         }
+        "CTL" if key == "CTL_CAPS" || key == "CTL_ESC" => {
+            // Shorthands of the common Ctrl tap-hold keys
+            let ident = if key == "CTL_CAPS" {
+                format_ident!("CapsLock")
+            } else {
+                format_ident!("Escape")
+            };
+            let modifiers = parse_modifiers("LCtrl");
+            quote! {
+                ::rmk::mt!(#ident, #modifiers)
+            }
+        }
         "TH(" => {
             if let Some(internal) = key.trim_start_matches("TH(").strip_suffix(")") {
                 let keys: Vec<&str> = internal
@@ -304,6 +328,112 @@ fn parse_key(key: String) -> TokenStream2 {
                 };
             }
         }
+        "Gam" if key.starts_with("GamepadButton(") => {
+            match key
+                .trim_start_matches("GamepadButton(")
+                .trim_end_matches(")")
+                .trim()
+                .parse::<u8>()
+            {
+                Ok(button) if button < 32 => quote! {
+                    ::rmk::action::KeyAction::Single(::rmk::action::Action::GamepadButton(#button))
+                },
+                _ => quote! {
+                    compile_error!("keyboard.toml: GamepadButton(n) invalid, n should be 0~31, please check the documentation: https://haobogu.github.io/rmk/keyboard_configuration.html");
+                },
+            }
+        }
+        "Gam" if key.starts_with("GamepadAxis(") => {
+            let args: Vec<&str> = key
+                .trim_start_matches("GamepadAxis(")
+                .trim_end_matches(")")
+                .split_terminator(",")
+                .map(|w| w.trim())
+                .collect();
+            let axis: u8 = match args.first() {
+                Some(&"X") => 0,
+                Some(&"Y") => 1,
+                Some(&"Z") => 2,
+                Some(&"Rz") => 3,
+                _ => {
+                    return quote! {
+                        compile_error!("keyboard.toml: axis in GamepadAxis(axis, direction) should be one of X, Y, Z and Rz, please check the documentation: https://haobogu.github.io/rmk/keyboard_configuration.html");
+                    }
+                }
+            };
+            let positive = match args.get(1) {
+                Some(&"+") => true,
+                Some(&"-") => false,
+                _ => {
+                    return quote! {
+                        compile_error!("keyboard.toml: direction in GamepadAxis(axis, direction) should be + or -, please check the documentation: https://haobogu.github.io/rmk/keyboard_configuration.html");
+                    }
+                }
+            };
+            quote! {
+                ::rmk::action::KeyAction::Single(::rmk::action::Action::GamepadAxis(#axis, #positive))
+            }
+        }
+        "Spa" if key.starts_with("SpaceFn(") => {
+            // SpaceFn: tap for space, hold for the layer
+            let layer = get_layer(key, "SpaceFn(", ")");
+            quote! {
+                ::rmk::lt!(#layer, Space)
+            }
+        }
+        "Key" if key == "KeyLock" => {
+            quote! {
+                ::rmk::action::KeyAction::Single(::rmk::action::Action::KeyLock)
+            }
+        }
+        "Gam" if key == "GameModeToggle" => {
+            quote! {
+                ::rmk::action::KeyAction::Single(::rmk::action::Action::GameModeToggle)
+            }
+        }
+        "OnR" if key.starts_with("OnRelease(") => {
+            if let Some(internal) = key.trim_start_matches("OnRelease(").strip_suffix(")") {
+                let ident = format_ident!("{}", internal.trim());
+                quote! {
+                    ::rmk::on_release!(#ident)
+                }
+            } else {
+                quote! {
+                    compile_error!("keyboard.toml: OnRelease(key) invalid, please check the documentation: https://haobogu.github.io/rmk/keyboard_configuration.html");
+                }
+            }
+        }
+        "Rep" if key.starts_with("Repeating(") => {
+            if let Some(internal) = key.trim_start_matches("Repeating(").strip_suffix(")") {
+                let keys: Vec<&str> = internal
+                    .split_terminator(",")
+                    .map(|w| w.trim())
+                    .filter(|w| w.len() > 0)
+                    .collect();
+                if keys.len() != 2 {
+                    return quote! {
+                        compile_error!("keyboard.toml: Repeating(interval, key) invalid, please check the documentation: https://haobogu.github.io/rmk/keyboard_configuration.html");
+                    };
+                }
+                let interval = match keys[0].parse::<u16>() {
+                    Ok(interval) => interval,
+                    Err(_) => {
+                        return quote! {
+                            compile_error!("keyboard.toml: interval in Repeating(interval, key) should be a number in ms, please check the documentation: https://haobogu.github.io/rmk/keyboard_configuration.html");
+                        };
+                    }
+                };
+                let ident = format_ident!("{}", keys[1].to_string());
+
+                quote! {
+                    ::rmk::rep!(#interval, #ident)
+                }
+            } else {
+                return quote! {
+                    compile_error!("keyboard.toml: Repeating(interval, key) invalid, please check the documentation: https://haobogu.github.io/rmk/keyboard_configuration.html");
+                };
+            }
+        }
         _ => {
             let ident = format_ident!("{}", key);
             quote! {::rmk::k!(#ident) }
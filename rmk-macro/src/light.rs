@@ -3,8 +3,10 @@
 use quote::quote;
 
 use crate::{
-    config::PinConfig, gpio_config::convert_gpio_str_to_output_pin,
-    keyboard_config::KeyboardConfig, ChipModel,
+    config::{LightConfig, PinConfig},
+    gpio_config::convert_gpio_str_to_output_pin,
+    keyboard_config::KeyboardConfig,
+    ChipModel,
 };
 
 pub(crate) fn build_light_config(
@@ -27,9 +29,44 @@ pub(crate) fn build_light_config(
 }
 
 pub(crate) fn expand_light_config(keyboard_config: &KeyboardConfig) -> proc_macro2::TokenStream {
-    let numslock = build_light_config(&keyboard_config.chip, &keyboard_config.light.numslock);
-    let capslock = build_light_config(&keyboard_config.chip, &keyboard_config.light.capslock);
-    let scrolllock = build_light_config(&keyboard_config.chip, &keyboard_config.light.scrolllock);
+    expand_light_pins(&keyboard_config.chip, &keyboard_config.light)
+}
+
+/// Generate `light_config` from the given light pins
+pub(crate) fn expand_light_pins(
+    chip: &ChipModel,
+    light_config: &LightConfig,
+) -> proc_macro2::TokenStream {
+    let numslock = build_light_config(chip, &light_config.numslock);
+    let capslock = build_light_config(chip, &light_config.capslock);
+    let scrolllock = build_light_config(chip, &light_config.scrolllock);
+    let layer_indicator = match light_config.layer_indicator.as_deref() {
+        Some("lock_first") => {
+            quote! { ::core::option::Option::Some(::rmk::config::LayerIndicator::LockFirst) }
+        }
+        Some("layer_first") => {
+            quote! { ::core::option::Option::Some(::rmk::config::LayerIndicator::LayerFirst) }
+        }
+        Some(_) => quote! {
+            compile_error!("keyboard.toml: layer_indicator in [light] should be \"lock_first\" or \"layer_first\"")
+        },
+        None => quote! { ::core::option::Option::None },
+    };
+    let game_mode_indicator = match light_config.game_mode_indicator.as_deref() {
+        Some("capslock") => {
+            quote! { ::core::option::Option::Some(::rmk::config::LockLed::CapsLock) }
+        }
+        Some("numslock") => {
+            quote! { ::core::option::Option::Some(::rmk::config::LockLed::NumsLock) }
+        }
+        Some("scrolllock") => {
+            quote! { ::core::option::Option::Some(::rmk::config::LockLed::ScrollLock) }
+        }
+        Some(_) => quote! {
+            compile_error!("keyboard.toml: game_mode_indicator in [light] should be \"capslock\", \"numslock\" or \"scrolllock\"")
+        },
+        None => quote! { ::core::option::Option::None },
+    };
 
     // Generate a macro that does light config
     quote! {
@@ -37,6 +74,8 @@ pub(crate) fn expand_light_config(keyboard_config: &KeyboardConfig) -> proc_macr
             capslock: #capslock,
             numslock: #numslock,
             scrolllock: #scrolllock,
+            layer_indicator: #layer_indicator,
+            game_mode_indicator: #game_mode_indicator,
         };
     }
 }
@@ -52,6 +52,70 @@ pub(crate) fn expand_matrix_config(
     matrix_config
 }
 
+/// Expand the `matrix_config` field of `RmkConfig`, it's empty if there's no matrix option set
+pub(crate) fn expand_rmk_matrix_config(
+    keyboard_config: &KeyboardConfig,
+) -> proc_macro2::TokenStream {
+    let matrix = match &keyboard_config.board {
+        BoardConfig::Normal(matrix) => matrix,
+        _ => return quote! {},
+    };
+    if matrix.disabled_positions.is_none()
+        && matrix.scan_fast_ms.is_none()
+        && matrix.scan_idle_ms.is_none()
+        && matrix.boot_settle_ms.is_none()
+        && matrix.discharge_us.is_none()
+    {
+        return quote! {};
+    }
+
+    let disabled_positions = match &matrix.disabled_positions {
+        Some(positions) => {
+            let positions = positions.iter().map(|[row, col]| quote! { (#row, #col) });
+            quote! { disabled_positions: &[#(#positions),*], }
+        }
+        None => quote! {},
+    };
+    let scan_fast_interval = match matrix.scan_fast_ms {
+        Some(ms) => {
+            let ms = ms as u64;
+            quote! { scan_fast_interval: Some(::embassy_time::Duration::from_millis(#ms)), }
+        }
+        None => quote! {},
+    };
+    let scan_idle_interval = match matrix.scan_idle_ms {
+        Some(ms) => {
+            let ms = ms as u64;
+            quote! { scan_idle_interval: Some(::embassy_time::Duration::from_millis(#ms)), }
+        }
+        None => quote! {},
+    };
+    let boot_settle_time = match matrix.boot_settle_ms {
+        Some(ms) => {
+            let ms = ms as u64;
+            quote! { boot_settle_time: Some(::embassy_time::Duration::from_millis(#ms)), }
+        }
+        None => quote! {},
+    };
+    let discharge_time = match matrix.discharge_us {
+        Some(us) => {
+            let us = us as u64;
+            quote! { discharge_time: Some(::embassy_time::Duration::from_micros(#us)), }
+        }
+        None => quote! {},
+    };
+    quote! {
+        matrix_config: ::rmk::config::MatrixConfig {
+            #disabled_positions
+            #scan_fast_interval
+            #scan_idle_interval
+            #boot_settle_time
+            #discharge_time
+            ..Default::default()
+        },
+    }
+}
+
 pub(crate) fn expand_matrix_direct_pins(
     chip: &ChipModel,
     direct_pins: Vec<Vec<String>>,
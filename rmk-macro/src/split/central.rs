@@ -9,9 +9,9 @@ use crate::{
     bind_interrupt::expand_bind_interrupt,
     ble::expand_ble_config,
     chip_init::expand_chip_init,
-    comm::expand_usb_init,
+    comm::{expand_host_config, expand_usb_init},
     config::{MatrixType, SerialConfig, SplitConfig},
-    feature::{get_rmk_features, is_feature_enabled},
+    feature::{expand_feature_checks, get_rmk_features, is_feature_enabled},
     flash::expand_flash_init,
     import::expand_imports,
     keyboard::gen_imports,
@@ -40,6 +40,10 @@ pub(crate) fn parse_split_central_mod(
         Err(e) => return e,
     };
 
+    if let Err(e) = expand_feature_checks(&toml_config, &rmk_features) {
+        return e;
+    }
+
     let keyboard_config = match KeyboardConfig::new(toml_config) {
         Ok(c) => c,
         Err(e) => return e,
@@ -130,6 +134,8 @@ fn expand_split_central(
         expand_split_communication_config(&keyboard_config.chip, split_config);
     let run_rmk = expand_split_central_entry(keyboard_config, split_config);
     let (ble_config, set_ble_config) = expand_ble_config(keyboard_config);
+    let set_host_config = expand_host_config(keyboard_config);
+    let mirror_indicators = split_config.mirror_indicators;
 
     let main_function_sig = if keyboard_config.chip.series == ChipSeries::Esp32 {
         quote! {
@@ -180,6 +186,10 @@ fn expand_split_central(
                 storage_config,
                 behavior_config,
                 #set_ble_config
+                #set_host_config
+                split_config: ::rmk::config::SplitConfig {
+                    mirror_indicators: #mirror_indicators,
+                },
                 ..Default::default()
             };
 
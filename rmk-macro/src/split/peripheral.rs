@@ -8,6 +8,7 @@ use crate::{
     feature::{get_rmk_features, is_feature_enabled},
     import::expand_imports,
     keyboard_config::{read_keyboard_toml_config, BoardConfig, KeyboardConfig},
+    light::expand_light_pins,
     matrix::{expand_matrix_direct_pins, expand_matrix_input_output_pins},
     split::central::expand_serial_init,
     ChipModel, ChipSeries,
@@ -136,8 +137,22 @@ fn expand_split_peripheral(
         }
     }
 
-    let run_rmk_peripheral =
+    let (peripheral_init, peripheral_fut) =
         expand_split_peripheral_entry(&keyboard_config.chip, peripheral_config, &central_config);
+    let run_rmk_peripheral = match &peripheral_config.light {
+        Some(light_config) => {
+            let light_config = expand_light_pins(&keyboard_config.chip, light_config);
+            quote! {
+                #peripheral_init
+                #light_config
+                ::rmk::split::peripheral::run_with_indicator_leds(#peripheral_fut, light_config).await;
+            }
+        }
+        None => quote! {
+            #peripheral_init
+            #peripheral_fut.await;
+        },
+    };
 
     quote! {
         #imports
@@ -146,11 +161,13 @@ fn expand_split_peripheral(
         #run_rmk_peripheral
     }
 }
+
+/// Generate the initialization and the future of split peripheral service
 fn expand_split_peripheral_entry(
     chip: &ChipModel,
     peripheral_config: &SplitBoardConfig,
     central_config: &SplitBoardConfig,
-) -> TokenStream2 {
+) -> (TokenStream2, TokenStream2) {
     match chip.series {
         ChipSeries::Stm32 => todo!(),
         ChipSeries::Nrf52 => {
@@ -163,7 +180,7 @@ fn expand_split_peripheral_entry(
                 "Peripheral should have a ble address, please check the `ble_addr` field in `keyboard.toml`",
             );
             let low_active = peripheral_config.matrix.direct_pin_low_active;
-            match peripheral_config.matrix.matrix_type {
+            let peripheral_run = match peripheral_config.matrix.matrix_type {
                 MatrixType::direct_pin => {
                     let size = row * col;
                     quote! {
@@ -179,7 +196,7 @@ fn expand_split_peripheral_entry(
                             [#(#peripheral_addr), *],
                             #low_active,
                             spawner,
-                        ).await
+                        )
                     }
                 }
                 MatrixType::normal => {
@@ -195,10 +212,11 @@ fn expand_split_peripheral_entry(
                             [#(#central_addr), *],
                             [#(#peripheral_addr), *],
                             spawner,
-                        ).await
+                        )
                     }
                 }
-            }
+            };
+            (quote! {}, peripheral_run)
         }
         ChipSeries::Rp2040 => {
             let peripheral_serial = peripheral_config
@@ -220,7 +238,7 @@ fn expand_split_peripheral_entry(
                         _,
                         #row,
                         #col,
-                    >(input_pins, output_pins, uart0).await;
+                    >(input_pins, output_pins, uart0)
                 },
                 MatrixType::direct_pin => quote! {
                     ::rmk::split::peripheral::run_rmk_split_peripheral_direct_pin::<
@@ -229,13 +247,10 @@ fn expand_split_peripheral_entry(
                         _,
                         #row,
                         #col,
-                    >(direct_pins, uart0).await;
+                    >(direct_pins, uart0)
                 },
             };
-            quote! {
-                #serial_init
-                #peripheral_run
-            }
+            (serial_init, peripheral_run)
         }
         ChipSeries::Esp32 => todo!(),
     }
@@ -47,6 +47,17 @@ pub enum KeyAction {
     ///
     /// Serialized as 1|BasicAction(7bits)|BasicAction(8bits).
     TapHold(Action, Action),
+    /// Repeat the action while the key is held, the second field is the repeat interval in ms.
+    ///
+    /// The action is triggered once when pressed, then triggered again at every interval until the key is released.
+    /// It cannot be serialized to a key action code, so it can be set only in the default keymap.
+    /// It's saved to storage as a placeholder, which keeps the action in the default keymap after reboot.
+    Repeating(Action, u16),
+    /// Nothing happens when the key is pressed, the action is tapped when the key is released.
+    ///
+    /// It cannot be serialized to a key action code, so it can be set only in the default keymap.
+    /// It's saved to storage as a placeholder, which keeps the action in the default keymap after reboot.
+    OnRelease(Action),
 }
 
 impl KeyAction {
@@ -75,6 +86,17 @@ impl KeyAction {
             KeyAction::TapHold(tap, hold) => {
                 0x8000 | (hold.to_basic_action_code() << 15) | tap.to_basic_action_code()
             }
+            KeyAction::Repeating(action, interval) => {
+                error!(
+                    "Repeating action cannot be serialized: {:?}, interval: {}",
+                    action, interval
+                );
+                0x0000
+            }
+            KeyAction::OnRelease(action) => {
+                error!("OnRelease action cannot be serialized: {:?}", action);
+                0x0000
+            }
         }
     }
 }
@@ -112,6 +134,34 @@ pub enum Action {
     ///
     /// Uses 0xEA0 ~ 0xEBF. Serialized as 1110|101|layer_num(5bits)
     LayerToggleOnly(u8),
+    /// Lock the next pressed key in the held state, until it's pressed again
+    ///
+    /// Uses 0xEC0. Serialized as 1110|110|00000, the via keycode is 0x7F00
+    KeyLock,
+    /// Toggle the game mode. In game mode, tap-hold keys act as their tap action immediately
+    ///
+    /// Uses 0xEC1. Serialized as 1110|110|00001, the via keycode is 0x7F01
+    GameModeToggle,
+    /// Press a gamepad button, the button number is 0~31
+    ///
+    /// Uses 0xEE0 ~ 0xEFF. Serialized as 1110|111|button(5bits), the via keycode is QMK's JS_0 ~ JS_31
+    #[cfg(feature = "gamepad")]
+    GamepadButton(u8),
+    /// Move a gamepad axis to the max(`true`) or min(`false`) value while pressed.
+    /// Available axes: 0 for X, 1 for Y, 2 for Z, 3 for Rz
+    ///
+    /// Uses 0xF00 ~ 0xF07. Serialized as 1111|000000|axis(2bits)|direction(1bit), the via keycode is 0x7F60 ~ 0x7F67
+    #[cfg(feature = "gamepad")]
+    GamepadAxis(u8, bool),
+    /// Activate a layer until a non-transparent key on it is used
+    ///
+    /// Uses 0xF20 ~ 0xF3F. Serialized as 1111|001|layer_num(5bits), the via keycode is 0x7F20 ~ 0x7F3F
+    StickyLayer(u8),
+    /// Activate a layer while held, toggle the layer on by a quick double tap.
+    /// When the layer is toggled on, pressing the key again turns it off
+    ///
+    /// Uses 0xF40 ~ 0xF5F. Serialized as 1111|010|layer_num(5bits), the via keycode is 0x7F40 ~ 0x7F5F
+    LayerMomentaryOrToggle(u8),
 }
 
 impl Action {
@@ -125,6 +175,16 @@ impl Action {
             Action::LayerToggle(layer) => 0xE60 | (layer as u16),
             Action::DefaultLayer(layer) => 0xE80 | (layer as u16),
             Action::LayerToggleOnly(layer) => 0xEA0 | (layer as u16),
+            Action::KeyLock => 0xEC0,
+            Action::GameModeToggle => 0xEC1,
+            #[cfg(feature = "gamepad")]
+            Action::GamepadButton(button) => 0xEE0 | (button as u16 & 0x1F),
+            #[cfg(feature = "gamepad")]
+            Action::GamepadAxis(axis, positive) => {
+                0xF00 | ((axis as u16 & 0b11) << 1) | (positive as u16)
+            }
+            Action::StickyLayer(layer) => 0xF20 | (layer as u16),
+            Action::LayerMomentaryOrToggle(layer) => 0xF40 | (layer as u16),
         }
     }
 
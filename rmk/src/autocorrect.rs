@@ -0,0 +1,318 @@
+//! Autocorrect of common typos.
+//!
+//! The typos are stored in a trie, which is built once from the word list in [`crate::config::AutocorrectConfig`].
+//! Typed letters are collected into a word buffer, when a word boundary(space, punctuation, etc.) is typed,
+//! the finished word is looked up in the trie. If the whole word is a typo, it's erased by backspaces and the correction is typed.
+
+use heapless::Vec;
+
+use crate::keycode::KeyCode;
+
+/// Max number of nodes in the typo trie
+pub(crate) const AUTOCORRECT_TRIE_SIZE: usize = 256;
+
+/// Max length of a word which can be checked
+const MAX_WORD_LEN: usize = 24;
+
+/// Modifier bits of left and right shift
+const SHIFT_BITS: u8 = (1 << 1) | (1 << 5);
+
+#[derive(Clone, Copy)]
+struct TrieNode {
+    /// Char of this node
+    ch: u8,
+    /// Index of the first child, 0 means no child because the root is never a child
+    child: u16,
+    /// Index of the next sibling, 0 means no sibling
+    sibling: u16,
+    /// Index of the word in the word list, if a typo ends at this node
+    word: Option<u16>,
+}
+
+impl TrieNode {
+    const fn new(ch: u8) -> Self {
+        Self {
+            ch,
+            child: 0,
+            sibling: 0,
+            word: None,
+        }
+    }
+}
+
+/// Action which is required after a key is typed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Correction {
+    /// Number of backspaces to erase the typo
+    pub(crate) backspaces: usize,
+    /// The correct word to type
+    pub(crate) text: &'static str,
+}
+
+pub(crate) struct Autocorrect {
+    /// Word list, (typo, correction)
+    words: &'static [(&'static str, &'static str)],
+    /// Trie of typos, `nodes[0]` is the root
+    nodes: Vec<TrieNode, AUTOCORRECT_TRIE_SIZE>,
+    /// Chars of the word which is being typed
+    word: Vec<u8, MAX_WORD_LEN>,
+    /// Whether the current word starts at a word boundary and is fully tracked in `word`
+    valid: bool,
+}
+
+impl Autocorrect {
+    pub(crate) fn new(words: &'static [(&'static str, &'static str)]) -> Self {
+        let mut autocorrect = Self {
+            words,
+            nodes: Vec::new(),
+            word: Vec::new(),
+            valid: true,
+        };
+        if words.is_empty() {
+            return autocorrect;
+        }
+
+        let _ = autocorrect.nodes.push(TrieNode::new(0));
+        for (idx, (typo, _)) in words.iter().enumerate() {
+            if typo.is_empty() || typo.len() > MAX_WORD_LEN || !typo.bytes().all(is_word_char) {
+                warn!("Invalid autocorrect typo, only lowercase letters and ' are supported");
+                continue;
+            }
+            if !autocorrect.insert(typo.as_bytes(), idx as u16) {
+                error!("Autocorrect trie is full, remaining words are ignored");
+                break;
+            }
+        }
+
+        autocorrect
+    }
+
+    /// Insert a typo to the trie, returns false if the trie is full
+    fn insert(&mut self, typo: &[u8], word_idx: u16) -> bool {
+        let mut node = 0;
+        for &ch in typo {
+            node = match self.find_child(node, ch) {
+                Some(child) => child,
+                None => {
+                    let child = self.nodes.len();
+                    let mut new_node = TrieNode::new(ch);
+                    new_node.sibling = self.nodes[node].child;
+                    if self.nodes.push(new_node).is_err() {
+                        return false;
+                    }
+                    self.nodes[node].child = child as u16;
+                    child
+                }
+            };
+        }
+        // Keep the first correction if a typo is listed more than once
+        if self.nodes[node].word.is_none() {
+            self.nodes[node].word = Some(word_idx);
+        }
+        true
+    }
+
+    fn find_child(&self, node: usize, ch: u8) -> Option<usize> {
+        let mut child = self.nodes[node].child as usize;
+        while child != 0 {
+            if self.nodes[child].ch == ch {
+                return Some(child);
+            }
+            child = self.nodes[child].sibling as usize;
+        }
+        None
+    }
+
+    /// Look up a whole word in the trie
+    fn lookup(&self, word: &[u8]) -> Option<&'static str> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let mut node = 0;
+        for &ch in word {
+            node = self.find_child(node, ch)?;
+        }
+        self.nodes[node].word.map(|idx| self.words[idx as usize].1)
+    }
+
+    /// Start a new word after a word boundary
+    fn reset(&mut self) {
+        self.word.clear();
+        self.valid = true;
+    }
+
+    /// Stop tracking the current word until the next word boundary
+    fn invalidate(&mut self) {
+        self.word.clear();
+        self.valid = false;
+    }
+
+    /// Process a pressed basic key with the current modifier bits.
+    ///
+    /// If the key finishes a typo, returns the correction which should be typed before the key.
+    pub(crate) fn process_key(&mut self, key: KeyCode, modifier: u8) -> Option<Correction> {
+        if self.nodes.is_empty() || key.is_modifier() {
+            return None;
+        }
+
+        // Keys with ctrl/alt/gui are shortcuts, the cursor might be moved
+        if modifier & !SHIFT_BITS != 0 {
+            self.invalidate();
+            return None;
+        }
+        let shifted = modifier & SHIFT_BITS != 0;
+
+        match key {
+            KeyCode::Backspace => {
+                if self.word.pop().is_none() {
+                    // Erasing into the previous word, which isn't tracked
+                    self.valid = false;
+                }
+                None
+            }
+            KeyCode::Quote if !shifted => {
+                self.push_char(b'\'');
+                None
+            }
+            k if (KeyCode::A as u16..=KeyCode::Z as u16).contains(&(k as u16)) => {
+                if shifted {
+                    // Capitalized words are left untouched
+                    self.invalidate();
+                } else {
+                    self.push_char(b'a' + (k as u16 - KeyCode::A as u16) as u8);
+                }
+                None
+            }
+            k if is_word_boundary(k, shifted) => {
+                let correction = if self.valid && !self.word.is_empty() {
+                    self.lookup(&self.word).map(|text| Correction {
+                        backspaces: self.word.len(),
+                        text,
+                    })
+                } else {
+                    None
+                };
+                self.reset();
+                correction
+            }
+            _ => {
+                self.invalidate();
+                None
+            }
+        }
+    }
+
+    fn push_char(&mut self, ch: u8) {
+        if self.valid && self.word.push(ch).is_err() {
+            // Too long to be a typo
+            self.invalidate();
+        }
+    }
+}
+
+/// Chars which can be in a typo
+fn is_word_char(ch: u8) -> bool {
+    ch.is_ascii_lowercase() || ch == b'\''
+}
+
+/// Keys which end a word
+fn is_word_boundary(key: KeyCode, shifted: bool) -> bool {
+    match key {
+        KeyCode::Enter
+        | KeyCode::Tab
+        | KeyCode::Space
+        | KeyCode::Minus
+        | KeyCode::Comma
+        | KeyCode::Dot
+        | KeyCode::Slash
+        | KeyCode::Semicolon
+        | KeyCode::Quote
+        | KeyCode::KpEnter => true,
+        // Shifted digits are symbols like `!` and `(`
+        k if (KeyCode::Kc1 as u16..=KeyCode::Kc0 as u16).contains(&(k as u16)) => shifted,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    static WORDS: [(&str, &str); 3] = [("teh", "the"), ("becuase", "because"), ("dont", "don't")];
+
+    fn type_str(autocorrect: &mut Autocorrect, s: &str) -> Option<Correction> {
+        let mut result = None;
+        for ch in s.bytes() {
+            let (key, shifted) = KeyCode::from_ascii(ch);
+            result = autocorrect.process_key(key, if shifted { 1 << 1 } else { 0 });
+        }
+        result
+    }
+
+    #[test]
+    fn test_autocorrect_whole_word() {
+        let mut autocorrect = Autocorrect::new(&WORDS);
+        assert_eq!(
+            type_str(&mut autocorrect, "teh "),
+            Some(Correction {
+                backspaces: 3,
+                text: "the"
+            })
+        );
+        assert_eq!(
+            type_str(&mut autocorrect, "becuase,"),
+            Some(Correction {
+                backspaces: 7,
+                text: "because"
+            })
+        );
+        assert_eq!(
+            type_str(&mut autocorrect, "dont!"),
+            Some(Correction {
+                backspaces: 4,
+                text: "don't"
+            })
+        );
+    }
+
+    #[test]
+    fn test_autocorrect_inside_word() {
+        let mut autocorrect = Autocorrect::new(&WORDS);
+        assert_eq!(type_str(&mut autocorrect, "tehx "), None);
+        assert_eq!(type_str(&mut autocorrect, "steh "), None);
+        assert_eq!(type_str(&mut autocorrect, "te "), None);
+        // Capitalized
+        assert_eq!(type_str(&mut autocorrect, "Teh "), None);
+        // Word after a non-text key
+        autocorrect.process_key(KeyCode::Left, 0);
+        assert_eq!(type_str(&mut autocorrect, "teh "), None);
+        // Shortcut
+        assert_eq!(type_str(&mut autocorrect, "te"), None);
+        autocorrect.process_key(KeyCode::H, 1 << 0);
+        assert_eq!(type_str(&mut autocorrect, " "), None);
+    }
+
+    #[test]
+    fn test_autocorrect_backspace() {
+        let mut autocorrect = Autocorrect::new(&WORDS);
+        assert_eq!(type_str(&mut autocorrect, "tex"), None);
+        autocorrect.process_key(KeyCode::Backspace, 0);
+        assert_eq!(
+            type_str(&mut autocorrect, "h "),
+            Some(Correction {
+                backspaces: 3,
+                text: "the"
+            })
+        );
+        // Erasing into the previous word
+        autocorrect.process_key(KeyCode::Backspace, 0);
+        assert_eq!(type_str(&mut autocorrect, "teh "), None);
+        assert_eq!(
+            type_str(&mut autocorrect, "teh."),
+            Some(Correction {
+                backspaces: 3,
+                text: "the"
+            })
+        );
+    }
+}
@@ -2,6 +2,7 @@ pub(crate) mod server;
 
 use self::server::{BleServer, VialReaderWriter};
 use crate::config::StorageConfig;
+use crate::hid::ConnectionType;
 use crate::keyboard::KEYBOARD_REPORT_CHANNEL;
 use crate::matrix::MatrixTrait;
 use crate::storage::nor_flash::esp_partition::{Partition, PartitionType};
@@ -69,6 +70,7 @@ pub async fn initialize_esp_ble_keyboard_with_config_and_run<
         &keyboard_report_sender,
         keyboard_config.behavior_config,
     );
+    keyboard.set_consumer_max_hold(keyboard_config.host_config.consumer_max_hold);
     // esp32c3 doesn't have USB device, so there is no usb here
     // TODO: add usb service for other chips of esp32 which have USB device
 
@@ -98,6 +100,7 @@ pub async fn initialize_esp_ble_keyboard_with_config_and_run<
 
         let disconnect = BleServer::wait_for_disconnection(ble_server.server);
 
+        keyboard.set_transport(ConnectionType::Ble);
         let keyboard_fut = keyboard.run();
         let ble_fut = ble_communication_task(
             &keyboard_report_receiver,
@@ -105,6 +108,7 @@ pub async fn initialize_esp_ble_keyboard_with_config_and_run<
             &mut media_writer,
             &mut system_writer,
             &mut mouse_writer,
+            keyboard_config.host_config.report_coalesce,
         );
 
         ble_server.output_vial.lock().on_write(|args| {
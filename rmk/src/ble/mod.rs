@@ -6,14 +6,18 @@ pub mod esp;
 #[cfg(feature = "_nrf_ble")]
 pub mod nrf;
 
+use embassy_futures::select::{select, Either};
 use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Receiver};
-use embassy_time::Timer;
+use embassy_time::{Duration, Instant, Timer};
 #[cfg(any(feature = "nrf52840_ble", feature = "nrf52833_ble"))]
 pub use nrf::SOFTWARE_VBUS;
 
 use crate::{
-    hid::HidWriterWrapper,
-    keyboard::{write_other_report_to_host, KeyboardReportMessage, REPORT_CHANNEL_SIZE},
+    hid::{HidWriterWrapper, ReportCoalescer},
+    keyboard::{
+        write_keyboard_report_to_host, write_other_report_to_host, KeyboardReportMessage,
+        REPORT_CHANNEL_SIZE,
+    },
     usb::descriptor::CompositeReportType,
     CONNECTION_STATE,
 };
@@ -37,11 +41,26 @@ pub(crate) async fn ble_communication_task<
     ble_media_writer: &mut W2,
     ble_system_control_writer: &mut W3,
     ble_mouse_writer: &mut W4,
+    report_coalesce: Duration,
 ) {
     // Wait 1 seconds, ensure that gatt server has been started
     Timer::after_secs(1).await;
+    let mut coalescer = ReportCoalescer::new(report_coalesce);
     loop {
-        let report = keyboard_report_receiver.receive().await;
+        let deadline = coalescer.deadline().unwrap_or(Instant::MAX);
+        let report = match select(keyboard_report_receiver.receive(), Timer::at(deadline)).await {
+            Either::First(report) => report,
+            Either::Second(_) => {
+                // The coalescing window ends, send the pending keyboard report.
+                // If the connection is lost, the report is dropped: the write might block, and the report is stale for the next host
+                if let Some(report) = coalescer.flush(Instant::now()) {
+                    if CONNECTION_STATE.load(core::sync::atomic::Ordering::Acquire) {
+                        write_keyboard_report_to_host(&report, ble_keyboard_writer).await;
+                    }
+                }
+                continue;
+            }
+        };
         // Only send the report after the connection is established.
         if CONNECTION_STATE.load(core::sync::atomic::Ordering::Acquire) {
             match report {
@@ -50,12 +69,15 @@ pub(crate) async fn ble_communication_task<
                         "Send keyboard report via BLE: {:?}, modifier: {:b}",
                         report.keycodes, report.modifier
                     );
-                    match ble_keyboard_writer.write_serialize(&report).await {
-                        Ok(()) => {}
-                        Err(e) => error!("Send keyboard report error: {:?}", e),
-                    };
+                    if let Some(report) = coalescer.push(report, Instant::now()) {
+                        write_keyboard_report_to_host(&report, ble_keyboard_writer).await;
+                    }
                 }
                 KeyboardReportMessage::CompositeReport(report, report_type) => {
+                    // Keep the order of reports, send the pending keyboard report first
+                    if let Some(pending) = coalescer.flush(Instant::now()) {
+                        write_keyboard_report_to_host(&pending, ble_keyboard_writer).await;
+                    }
                     match report_type {
                         CompositeReportType::Media => {
                             write_other_report_to_host(report, report_type, ble_media_writer).await
@@ -74,6 +96,10 @@ pub(crate) async fn ble_communication_task<
                         CompositeReportType::None => (),
                     };
                 }
+                #[cfg(feature = "gamepad")]
+                KeyboardReportMessage::GamepadReport(_) => {
+                    debug!("Gamepad report is available via USB only")
+                }
             }
         }
     }
@@ -1,8 +1,24 @@
 use crate::config::BleBatteryConfig;
+use crate::light::BATTERY_SAVER_SIGNAL;
+use crate::BATTERY_SAVER;
+use core::sync::atomic::Ordering;
 use embassy_time::Timer;
 use nrf_softdevice::ble::Connection;
 
 use super::server::BleServer;
+use super::update_conn_interval;
+
+/// The battery saver is turned off after the battery level rises this much above the threshold, e.g. when charging
+const BATTERY_SAVER_HYSTERESIS: u8 = 5;
+
+/// Get the battery saver state from the battery level
+fn battery_saver_state(current: bool, percent: u8, threshold: u8) -> bool {
+    if current {
+        percent < threshold.saturating_add(BATTERY_SAVER_HYSTERESIS)
+    } else {
+        percent < threshold
+    }
+}
 
 #[nrf_softdevice::gatt_service(uuid = "180f")]
 #[derive(Debug, Clone, Copy)]
@@ -51,6 +67,9 @@ impl<'a> BatteryService {
                 saadc.sample(&mut buf).await;
                 // We only sampled one ADC channel.
                 let val: u8 = self.get_battery_percent(buf[0], battery_config);
+                // Report the battery level via USB as well
+                #[cfg(feature = "usb_battery")]
+                crate::usb::set_battery_level(val);
                 match self.battery_level_notify(conn, &val) {
                     Ok(_) => info!("Battery value: {}", val),
                     Err(e) => match self.battery_level_set(&val) {
@@ -60,6 +79,16 @@ impl<'a> BatteryService {
                         }
                     },
                 }
+                if let Some(threshold) = battery_config.battery_saver_percent {
+                    let current = BATTERY_SAVER.load(Ordering::Relaxed);
+                    let battery_saver = battery_saver_state(current, val, threshold);
+                    if battery_saver != current {
+                        info!("Battery saver: {}", battery_saver);
+                        BATTERY_SAVER.store(battery_saver, Ordering::Relaxed);
+                        BATTERY_SAVER_SIGNAL.signal(battery_saver);
+                        update_conn_interval(conn);
+                    }
+                }
                 if val < 10 {
                     // The battery is low, blink the led!
                     if let Some(ref mut charge_led) = battery_config.charge_led_pin {
@@ -1,4 +1,4 @@
-use super::BONDED_DEVICE_NUM;
+use super::{tx_power::reset_profile_tx_power, BONDED_DEVICE_NUM};
 use crate::{
     ble::nrf::ACTIVE_PROFILE,
     storage::{FlashOperationMessage, FLASH_CHANNEL},
@@ -317,6 +317,7 @@ impl SecurityHandler for Bonder {
                 Err(_e) => error!("Send clear to flash channel error"),
             }
             self.bond_info.borrow_mut().remove(&unlucky);
+            reset_profile_tx_power(unlucky);
         } else {
             // Save bond info
             let mut sys_attr_data: [u8; 62] = [0; 62];
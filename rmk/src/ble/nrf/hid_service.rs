@@ -184,6 +184,8 @@ impl gatt_server::Service for HidService {
             // Fires if a keyboard output is changed - e.g. the caps lock LED
             let led_indicator = LedIndicator::from_bits(data[0]);
             info!("HID output keyboard: {:?}", led_indicator);
+            #[cfg(feature = "split")]
+            crate::light::update_split_led_indicator(led_indicator);
             // Retry 3 times in case the channel is full(which is really rare)
             for _i in 0..3 {
                 match LED_CHANNEL.try_send(led_indicator) {
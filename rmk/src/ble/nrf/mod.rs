@@ -6,13 +6,15 @@ mod hid_service;
 pub(crate) mod profile;
 pub(crate) mod server;
 pub(crate) mod spec;
+mod tx_power;
 mod vial_service;
 
 use self::server::BleServer;
-use crate::config::BleBatteryConfig;
+use crate::config::{BleBatteryConfig, BleTxPowerConfig};
+use crate::hid::ConnectionType;
 use crate::keyboard::{KEYBOARD_REPORT_CHANNEL, REPORT_CHANNEL_SIZE};
 use crate::matrix::MatrixTrait;
-use crate::storage::StorageKeys;
+use crate::storage::{get_profile_tx_power_key, StorageKeys};
 use crate::{
     ble::{
         ble_communication_task,
@@ -27,7 +29,7 @@ use crate::{
     storage::{get_bond_info_key, Storage, StorageData},
     vial_task, KeyAction, KeyMap, LightService, RmkConfig, VialService, CONNECTION_TYPE,
 };
-use crate::{CONNECTION_STATE, KEYBOARD_STATE};
+use crate::{BATTERY_SAVER, CONNECTION_STATE, KEYBOARD_STATE};
 use bonder::MultiBonder;
 use core::sync::atomic::{AtomicU8, Ordering};
 use core::{cell::RefCell, mem};
@@ -35,12 +37,12 @@ use embassy_executor::Spawner;
 use embassy_futures::join::join;
 use embassy_futures::select::{select, select4, Either4};
 use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Receiver};
-use embassy_time::Timer;
+use embassy_time::{Duration, Timer};
 use embedded_hal::digital::OutputPin;
 use embedded_storage_async::nor_flash::NorFlash as AsyncNorFlash;
 use heapless::FnvIndexMap;
 use nrf_softdevice::ble::peripheral::ConnectableAdvertisement;
-use nrf_softdevice::ble::{PhySet, PhyUpdateError, TxPower};
+use nrf_softdevice::ble::{PhySet, PhyUpdateError};
 use nrf_softdevice::raw::sd_ble_gap_conn_param_update;
 use nrf_softdevice::{
     ble::{gatt_server, peripheral, security::SecurityHandler as _, Connection},
@@ -49,6 +51,7 @@ use nrf_softdevice::{
 use profile::update_profile;
 use sequential_storage::{cache::NoCache, map::fetch_item};
 use static_cell::StaticCell;
+use tx_power::{run_tx_power_control, save_profile_tx_power, to_tx_power, PROFILE_TX_POWER};
 use vial_service::VialReaderWriter;
 #[cfg(not(feature = "_no_usb"))]
 use {
@@ -268,6 +271,15 @@ pub async fn initialize_nrf_ble_keyboard_and_run<
         ACTIVE_PROFILE.store(0, Ordering::SeqCst);
     };
 
+    // Load adapted tx power of each profile
+    for profile in 0..BONDED_DEVICE_NUM {
+        if let Ok(Some(StorageData::ProfileTxPower(_, dbm))) =
+            read_storage!(storage, &get_profile_tx_power_key(profile as u8), buf)
+        {
+            PROFILE_TX_POWER[profile].store(dbm, Ordering::Release);
+        }
+    }
+
     // Load current connection type
     if let Ok(Some(StorageData::ConnectionType(conn_type))) =
         read_storage!(storage, &(StorageKeys::ConnectionType as u32), buf)
@@ -309,8 +321,13 @@ pub async fn initialize_nrf_ble_keyboard_and_run<
         &keyboard_report_sender,
         keyboard_config.behavior_config,
     );
+    keyboard.set_consumer_max_hold(keyboard_config.host_config.consumer_max_hold);
     #[cfg(not(feature = "_no_usb"))]
-    let mut usb_device = KeyboardUsbDevice::new(usb_driver, keyboard_config.usb_config);
+    let mut usb_device = KeyboardUsbDevice::new(
+        usb_driver,
+        keyboard_config.usb_config,
+        keyboard_config.host_config.primary_device,
+    );
     let mut vial_service = VialService::new(&keymap, keyboard_config.vial_config);
     let mut light_service = LightService::from_config(keyboard_config.light_config);
 
@@ -321,7 +338,11 @@ pub async fn initialize_nrf_ble_keyboard_and_run<
         let mut config = peripheral::Config::default();
         // Interval: 500ms
         config.interval = 800;
-        config.tx_power = TxPower::Plus4dBm;
+        config.tx_power = to_tx_power(
+            keyboard_config
+                .ble_tx_power_config
+                .profile_default_tx_power(ACTIVE_PROFILE.load(Ordering::Acquire)),
+        );
         let adv = ConnectableAdvertisement::ScannableUndirected {
             adv_data: &create_advertisement_data(keyboard_name),
             scan_data: &SCAN_DATA,
@@ -345,6 +366,7 @@ pub async fn initialize_nrf_ble_keyboard_and_run<
                     &mut light_service,
                     &mut vial_service,
                     &keyboard_report_receiver,
+                    keyboard_config.host_config.report_coalesce,
                 );
                 if CONNECTION_TYPE.load(Ordering::Relaxed) == 0 {
                     info!("Running USB keyboard");
@@ -390,7 +412,9 @@ pub async fn initialize_nrf_ble_keyboard_and_run<
                                     &mut light_service,
                                     &mut vial_service,
                                     &mut keyboard_config.ble_battery_config,
+                                    &keyboard_config.ble_tx_power_config,
                                     &keyboard_report_receiver,
+                                    keyboard_config.host_config.report_coalesce,
                                 ),
                                 wait_for_usb_enabled(),
                                 update_profile(bonder),
@@ -449,7 +473,9 @@ pub async fn initialize_nrf_ble_keyboard_and_run<
                                 &mut light_service,
                                 &mut vial_service,
                                 &mut keyboard_config.ble_battery_config,
+                                &keyboard_config.ble_tx_power_config,
                                 &keyboard_report_receiver,
+                                keyboard_config.host_config.report_coalesce,
                             ),
                             wait_for_usb_enabled(),
                             update_profile(bonder),
@@ -490,7 +516,9 @@ pub async fn initialize_nrf_ble_keyboard_and_run<
                         &mut light_service,
                         &mut vial_service,
                         &mut keyboard_config.ble_battery_config,
+                        &keyboard_config.ble_tx_power_config,
                         &keyboard_report_receiver,
+                        keyboard_config.host_config.report_coalesce,
                     ),
                     update_profile(bonder),
                 )
@@ -524,19 +552,36 @@ pub(crate) async fn set_conn_params(conn: &Connection) {
             debug!("Set conn params result: {:?}", re);
 
             embassy_time::Timer::after_millis(5000).await;
+        }
 
-            // Setting the conn param the second time ensures that we have best performance on all platforms
-            let re = sd_ble_gap_conn_param_update(
+        // Setting the conn param the second time ensures that we have best performance on all platforms
+        update_conn_interval(conn);
+    }
+}
+
+/// Set the connection interval according to the battery saver state.
+///
+/// The battery saver uses 30ms interval instead of 7.5ms, which saves power at the cost of latency.
+pub(crate) fn update_conn_interval(conn: &Connection) {
+    if let Some(conn_handle) = conn.handle() {
+        // In units of 1.25ms
+        let interval = if BATTERY_SAVER.load(Ordering::Relaxed) {
+            24
+        } else {
+            6
+        };
+        let re = unsafe {
+            sd_ble_gap_conn_param_update(
                 conn_handle,
                 &raw::ble_gap_conn_params_t {
-                    min_conn_interval: 6,
-                    max_conn_interval: 6,
+                    min_conn_interval: interval,
+                    max_conn_interval: interval,
                     slave_latency: 99,
                     conn_sup_timeout: 500, // timeout: 5s
                 },
-            );
-            debug!("Set conn params result: {:?}", re);
-        }
+            )
+        };
+        debug!("Set conn params result: {:?}", re);
     }
 }
 
@@ -616,12 +661,14 @@ pub(crate) async fn run_ble_keyboard<
     light_service: &mut LightService<Out>,
     vial_service: &mut VialService<'a, ROW, COL, NUM_LAYER>,
     battery_config: &mut BleBatteryConfig<'b>,
+    tx_power_config: &BleTxPowerConfig,
     keyboard_report_receiver: &Receiver<
         'a,
         CriticalSectionRawMutex,
         KeyboardReportMessage,
         REPORT_CHANNEL_SIZE,
     >,
+    report_coalesce: Duration,
 ) {
     CONNECTION_STATE.store(false, Ordering::Release);
     info!("Starting GATT server 20 ms later");
@@ -638,9 +685,11 @@ pub(crate) async fn run_ble_keyboard<
     // Tasks
     let battery_fut = bas.run(battery_config, &conn);
     let led_fut = led_service_task(light_service);
+    let tx_power_fut = run_tx_power_control(&conn, tx_power_config);
     let matrix_fut = matrix.run();
     // Run the GATT server on the connection. This returns when the connection gets disconnected.
     let ble_fut = gatt_server::run(&conn, ble_server, |_| {});
+    keyboard.set_transport(ConnectionType::Ble);
     let keyboard_fut = keyboard.run();
     let ble_communication_task = ble_communication_task(
         keyboard_report_receiver,
@@ -648,6 +697,7 @@ pub(crate) async fn run_ble_keyboard<
         &mut ble_media_writer,
         &mut ble_system_control_writer,
         &mut ble_mouse_writer,
+        report_coalesce,
     );
     let storage_fut = storage.run();
     let set_conn_param = set_conn_params(&conn);
@@ -656,14 +706,17 @@ pub(crate) async fn run_ble_keyboard<
     match select4(
         select(matrix_fut, join(ble_fut, set_conn_param)),
         select(ble_communication_task, keyboard_fut),
-        select(battery_fut, led_fut),
+        select(battery_fut, select(led_fut, tx_power_fut)),
         select(vial_task, storage_fut),
     )
     .await
     {
         Either4::First(e) => error!("ble_fut exited with error: {:?}", e),
         Either4::Second(_) => error!("Keyboard task or ble task exited"),
-        Either4::Third(_) => error!("Battery task or led task exited"),
+        Either4::Third(_) => error!("Battery task, led task or tx power task exited"),
         Either4::Fourth(_) => error!("Storage task exited"),
     }
+
+    // Persist the tx power adapted in this connection, it's written by the storage task later
+    save_profile_tx_power();
 }
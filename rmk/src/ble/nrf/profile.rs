@@ -12,7 +12,7 @@ use crate::{
     CONNECTION_TYPE,
 };
 
-use super::bonder::MultiBonder;
+use super::{bonder::MultiBonder, tx_power::reset_profile_tx_power};
 
 pub(crate) static BLE_PROFILE_CHANNEL: Channel<CriticalSectionRawMutex, BleProfileAction, 1> =
     Channel::new();
@@ -65,6 +65,7 @@ pub(crate) async fn update_profile(bonder: &MultiBonder) {
             BleProfileAction::ClearProfile => {
                 let profile = ACTIVE_PROFILE.load(Ordering::SeqCst);
                 bonder.clear_bonded(profile);
+                reset_profile_tx_power(profile);
                 FLASH_CHANNEL
                     .send(FlashOperationMessage::ClearSlot(profile))
                     .await;
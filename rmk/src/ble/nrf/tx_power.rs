@@ -0,0 +1,169 @@
+use crate::{
+    ble::nrf::{ACTIVE_PROFILE, BONDED_DEVICE_NUM},
+    config::BleTxPowerConfig,
+    storage::{FlashOperationMessage, FLASH_CHANNEL},
+};
+use core::sync::atomic::{AtomicI8, AtomicU8, Ordering};
+use embassy_time::Timer;
+use nrf_softdevice::{
+    ble::{Connection, TxPower},
+    raw,
+};
+
+/// Tx power levels which are supported by all nRF52 chips, in dBm. Keep it in sync with `BLE_TX_POWER_LEVELS` in rmk-macro
+const TX_POWER_LEVELS: [i8; 8] = [-40, -20, -16, -12, -8, -4, 0, 4];
+
+/// If the RSSI is above this value(dBm), the link is good enough to lower the tx power
+const RSSI_STRONG: i8 = -55;
+
+/// If the RSSI is below this value(dBm), the tx power should be raised
+const RSSI_WEAK: i8 = -75;
+
+/// Interval of checking the RSSI, in seconds
+const RSSI_CHECK_INTERVAL: u64 = 5;
+
+/// Marks a profile whose tx power hasn't been adapted yet
+const TX_POWER_UNSET: i8 = i8::MIN;
+
+/// Adapted tx power of each profile in dBm, or `TX_POWER_UNSET`.
+///
+/// A new connection of the profile starts from the adapted tx power instead of the default one.
+/// It's loaded from the storage at startup, and saved when the connection ends.
+pub(crate) static PROFILE_TX_POWER: [AtomicI8; BONDED_DEVICE_NUM] =
+    [const { AtomicI8::new(TX_POWER_UNSET) }; BONDED_DEVICE_NUM];
+
+/// Bitmask of profiles whose adapted tx power is not saved yet
+static UNSAVED_TX_POWER: AtomicU8 = AtomicU8::new(0);
+
+/// Save the adapted tx power of all profiles which are changed since last saving
+pub(crate) fn save_profile_tx_power() {
+    let unsaved = UNSAVED_TX_POWER.swap(0, Ordering::AcqRel);
+    for profile in 0..BONDED_DEVICE_NUM as u8 {
+        if unsaved & (1 << profile) == 0 {
+            continue;
+        }
+        let dbm = PROFILE_TX_POWER[profile as usize].load(Ordering::Acquire);
+        if FLASH_CHANNEL
+            .try_send(FlashOperationMessage::ProfileTxPower(profile, dbm))
+            .is_err()
+        {
+            // Retry next time
+            UNSAVED_TX_POWER.fetch_or(1 << profile, Ordering::AcqRel);
+            error!(
+                "Send tx power of profile {} to flash channel error",
+                profile
+            );
+        }
+    }
+}
+
+/// Forget the adapted tx power of the profile, the next connection starts from the default tx power
+pub(crate) fn reset_profile_tx_power(profile: u8) {
+    if let Some(tx_power) = PROFILE_TX_POWER.get(profile as usize) {
+        if tx_power.swap(TX_POWER_UNSET, Ordering::AcqRel) != TX_POWER_UNSET {
+            UNSAVED_TX_POWER.fetch_or(1 << profile, Ordering::AcqRel);
+        }
+    }
+}
+
+/// Get the nearest supported tx power level, which is not higher than the given power
+fn nearest_level(dbm: i8) -> usize {
+    TX_POWER_LEVELS
+        .iter()
+        .rposition(|&level| level <= dbm)
+        .unwrap_or(0)
+}
+
+/// Convert tx power in dBm to the advertising tx power
+pub(crate) fn to_tx_power(dbm: i8) -> TxPower {
+    match TX_POWER_LEVELS[nearest_level(dbm)] {
+        -40 => TxPower::Minus40dBm,
+        -20 => TxPower::Minus20dBm,
+        -16 => TxPower::Minus16dBm,
+        -12 => TxPower::Minus12dBm,
+        -8 => TxPower::Minus8dBm,
+        -4 => TxPower::Minus4dBm,
+        0 => TxPower::ZerodBm,
+        _ => TxPower::Plus4dBm,
+    }
+}
+
+/// Set the tx power of the connection
+fn set_conn_tx_power(conn_handle: u16, dbm: i8) {
+    let re = unsafe {
+        raw::sd_ble_gap_tx_power_set(raw::BLE_GAP_TX_POWER_ROLES_CONN as u8, conn_handle, dbm)
+    };
+    debug!("Set tx power to {}dBm, result: {}", dbm, re);
+}
+
+/// Manage the tx power of the connection.
+///
+/// If adaptive tx power is disabled, the default tx power of the active profile is set once.
+/// Otherwise, the RSSI of the connection is checked periodically, the tx power is lowered when the RSSI is strong,
+/// and raised when the RSSI is weak, within `tx_power_min` and `tx_power_max`.
+/// The adapted tx power is recorded in `PROFILE_TX_POWER`, call `save_profile_tx_power` to persist it.
+pub(crate) async fn run_tx_power_control(conn: &Connection, config: &BleTxPowerConfig) {
+    let conn_handle = match conn.handle() {
+        Some(handle) => handle,
+        // Disconnected, the gatt server exits soon
+        None => core::future::pending().await,
+    };
+
+    let profile = ACTIVE_PROFILE.load(Ordering::Acquire);
+    let default_level = nearest_level(config.profile_default_tx_power(profile));
+    if !config.adaptive_tx_power {
+        // Keep the static tx power for the whole connection
+        set_conn_tx_power(conn_handle, TX_POWER_LEVELS[default_level]);
+        core::future::pending::<()>().await;
+    }
+
+    let min_level = nearest_level(config.tx_power_min);
+    let max_level = nearest_level(config.tx_power_max).max(min_level);
+    // Start from the tx power adapted in the last connection of this profile
+    let saved_tx_power = PROFILE_TX_POWER
+        .get(profile as usize)
+        .map(|p| p.load(Ordering::Acquire))
+        .unwrap_or(TX_POWER_UNSET);
+    let start_level = if saved_tx_power == TX_POWER_UNSET {
+        default_level
+    } else {
+        nearest_level(saved_tx_power)
+    };
+    let mut level = start_level.clamp(min_level, max_level);
+    set_conn_tx_power(conn_handle, TX_POWER_LEVELS[level]);
+
+    // Start RSSI reporting, changes less than 2dBm are ignored
+    let re = unsafe { raw::sd_ble_gap_rssi_start(conn_handle, 2, 0) };
+    if re != raw::NRF_SUCCESS {
+        error!("Failed to start RSSI reporting: {}", re);
+        core::future::pending::<()>().await;
+    }
+
+    loop {
+        Timer::after_secs(RSSI_CHECK_INTERVAL).await;
+        let mut rssi: i8 = 0;
+        let mut channel: u8 = 0;
+        let re = unsafe { raw::sd_ble_gap_rssi_get(conn_handle, &mut rssi, &mut channel) };
+        if re != raw::NRF_SUCCESS {
+            continue;
+        }
+
+        let new_level = if rssi > RSSI_STRONG && level > min_level {
+            level - 1
+        } else if rssi < RSSI_WEAK && level < max_level {
+            level + 1
+        } else {
+            level
+        };
+
+        if new_level != level {
+            debug!("RSSI: {}dBm, adjust tx power", rssi);
+            level = new_level;
+            set_conn_tx_power(conn_handle, TX_POWER_LEVELS[level]);
+            if let Some(tx_power) = PROFILE_TX_POWER.get(profile as usize) {
+                tx_power.store(TX_POWER_LEVELS[level], Ordering::Release);
+                UNSAVED_TX_POWER.fetch_or(1 << profile, Ordering::AcqRel);
+            }
+        }
+    }
+}
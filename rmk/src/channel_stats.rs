@@ -0,0 +1,83 @@
+//! Backpressure statistics of RMK's internal channels.
+//!
+//! When `channel_stats` feature is enabled, RMK counts the messages which are dropped because a channel is full,
+//! and the times that a sender has to wait because a channel is full.
+//! It helps to size the channels and to diagnose missed keys.
+//! When the feature is disabled, recording is a no-op.
+
+#[cfg(feature = "channel_stats")]
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// Channels tracked by the statistics
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum StatsChannel {
+    /// `KEY_EVENT_CHANNEL`, key events from the matrix, split peripherals and input devices
+    KeyEvent = 0,
+    /// Channel of split messages between the central and peripherals
+    SplitMessage = 1,
+    /// `FLASH_CHANNEL`, storage operations
+    FlashOperation = 2,
+}
+
+#[cfg(feature = "channel_stats")]
+const NUM_STATS_CHANNEL: usize = 3;
+
+#[cfg(feature = "channel_stats")]
+static DROPPED: [AtomicU32; NUM_STATS_CHANNEL] = [const { AtomicU32::new(0) }; NUM_STATS_CHANNEL];
+
+#[cfg(feature = "channel_stats")]
+static BLOCKED: [AtomicU32; NUM_STATS_CHANNEL] = [const { AtomicU32::new(0) }; NUM_STATS_CHANNEL];
+
+/// Increase a counter. Only load/store are used, because atomic RMW is not available on all targets.
+/// A concurrent increase might be lost, which is acceptable for statistics.
+#[cfg(feature = "channel_stats")]
+fn increase(counter: &AtomicU32) {
+    counter.store(
+        counter.load(Ordering::Relaxed).wrapping_add(1),
+        Ordering::Relaxed,
+    );
+}
+
+/// Record a message which is dropped because the channel is full
+#[inline(always)]
+pub(crate) fn record_dropped(channel: StatsChannel) {
+    #[cfg(feature = "channel_stats")]
+    {
+        increase(&DROPPED[channel as usize]);
+        warn!("{:?} channel is full, message dropped", channel);
+    }
+    #[cfg(not(feature = "channel_stats"))]
+    let _ = channel;
+}
+
+/// Record that a sender has to wait because the channel is full
+#[inline(always)]
+pub(crate) fn record_blocked(channel: StatsChannel, is_full: bool) {
+    #[cfg(feature = "channel_stats")]
+    if is_full {
+        increase(&BLOCKED[channel as usize]);
+    }
+    #[cfg(not(feature = "channel_stats"))]
+    let _ = (channel, is_full);
+}
+
+/// Number of messages dropped because the channel is full
+#[cfg(feature = "channel_stats")]
+pub fn dropped_count(channel: StatsChannel) -> u32 {
+    DROPPED[channel as usize].load(Ordering::Relaxed)
+}
+
+/// Number of times that a sender waited because the channel is full
+#[cfg(feature = "channel_stats")]
+pub fn blocked_count(channel: StatsChannel) -> u32 {
+    BLOCKED[channel as usize].load(Ordering::Relaxed)
+}
+
+/// Reset all statistics
+#[cfg(feature = "channel_stats")]
+pub fn reset() {
+    for counter in DROPPED.iter().chain(BLOCKED.iter()) {
+        counter.store(0, Ordering::Relaxed);
+    }
+}
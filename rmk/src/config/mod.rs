@@ -6,21 +6,45 @@ mod nrf_config;
 #[cfg(feature = "_esp_ble")]
 pub use esp_config::BleBatteryConfig;
 #[cfg(feature = "_nrf_ble")]
-pub use nrf_config::BleBatteryConfig;
+pub use nrf_config::{BleBatteryConfig, BleTxPowerConfig};
 
 use embassy_time::Duration;
 use embedded_hal::digital::OutputPin;
+use heapless::Vec;
+
+use crate::keycode::{KeyCode, ModifierCombination};
+
+/// Max number of layers which have base modifiers
+pub const MAX_BASE_MODIFIER_LAYERS: usize = 8;
+
+/// Max number of modifier-activated layers in `BehaviorConfig::mod_layers`
+pub const MAX_MOD_LAYERS: usize = 8;
+
+/// Max number of modifiers in `BehaviorConfig::double_tap_lock`
+pub const MAX_DOUBLE_TAP_LOCKS: usize = 8;
+
+/// Max number of remapped positions in `BehaviorConfig::position_remap`
+pub const MAX_POSITION_REMAPS: usize = 16;
+
+/// Max number of layers which have their own mouse key settings
+pub const MAX_MOUSE_LAYERS: usize = 8;
 
 /// Internal configurations for RMK keyboard.
 pub struct RmkConfig<'a, O: OutputPin> {
     pub mouse_config: MouseConfig,
+    pub matrix_config: MatrixConfig<'a>,
     pub usb_config: KeyboardUsbConfig<'a>,
     pub vial_config: VialConfig<'a>,
     pub light_config: LightConfig<O>,
     pub storage_config: StorageConfig,
     pub behavior_config: BehaviorConfig,
+    pub host_config: HostConfig,
+    #[cfg(feature = "split")]
+    pub split_config: SplitConfig,
     #[cfg(feature = "_nrf_ble")]
     pub ble_battery_config: BleBatteryConfig<'a>,
+    #[cfg(feature = "_nrf_ble")]
+    pub ble_tx_power_config: BleTxPowerConfig,
     #[cfg(feature = "_esp_ble")]
     pub ble_battery_config: BleBatteryConfig,
 }
@@ -29,13 +53,19 @@ impl<'a, O: OutputPin> Default for RmkConfig<'a, O> {
     fn default() -> Self {
         Self {
             mouse_config: MouseConfig::default(),
+            matrix_config: MatrixConfig::default(),
             usb_config: KeyboardUsbConfig::default(),
             vial_config: VialConfig::default(),
             light_config: LightConfig::default(),
             storage_config: StorageConfig::default(),
             behavior_config: BehaviorConfig::default(),
+            host_config: HostConfig::default(),
+            #[cfg(feature = "split")]
+            split_config: SplitConfig::default(),
             #[cfg(any(feature = "_nrf_ble", feature = "_esp_ble"))]
             ble_battery_config: BleBatteryConfig::default(),
+            #[cfg(feature = "_nrf_ble")]
+            ble_tx_power_config: BleTxPowerConfig::default(),
         }
     }
 }
@@ -46,6 +76,146 @@ pub struct BehaviorConfig {
     pub tri_layer: Option<[u8; 3]>,
     pub tap_hold: TapHoldConfig,
     pub one_shot: OneShotConfig,
+    pub double_tap: DoubleTapConfig,
+    pub host_layout: HostLayout,
+    /// Deactivate all layers, back to the default layer, after the keyboard is idle for this duration
+    pub idle_layer_reset: Option<Duration>,
+    /// Default layer which is applied when the keyboard is connected via USB
+    pub default_layer_usb: Option<u8>,
+    /// Default layer which is applied when the keyboard is connected via BLE
+    pub default_layer_ble: Option<u8>,
+    /// Repeat held consumer keys at this interval over BLE, for hosts which don't auto-repeat them
+    pub ble_consumer_repeat: Option<Duration>,
+    /// Index of the macro which is run once when the keyboard is connected to the host
+    pub on_connect_macro: Option<u8>,
+    /// Suppress a press of a key if it comes within this duration after the last press of the same key
+    pub per_key_min_interval: Option<Duration>,
+    /// Modifiers which are applied to the keys of a layer automatically while the layer is active, (layer, modifiers)
+    pub layer_base_modifiers: Vec<(u8, ModifierCombination), MAX_BASE_MODIFIER_LAYERS>,
+    /// Layers which are active while the modifiers are held, (modifiers, layer). The modifiers are still sent to the host
+    pub mod_layers: Vec<(ModifierCombination, u8), MAX_MOD_LAYERS>,
+    /// Double tapping a modifier key taps the output key, (modifier, output), e.g. (LShift, CapsLock)
+    pub double_tap_lock: Vec<(KeyCode, KeyCode), MAX_DOUBLE_TAP_LOCKS>,
+    /// Key events of a matrix position are treated as another position before the keymap is looked up, ((row, col), (row, col)).
+    /// It's used to fix wiring mistakes without editing every layer
+    pub position_remap: Vec<((u8, u8), (u8, u8)), MAX_POSITION_REMAPS>,
+    /// Mouse key settings which are used while the layer is the active layer, (layer, settings)
+    pub layer_mouse: Vec<(u8, LayerMouseConfig), MAX_MOUSE_LAYERS>,
+    /// Initial state of magic keycodes
+    pub magic: MagicConfig,
+    #[cfg(feature = "autocorrect")]
+    pub autocorrect: AutocorrectConfig,
+}
+
+/// Mouse key settings of a layer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LayerMouseConfig {
+    /// Cursor speed of mouse keys, in percentage of the default speed
+    pub speed_percent: u16,
+    /// Invert the direction of mouse wheel keys, aka natural scrolling
+    pub scroll_invert: bool,
+}
+
+impl Default for LayerMouseConfig {
+    fn default() -> Self {
+        Self {
+            speed_percent: 100,
+            scroll_invert: false,
+        }
+    }
+}
+
+/// Initial state of magic keycodes, which remap keys on all layers.
+/// The state can be changed by magic keycodes at runtime, the changed state is saved in storage and takes precedence over this config.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MagicConfig {
+    /// Swap left Ctrl and CapsLock
+    pub swap_ctrl_caps: bool,
+    /// Treat CapsLock as left Ctrl
+    pub capslock_as_ctrl: bool,
+    /// Swap left Alt and left GUI
+    pub swap_lalt_lgui: bool,
+    /// Swap right Alt and right GUI
+    pub swap_ralt_rgui: bool,
+    /// Disable GUI keys, useful for gaming
+    pub no_gui: bool,
+    /// Swap Grave and Escape
+    pub swap_grave_esc: bool,
+    /// Swap Backslash and Backspace
+    pub swap_backslash_backspace: bool,
+    /// Swap left Ctrl and left GUI
+    pub swap_lctl_lgui: bool,
+    /// Swap right Ctrl and right GUI
+    pub swap_rctl_rgui: bool,
+    /// Swap Escape and CapsLock
+    pub swap_escape_caps: bool,
+}
+
+/// Config for autocorrect
+#[cfg(feature = "autocorrect")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AutocorrectConfig {
+    /// List of (typo, correction). Typos can contain lowercase letters and `'` only,
+    /// they are corrected only when a whole word is typed
+    pub words: &'static [(&'static str, &'static str)],
+}
+
+/// Config for the communication with the host
+#[derive(Clone, Copy, Debug)]
+pub struct HostConfig {
+    /// Keyboard reports generated within this window are coalesced into one transmission, which reduces radio wakeups.
+    /// The default value 0 sends every report immediately
+    pub report_coalesce: Duration,
+    /// Primary hid device exposed via USB
+    pub primary_device: UsbPrimaryDevice,
+    /// The release report of a consumer or system control key is sent again if the key is released after it's pressed for this duration,
+    /// in case that the host missed the release and the key is stuck. Keys which are still held are never released.
+    /// The default value is 10s, `None` disables it
+    pub consumer_max_hold: Option<Duration>,
+}
+
+impl Default for HostConfig {
+    fn default() -> Self {
+        Self {
+            report_coalesce: Duration::from_ticks(0),
+            primary_device: UsbPrimaryDevice::default(),
+            consumer_max_hold: Some(Duration::from_secs(10)),
+        }
+    }
+}
+
+/// Primary hid device exposed via USB
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UsbPrimaryDevice {
+    /// Boot keyboard, with mouse, system control and consumer control in another interface
+    #[default]
+    Keyboard,
+    /// Consumer control only, the keyboard interface is omitted. It's used by media remotes, since some hosts only accept media keys from a consumer control device
+    Consumer,
+}
+
+/// Config for split keyboards, used by the central
+#[cfg(feature = "split")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SplitConfig {
+    /// Forward the led indicator state from the host to peripherals,
+    /// so that capslock/numslock/scrolllock LEDs on peripherals reflect it as well
+    pub mirror_indicators: bool,
+}
+
+/// Keyboard layout used by the host, which determines how `Text` in macros is typed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum HostLayout {
+    /// US ANSI
+    #[default]
+    Us,
+    /// UK ISO
+    Uk,
+    /// German QWERTZ
+    De,
+    /// French AZERTY
+    Fr,
 }
 
 /// Configurations for tap hold behavior
@@ -54,6 +224,11 @@ pub struct TapHoldConfig {
     pub prior_idle_time: Duration,
     pub post_wait_time: Duration,
     pub hold_timeout: Duration,
+    /// Apply the hold modifier of modifier tap-hold keys immediately when pressed, and retract it if it's a tap.
+    /// It reduces the latency of the modifier, at the cost of a brief spurious modifier when tapping
+    pub eager_hold: bool,
+    /// Tap-hold keys act as their hold action immediately while a real modifier key is held
+    pub disable_during_modifier: bool,
 }
 
 impl Default for TapHoldConfig {
@@ -63,6 +238,8 @@ impl Default for TapHoldConfig {
             prior_idle_time: Duration::from_millis(120),
             post_wait_time: Duration::from_millis(50),
             hold_timeout: Duration::from_millis(250),
+            eager_hold: false,
+            disable_during_modifier: false,
         }
     }
 }
@@ -70,12 +247,29 @@ impl Default for TapHoldConfig {
 /// Config for one shot behavior
 pub struct OneShotConfig {
     pub timeout: Duration,
+    /// Tapping the pending one shot layer key again cancels the one shot layer
+    pub cancel_on_repeat: bool,
 }
 
 impl Default for OneShotConfig {
     fn default() -> Self {
         Self {
             timeout: Duration::from_secs(1),
+            cancel_on_repeat: false,
+        }
+    }
+}
+
+/// Config for double tap behavior
+pub struct DoubleTapConfig {
+    /// Max interval between the release of the first tap and the press of the second tap
+    pub timeout: Duration,
+}
+
+impl Default for DoubleTapConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_millis(200),
         }
     }
 }
@@ -88,6 +282,13 @@ pub struct StorageConfig {
     pub start_addr: usize,
     // Number of sectors used for storage, >= 2.
     pub num_sectors: u8,
+    /// Size of a storage sector in bytes, MUST BE a multiple of the flash's erase size.
+    /// If sector_size is set to 0(this is the default value), the erase size of the flash is used.
+    /// Set it when the storage is on an external flash/eeprom whose sector size differs from its erase size.
+    ///
+    /// It only changes the layout of the storage range, that is, the alignment of `start_addr` and the size of the range.
+    /// The storage is still paged by the flash's erase size(`NorFlash::ERASE_SIZE`), which is the unit of erase and garbage collection.
+    pub sector_size: usize,
     pub clear_storage: bool,
 }
 
@@ -96,6 +297,7 @@ impl Default for StorageConfig {
         Self {
             start_addr: 0,
             num_sectors: 2,
+            sector_size: 0,
             clear_storage: false,
         }
     }
@@ -107,6 +309,29 @@ pub struct LightConfig<O: OutputPin> {
     pub capslock: Option<LightPinConfig<O>>,
     pub scrolllock: Option<LightPinConfig<O>>,
     pub numslock: Option<LightPinConfig<O>>,
+    /// Show the active layer on the lock LEDs
+    pub layer_indicator: Option<LayerIndicator>,
+    /// Show whether the game mode is on with a lock LED, see `Action::GameModeToggle`
+    pub game_mode_indicator: Option<LockLed>,
+}
+
+/// How the lock LEDs show the active layer.
+///
+/// The layer number is binary-coded on the capslock(bit 0), numslock(bit 1) and scrolllock(bit 2) LEDs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LayerIndicator {
+    /// Lock states win, the layer is shown only when all locks are off
+    LockFirst,
+    /// Layer wins, the layer is shown whenever a layer other than the default layer is active
+    LayerFirst,
+}
+
+/// A lock LED
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LockLed {
+    CapsLock,
+    NumsLock,
+    ScrollLock,
 }
 
 #[derive(Clone, Copy, Default, Debug)]
@@ -121,6 +346,8 @@ impl<O: OutputPin> Default for LightConfig<O> {
             capslock: None,
             scrolllock: None,
             numslock: None,
+            layer_indicator: None,
+            game_mode_indicator: None,
         }
     }
 }
@@ -143,6 +370,21 @@ impl<'a> VialConfig<'a> {
     }
 }
 
+/// Configurations for the keyboard matrix
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MatrixConfig<'a> {
+    /// Matrix positions `(row, col)` which are never scanned, such as unpopulated positions which read noise
+    pub disabled_positions: &'a [(u8, u8)],
+    /// Scan interval while any key is pressed or debouncing, default is 100us
+    pub scan_fast_interval: Option<Duration>,
+    /// Scan interval while all keys are released, default is 100us
+    pub scan_idle_interval: Option<Duration>,
+    /// Time to wait for the matrix pins to settle before the first scan after boot
+    pub boot_settle_time: Option<Duration>,
+    /// Time to hold all output pins at the inactive level after an output pin is scanned, default is 0
+    pub discharge_time: Option<Duration>,
+}
+
 /// Configuration for debouncing
 pub struct DebounceConfig {
     /// Debounce time in ms
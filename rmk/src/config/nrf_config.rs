@@ -1,3 +1,4 @@
+use crate::ble::nrf::BONDED_DEVICE_NUM;
 use embassy_nrf::{
     gpio::{Input, Output},
     saadc::Saadc,
@@ -11,6 +12,8 @@ pub struct BleBatteryConfig<'a> {
     pub saadc: Option<Saadc<'a, 1>>,
     pub adc_divider_measured: u32,
     pub adc_divider_total: u32,
+    /// Turn on the battery saver when the battery level drops below this percentage
+    pub battery_saver_percent: Option<u8>,
 }
 
 impl<'a> Default for BleBatteryConfig<'a> {
@@ -23,6 +26,7 @@ impl<'a> Default for BleBatteryConfig<'a> {
             saadc: None,
             adc_divider_measured: 1,
             adc_divider_total: 1,
+            battery_saver_percent: None,
         }
     }
 }
@@ -45,6 +49,60 @@ impl<'a> BleBatteryConfig<'a> {
             saadc,
             adc_divider_measured,
             adc_divider_total,
+            battery_saver_percent: None,
         }
     }
 }
+
+/// Config of the BLE tx power, all values are in dBm.
+///
+/// When `adaptive_tx_power` is enabled, the tx power of the connection is adjusted according to the RSSI,
+/// within `tx_power_min` and `tx_power_max`. Otherwise `default_tx_power` is always used.
+///
+/// `profile_tx_power` overrides `default_tx_power` for each BLE profile, so that hosts with different distances
+/// can use different tx power.
+pub struct BleTxPowerConfig {
+    pub default_tx_power: i8,
+    pub adaptive_tx_power: bool,
+    pub tx_power_min: i8,
+    pub tx_power_max: i8,
+    pub profile_tx_power: [Option<i8>; BONDED_DEVICE_NUM],
+}
+
+impl Default for BleTxPowerConfig {
+    fn default() -> Self {
+        Self {
+            default_tx_power: 4,
+            adaptive_tx_power: false,
+            tx_power_min: -20,
+            tx_power_max: 4,
+            profile_tx_power: [None; BONDED_DEVICE_NUM],
+        }
+    }
+}
+
+impl BleTxPowerConfig {
+    pub fn new(
+        default_tx_power: i8,
+        adaptive_tx_power: bool,
+        tx_power_min: i8,
+        tx_power_max: i8,
+    ) -> Self {
+        Self {
+            default_tx_power,
+            adaptive_tx_power,
+            tx_power_min,
+            tx_power_max,
+            profile_tx_power: [None; BONDED_DEVICE_NUM],
+        }
+    }
+
+    /// Get the default tx power of the given BLE profile
+    pub(crate) fn profile_default_tx_power(&self, profile: u8) -> i8 {
+        self.profile_tx_power
+            .get(profile as usize)
+            .copied()
+            .flatten()
+            .unwrap_or(self.default_tx_power)
+    }
+}
@@ -36,11 +36,13 @@ pub struct DefaultDebouncer<const INPUT_PIN_NUM: usize, const OUTPUT_PIN_NUM: us
 impl<const INPUT_PIN_NUM: usize, const OUTPUT_PIN_NUM: usize> DebouncerTrait
     for DefaultDebouncer<INPUT_PIN_NUM, OUTPUT_PIN_NUM>
 {
-    /// Create a default debouncer
+    /// Create a default debouncer, all keys start in the known released state
     fn new() -> Self {
         DefaultDebouncer {
             counters: [[DebounceCounter(0); INPUT_PIN_NUM]; OUTPUT_PIN_NUM],
-            last_ms: 0,
+            // Count from the creation instead of the boot, the elapsed time since boot may overflow `u16`
+            // and skip the first transition
+            last_ms: Instant::now().as_millis() as u32,
         }
     }
 
@@ -54,7 +56,7 @@ impl<const INPUT_PIN_NUM: usize, const OUTPUT_PIN_NUM: usize> DebouncerTrait
     ) -> DebounceState {
         // Check debounce state every 1 ms
         let cur_ms = Instant::now().as_millis() as u32;
-        let elapsed_ms = (cur_ms - self.last_ms) as u16;
+        let elapsed_ms = cur_ms.wrapping_sub(self.last_ms) as u16;
 
         // If `elapsed_ms` == 0, the debounce state is checked within 1 ms, skip
         if elapsed_ms > 0 {
@@ -2,6 +2,10 @@ use crate::matrix::KeyState;
 
 pub mod default_bouncer;
 pub mod fast_debouncer;
+pub mod verified_eager_debouncer;
+
+#[cfg(all(feature = "rapid_debouncer", feature = "verified_debouncer"))]
+compile_error!("You may not enable both `rapid_debouncer` and `verified_debouncer` features.");
 
 /// Default DEBOUNCE_THRESHOLD in ms.
 static DEBOUNCE_THRESHOLD: u16 = 10;
@@ -0,0 +1,232 @@
+use embassy_time::{Duration, Instant};
+
+use crate::matrix::KeyState;
+
+use super::{DebounceState, DebouncerTrait, DEBOUNCE_THRESHOLD};
+
+/// Number of consecutive samples which must read released before a release is reported
+const RELEASE_SAMPLES: u8 = 3;
+
+/// Per-key debouncing state of [`VerifiedEagerDebouncer`]
+#[derive(Copy, Clone, Debug)]
+enum VerifyState {
+    /// No debouncing in progress
+    Idle,
+    /// A press has been reported, waiting for the verification window to end
+    Verifying(Instant),
+    /// A release is detected, waiting for the release to be stable.
+    /// Contains the start time and the number of consecutive released samples
+    Releasing(Instant, u8),
+}
+
+/// Eager per-key debouncer with a verified hold.
+///
+/// A press is reported to the keyboard immediately, the same as [`RapidDebouncer`](super::fast_debouncer::RapidDebouncer).
+/// After that, the key is verified within a window of `DEBOUNCE_THRESHOLD` ms.
+/// If the key reads released when the window ends, the contact may be chatter, a compensating release is emitted
+/// once the release is stable. A release is stable when it lasts `DEBOUNCE_THRESHOLD` ms and
+/// `RELEASE_SAMPLES` samples in a row, so a single noisy sample never releases a held key.
+///
+/// Enable it with the `verified_debouncer` feature.
+///
+/// Trade-off: a chattering contact produces a brief phantom press(about 2 * `DEBOUNCE_THRESHOLD` ms) which is
+/// corrected afterwards, instead of being filtered. Use it only when the press latency matters more than
+/// the occasional correction.
+pub struct VerifiedEagerDebouncer<const INPUT_PIN_NUM: usize, const OUTPUT_PIN_NUM: usize> {
+    states: [[VerifyState; INPUT_PIN_NUM]; OUTPUT_PIN_NUM],
+}
+
+impl<const INPUT_PIN_NUM: usize, const OUTPUT_PIN_NUM: usize> DebouncerTrait
+    for VerifiedEagerDebouncer<INPUT_PIN_NUM, OUTPUT_PIN_NUM>
+{
+    /// Create a verified eager debouncer
+    fn new() -> Self {
+        VerifiedEagerDebouncer {
+            states: [[VerifyState::Idle; INPUT_PIN_NUM]; OUTPUT_PIN_NUM],
+        }
+    }
+
+    /// Per-key eager debounce, verify the press afterwards
+    fn detect_change_with_debounce(
+        &mut self,
+        in_idx: usize,
+        out_idx: usize,
+        pin_state: bool,
+        key_state: &KeyState,
+    ) -> DebounceState {
+        self.detect_change_at(in_idx, out_idx, pin_state, key_state, Instant::now())
+    }
+}
+
+impl<const INPUT_PIN_NUM: usize, const OUTPUT_PIN_NUM: usize>
+    VerifiedEagerDebouncer<INPUT_PIN_NUM, OUTPUT_PIN_NUM>
+{
+    /// Debounce the sample which is read at `now`
+    fn detect_change_at(
+        &mut self,
+        in_idx: usize,
+        out_idx: usize,
+        pin_state: bool,
+        key_state: &KeyState,
+        now: Instant,
+    ) -> DebounceState {
+        let window = Duration::from_millis(DEBOUNCE_THRESHOLD as u64);
+        let state = &mut self.states[out_idx][in_idx];
+        match *state {
+            VerifyState::Idle => {
+                if key_state.pressed == pin_state {
+                    DebounceState::Ignored
+                } else if pin_state {
+                    // Report the press immediately, then verify it
+                    *state = VerifyState::Verifying(now);
+                    DebounceState::Debounced
+                } else {
+                    // Releases are deferred
+                    *state = VerifyState::Releasing(now, 1);
+                    DebounceState::InProgress
+                }
+            }
+            VerifyState::Verifying(start) => {
+                if now.saturating_duration_since(start) < window {
+                    DebounceState::InProgress
+                } else if key_state.pressed != pin_state {
+                    // The contact may not hold, release it only if the release is stable
+                    *state = VerifyState::Releasing(now, 1);
+                    DebounceState::InProgress
+                } else {
+                    *state = VerifyState::Idle;
+                    DebounceState::Ignored
+                }
+            }
+            VerifyState::Releasing(start, samples) => {
+                if key_state.pressed == pin_state {
+                    // Bounced back, it's not a release
+                    *state = VerifyState::Idle;
+                    DebounceState::Ignored
+                } else if samples < RELEASE_SAMPLES || now.saturating_duration_since(start) < window
+                {
+                    *state = VerifyState::Releasing(start, samples.saturating_add(1));
+                    DebounceState::InProgress
+                } else {
+                    debug!("Release at ({}, {}) is stable", in_idx, out_idx);
+                    *state = VerifyState::Idle;
+                    DebounceState::Debounced
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn detect(
+        debouncer: &mut VerifiedEagerDebouncer<1, 1>,
+        pin_state: bool,
+        key_state: &mut KeyState,
+        ms: u64,
+    ) -> bool {
+        let debounced = matches!(
+            debouncer.detect_change_at(0, 0, pin_state, key_state, Instant::from_millis(ms)),
+            DebounceState::Debounced
+        );
+        if debounced {
+            key_state.toggle_pressed();
+        }
+        debounced
+    }
+
+    /// Time just after the debouncing window which starts at `start`
+    fn after_window(start: u64) -> u64 {
+        start + DEBOUNCE_THRESHOLD as u64 + 1
+    }
+
+    #[test]
+    fn test_press_and_release() {
+        let mut debouncer = VerifiedEagerDebouncer::<1, 1>::new();
+        let mut key_state = KeyState::new();
+
+        // The press is reported immediately
+        assert!(detect(&mut debouncer, true, &mut key_state, 0));
+        assert!(key_state.pressed);
+        let t = after_window(0);
+        assert!(!detect(&mut debouncer, true, &mut key_state, t));
+
+        // The release is reported only after enough samples within the window
+        let release = t + 1;
+        for i in 0..RELEASE_SAMPLES as u64 {
+            assert!(!detect(&mut debouncer, false, &mut key_state, release + i));
+        }
+        assert!(detect(
+            &mut debouncer,
+            false,
+            &mut key_state,
+            after_window(release)
+        ));
+        assert!(!key_state.pressed);
+    }
+
+    #[test]
+    fn test_release_needs_samples() {
+        let mut debouncer = VerifiedEagerDebouncer::<1, 1>::new();
+        let mut key_state = KeyState::new();
+        assert!(detect(&mut debouncer, true, &mut key_state, 0));
+        let t = after_window(0);
+        assert!(!detect(&mut debouncer, true, &mut key_state, t));
+
+        // The window has passed, but a single released sample isn't enough
+        let release = t + 1;
+        assert!(!detect(&mut debouncer, false, &mut key_state, release));
+        let t = after_window(release);
+        for i in 1..RELEASE_SAMPLES as u64 {
+            assert!(!detect(&mut debouncer, false, &mut key_state, t + i));
+        }
+        assert!(detect(
+            &mut debouncer,
+            false,
+            &mut key_state,
+            t + RELEASE_SAMPLES as u64
+        ));
+    }
+
+    #[test]
+    fn test_noise_during_hold() {
+        let mut debouncer = VerifiedEagerDebouncer::<1, 1>::new();
+        let mut key_state = KeyState::new();
+        assert!(detect(&mut debouncer, true, &mut key_state, 0));
+
+        // A single noisy sample when the verification window ends doesn't release the key
+        let t = after_window(0);
+        assert!(!detect(&mut debouncer, false, &mut key_state, t));
+        assert!(!detect(&mut debouncer, true, &mut key_state, t + 1));
+        let t = after_window(t + 1);
+        assert!(!detect(&mut debouncer, false, &mut key_state, t));
+        let t = after_window(t);
+        assert!(!detect(&mut debouncer, true, &mut key_state, t));
+        assert!(key_state.pressed);
+    }
+
+    #[test]
+    fn test_chatter() {
+        let mut debouncer = VerifiedEagerDebouncer::<1, 1>::new();
+        let mut key_state = KeyState::new();
+        assert!(detect(&mut debouncer, true, &mut key_state, 0));
+
+        // The contact doesn't hold, the phantom press is released once the release is stable
+        for i in 1..=RELEASE_SAMPLES as u64 {
+            assert!(!detect(&mut debouncer, false, &mut key_state, i));
+        }
+        let release = after_window(0);
+        for i in 0..RELEASE_SAMPLES as u64 {
+            assert!(!detect(&mut debouncer, false, &mut key_state, release + i));
+        }
+        assert!(detect(
+            &mut debouncer,
+            false,
+            &mut key_state,
+            after_window(release)
+        ));
+        assert!(!key_state.pressed);
+    }
+}
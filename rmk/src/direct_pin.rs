@@ -1,12 +1,15 @@
 use crate::action::KeyAction;
-#[cfg(not(feature = "rapid_debouncer"))]
+#[cfg(not(any(feature = "rapid_debouncer", feature = "verified_debouncer")))]
 use crate::debounce::default_bouncer::DefaultDebouncer;
 #[cfg(feature = "rapid_debouncer")]
 use crate::debounce::fast_debouncer::RapidDebouncer;
+#[cfg(feature = "verified_debouncer")]
+use crate::debounce::verified_eager_debouncer::VerifiedEagerDebouncer;
 use crate::debounce::DebounceState;
 use crate::debounce::DebouncerTrait;
 use crate::event::KeyEvent;
 use crate::keyboard::KEY_EVENT_CHANNEL;
+use crate::latency_trace::record_scan;
 use crate::matrix::KeyState;
 use crate::MatrixTrait;
 use crate::RmkConfig;
@@ -163,10 +166,24 @@ pub async fn run_rmk_direct_pin_with_async_flash<
     low_active: bool,
     #[cfg(not(feature = "_esp_ble"))] spawner: Spawner,
 ) -> ! {
-    // Create the debouncer
-    #[cfg(feature = "rapid_debouncer")]
+    // Create the debouncer, which follows the orientation of the normal matrix
+    #[cfg(all(feature = "col2row", feature = "rapid_debouncer"))]
+    let debouncer = RapidDebouncer::<ROW, COL>::new();
+    #[cfg(all(feature = "col2row", feature = "verified_debouncer"))]
+    let debouncer = VerifiedEagerDebouncer::<ROW, COL>::new();
+    #[cfg(all(
+        feature = "col2row",
+        not(any(feature = "rapid_debouncer", feature = "verified_debouncer"))
+    ))]
+    let debouncer = DefaultDebouncer::<ROW, COL>::new();
+    #[cfg(all(not(feature = "col2row"), feature = "rapid_debouncer"))]
     let debouncer = RapidDebouncer::<COL, ROW>::new();
-    #[cfg(not(feature = "rapid_debouncer"))]
+    #[cfg(all(not(feature = "col2row"), feature = "verified_debouncer"))]
+    let debouncer = VerifiedEagerDebouncer::<COL, ROW>::new();
+    #[cfg(all(
+        not(feature = "col2row"),
+        not(any(feature = "rapid_debouncer", feature = "verified_debouncer"))
+    ))]
     let debouncer = DefaultDebouncer::<COL, ROW>::new();
 
     // Keyboard matrix
@@ -206,6 +223,23 @@ pub async fn run_rmk_direct_pin_with_async_flash<
     panic!("The run_rmk should never return");
 }
 
+/// Index of a direct pin in the debouncer, returns `(in_idx, out_idx)`.
+///
+/// All direct pins are input pins, so the debouncer follows the orientation of the normal matrix:
+/// it's sized as `Debouncer<ROW, COL>` for col2row(rows are inputs), and `Debouncer<COL, ROW>` for row2col(cols are inputs).
+/// The emitted key positions are always `(row, col)` of `direct_pins`, regardless of the orientation.
+pub(crate) const fn debouncer_index(
+    row_idx: usize,
+    col_idx: usize,
+    col2row: bool,
+) -> (usize, usize) {
+    if col2row {
+        (row_idx, col_idx)
+    } else {
+        (col_idx, row_idx)
+    }
+}
+
 /// DirectPinMartex only has input pins.
 pub(crate) struct DirectPinMatrix<
     #[cfg(feature = "async_matrix")] In: Wait + InputPin,
@@ -318,9 +352,11 @@ impl<
                             direct_pin.is_high().ok().unwrap_or_default()
                         };
 
+                        let (in_idx, out_idx) =
+                            debouncer_index(row_idx, col_idx, cfg!(feature = "col2row"));
                         let debounce_state = self.debouncer.detect_change_with_debounce(
-                            col_idx,
-                            row_idx,
+                            in_idx,
+                            out_idx,
                             pin_state,
                             &self.key_states[row_idx][col_idx],
                         );
@@ -330,6 +366,7 @@ impl<
                                 self.key_states[row_idx][col_idx].toggle_pressed();
                                 let key_state = self.key_states[row_idx][col_idx];
 
+                                record_scan();
                                 KEY_EVENT_CHANNEL
                                     .send(KeyEvent {
                                         row: row_idx as u8,
@@ -363,3 +400,36 @@ impl<
         f(&mut self.key_states[row][col]);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Check that every position of a `ROW` x `COL` direct pin grid fits in the debouncer, and no two positions share a slot
+    fn check_debouncer_index<const ROW: usize, const COL: usize>(col2row: bool) {
+        let (num_in, num_out) = if col2row { (ROW, COL) } else { (COL, ROW) };
+        let mut slots = [[false; 8]; 8];
+        for row_idx in 0..ROW {
+            for col_idx in 0..COL {
+                let (in_idx, out_idx) = debouncer_index(row_idx, col_idx, col2row);
+                assert!(in_idx < num_in && out_idx < num_out);
+                assert!(!slots[out_idx][in_idx]);
+                slots[out_idx][in_idx] = true;
+            }
+        }
+    }
+
+    #[test]
+    fn test_debouncer_index_col2row() {
+        check_debouncer_index::<2, 5>(true);
+        check_debouncer_index::<5, 2>(true);
+        check_debouncer_index::<1, 8>(true);
+    }
+
+    #[test]
+    fn test_debouncer_index_row2col() {
+        check_debouncer_index::<2, 5>(false);
+        check_debouncer_index::<5, 2>(false);
+        check_debouncer_index::<8, 1>(false);
+    }
+}
@@ -0,0 +1,136 @@
+//! Double tap detection, which is shared by `BehaviorConfig::double_tap_lock` and `Action::LayerMomentaryOrToggle`.
+//!
+//! A tap is a press shorter than the tapping term without any other key pressed during it.
+//! Two taps of the same key within the double tap timeout is a double tap.
+//! A key held longer than the tapping term, or any other key pressed between the taps, cancels the double tap.
+
+use embassy_time::{Duration, Instant};
+
+use crate::keycode::KeyCode;
+
+/// Double tap detection of a key, `K` identifies the tapped key
+pub(crate) struct DoubleTap<K> {
+    /// The held key, press time, and whether another key is pressed during the hold
+    held: Option<(K, Instant, bool)>,
+    /// The key and release time of the last tap
+    last_tap: Option<(K, Instant)>,
+}
+
+impl<K> Default for DoubleTap<K> {
+    fn default() -> Self {
+        Self {
+            held: None,
+            last_tap: None,
+        }
+    }
+}
+
+impl<K: Copy + PartialEq> DoubleTap<K> {
+    pub(crate) fn press(&mut self, key: K, now: Instant) {
+        self.held = Some((key, now, false));
+    }
+
+    /// The key which is being tracked, either held or tapped last time
+    pub(crate) fn key(&self) -> Option<K> {
+        match (self.held, self.last_tap) {
+            (Some((key, _, _)), _) | (None, Some((key, _))) => Some(key),
+            (None, None) => None,
+        }
+    }
+
+    /// Another key is pressed, the held key isn't a tap and the last tap can't be doubled anymore
+    pub(crate) fn interrupt(&mut self) {
+        if let Some((_, _, interrupted)) = &mut self.held {
+            *interrupted = true;
+        }
+        self.last_tap = None;
+    }
+
+    /// The key is released, returns whether it completes a double tap
+    pub(crate) fn release(
+        &mut self,
+        key: K,
+        now: Instant,
+        tapping_term: Duration,
+        double_tap_timeout: Duration,
+    ) -> bool {
+        let pressed_at = match self.held.take() {
+            Some((k, pressed_at, false))
+                if k == key && now.saturating_duration_since(pressed_at) < tapping_term =>
+            {
+                pressed_at
+            }
+            _ => {
+                // Held or used with other keys
+                self.last_tap = None;
+                return false;
+            }
+        };
+
+        match self.last_tap.take() {
+            Some((k, released_at))
+                if k == key
+                    && pressed_at.saturating_duration_since(released_at) <= double_tap_timeout =>
+            {
+                true
+            }
+            _ => {
+                self.last_tap = Some((key, now));
+                false
+            }
+        }
+    }
+}
+
+/// Double tap detection of the modifiers in `BehaviorConfig::double_tap_lock`
+pub(crate) type DoubleTapLockState = DoubleTap<KeyCode>;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_double_tap_lock() {
+        let tapping_term = Duration::from_millis(250);
+        let double_tap_timeout = Duration::from_millis(200);
+        let ms = |t: u64| Instant::from_millis(t);
+        let mut state = DoubleTapLockState::default();
+
+        // Double tapping Shift fires the output
+        state.press(KeyCode::LShift, ms(1000));
+        assert!(!state.release(KeyCode::LShift, ms(1050), tapping_term, double_tap_timeout));
+        state.press(KeyCode::LShift, ms(1150));
+        assert!(state.release(KeyCode::LShift, ms(1200), tapping_term, double_tap_timeout));
+        // The third tap starts over
+        state.press(KeyCode::LShift, ms(1300));
+        assert!(!state.release(KeyCode::LShift, ms(1350), tapping_term, double_tap_timeout));
+
+        // Shift used with another key is a normal modifier
+        state.press(KeyCode::LShift, ms(2000));
+        state.interrupt();
+        assert!(!state.release(KeyCode::LShift, ms(2100), tapping_term, double_tap_timeout));
+        state.press(KeyCode::LShift, ms(2150));
+        assert!(!state.release(KeyCode::LShift, ms(2200), tapping_term, double_tap_timeout));
+
+        // A key typed between the taps cancels the double tap
+        state.interrupt();
+        state.press(KeyCode::LShift, ms(2300));
+        assert!(!state.release(KeyCode::LShift, ms(2350), tapping_term, double_tap_timeout));
+
+        // Holding Shift isn't a tap
+        state.press(KeyCode::LShift, ms(3000));
+        assert!(!state.release(KeyCode::LShift, ms(3500), tapping_term, double_tap_timeout));
+        state.press(KeyCode::LShift, ms(3550));
+        assert!(!state.release(KeyCode::LShift, ms(3600), tapping_term, double_tap_timeout));
+
+        // The second tap after the double tap timeout is a single tap
+        state.press(KeyCode::LShift, ms(4000));
+        assert!(!state.release(KeyCode::LShift, ms(4050), tapping_term, double_tap_timeout));
+
+        // Tapping different modifiers isn't a double tap
+        state.press(KeyCode::RShift, ms(5000));
+        assert!(!state.release(KeyCode::RShift, ms(5050), tapping_term, double_tap_timeout));
+        state.press(KeyCode::LShift, ms(5100));
+        assert!(!state.release(KeyCode::LShift, ms(5150), tapping_term, double_tap_timeout));
+    }
+}
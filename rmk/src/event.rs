@@ -10,6 +10,7 @@ use crate::input_device::rotary_encoder::Direction;
 /// and then converts it to the final keyboard/mouse report.
 #[non_exhaustive]
 #[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Event {
     /// Keyboard event
     Key(KeyEvent),
@@ -86,3 +87,15 @@ pub struct KeyEvent {
     pub col: u8,
     pub pressed: bool,
 }
+
+/// Layer change requested by input devices or processors, which is applied by the keyboard
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LayerRequest {
+    /// Activate the layer
+    Activate(u8),
+    /// Deactivate the layer
+    Deactivate(u8),
+    /// Toggle the layer
+    Toggle(u8),
+}
@@ -185,6 +185,21 @@ macro_rules! error {
     };
 }
 
+/// Log an event passing through RMK with its timestamp, which helps to debug the event flow.
+/// It's compiled out entirely when `event_trace` feature is disabled.
+#[collapse_debuginfo(yes)]
+macro_rules! trace_event {
+    ($source:literal, $event:expr) => {
+        #[cfg(feature = "event_trace")]
+        info!(
+            "[{}us] {}: {:?}",
+            ::embassy_time::Instant::now().as_micros(),
+            $source,
+            $event
+        );
+    };
+}
+
 #[cfg(feature = "defmt")]
 #[collapse_debuginfo(yes)]
 macro_rules! unwrap {
@@ -1,10 +1,11 @@
 //! A thin hid wrapper layer which supports writing/reading HID reports via USB and BLE
 
+use embassy_time::{Duration, Instant};
 use embassy_usb::{
     class::hid::{HidReader, HidReaderWriter, HidWriter, ReadError},
     driver::Driver,
 };
-use usbd_hid::descriptor::AsInputReport;
+use usbd_hid::descriptor::{AsInputReport, KeyboardReport};
 
 #[derive(PartialEq, Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -18,6 +19,7 @@ pub(crate) enum HidError {
 }
 
 /// Type of connection
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub(crate) enum ConnectionType {
     Usb,
     Ble,
@@ -161,3 +163,218 @@ impl<'d, D: Driver<'d>, const READ_N: usize, const WRITE_N: usize> HidWriterWrap
             })
     }
 }
+
+/// An optional hid interface, the interface might be omitted by the config.
+/// Reports written to an omitted interface are dropped, and reading from it never completes.
+impl<T: ConnectionTypeWrapper> ConnectionTypeWrapper for Option<T> {
+    fn get_conn_type(&self) -> ConnectionType {
+        match self {
+            Some(hid) => hid.get_conn_type(),
+            None => ConnectionType::Usb,
+        }
+    }
+}
+
+impl<T: HidReaderWrapper> HidReaderWrapper for Option<T> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, HidError> {
+        match self {
+            Some(hid) => hid.read(buf).await,
+            None => core::future::pending().await,
+        }
+    }
+}
+
+impl<T: HidWriterWrapper> HidWriterWrapper for Option<T> {
+    async fn write_serialize<IR: AsInputReport>(&mut self, r: &IR) -> Result<(), HidError> {
+        match self {
+            Some(hid) => hid.write_serialize(r).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn write(&mut self, report: &[u8]) -> Result<(), HidError> {
+        match self {
+            Some(hid) => hid.write(report).await,
+            None => Ok(()),
+        }
+    }
+}
+
+/// Coalesce keyboard reports which are generated within a short window into one transmission,
+/// which reduces the radio wakeups of BLE.
+///
+/// The first report is sent immediately, reports in the following window are merged and sent when the window ends.
+/// A report is merged only if no transition is lost and the order of presses is kept,
+/// otherwise the pending report is sent immediately.
+pub(crate) struct ReportCoalescer {
+    /// Coalescing window, 0 means sending every report immediately
+    window: Duration,
+    /// Last report sent to the host
+    sent: KeyboardReport,
+    /// Time of the last report sent to the host
+    last_sent: Instant,
+    /// Merged report which is waiting for the end of the window
+    pending: Option<KeyboardReport>,
+}
+
+impl ReportCoalescer {
+    pub(crate) fn new(window: Duration) -> Self {
+        Self {
+            window,
+            sent: KeyboardReport {
+                modifier: 0,
+                reserved: 0,
+                leds: 0,
+                keycodes: [0; 6],
+            },
+            last_sent: Instant::from_ticks(0),
+            pending: None,
+        }
+    }
+
+    /// Time when the pending report should be sent
+    pub(crate) fn deadline(&self) -> Option<Instant> {
+        self.pending.map(|_| self.last_sent + self.window)
+    }
+
+    /// Push a new keyboard report, returns the report which should be sent now
+    pub(crate) fn push(&mut self, report: KeyboardReport, now: Instant) -> Option<KeyboardReport> {
+        match self.pending {
+            Some(pending) if can_merge(&self.sent, &pending, &report) => {
+                self.pending = Some(report);
+                None
+            }
+            Some(pending) => {
+                // Merging loses a transition, send the pending report now, and start a new window for the new report
+                self.pending = Some(report);
+                Some(self.mark_sent(pending, now))
+            }
+            None if now >= self.last_sent + self.window => Some(self.mark_sent(report, now)),
+            None => {
+                self.pending = Some(report);
+                None
+            }
+        }
+    }
+
+    /// Take the pending report, which should be sent now
+    pub(crate) fn flush(&mut self, now: Instant) -> Option<KeyboardReport> {
+        self.pending
+            .take()
+            .map(|report| self.mark_sent(report, now))
+    }
+
+    fn mark_sent(&mut self, report: KeyboardReport, now: Instant) -> KeyboardReport {
+        self.sent = report;
+        self.last_sent = now;
+        report
+    }
+}
+
+/// Whether the keycode is pressed in the report
+fn is_pressed(report: &KeyboardReport, keycode: u8) -> bool {
+    keycode != 0 && report.keycodes.contains(&keycode)
+}
+
+/// Whether `next` can replace `pending`, which hasn't been sent yet, without losing a transition or reordering presses
+fn can_merge(sent: &KeyboardReport, pending: &KeyboardReport, next: &KeyboardReport) -> bool {
+    // A modifier or key which changes twice would lose both transitions
+    if (sent.modifier ^ pending.modifier) & (pending.modifier ^ next.modifier) != 0 {
+        return false;
+    }
+    let toggled_twice = sent
+        .keycodes
+        .iter()
+        .chain(pending.keycodes.iter())
+        .chain(next.keycodes.iter())
+        .any(|&k| {
+            is_pressed(sent, k) != is_pressed(pending, k)
+                && is_pressed(pending, k) != is_pressed(next, k)
+        });
+    if toggled_twice {
+        return false;
+    }
+
+    // Presses in the pending report should reach the host before the later presses and modifier changes
+    let pending_presses = pending.modifier & !sent.modifier != 0
+        || pending
+            .keycodes
+            .iter()
+            .any(|&k| k != 0 && !is_pressed(sent, k));
+    let next_presses = next
+        .keycodes
+        .iter()
+        .any(|&k| k != 0 && !is_pressed(pending, k));
+    !(pending_presses && (next_presses || next.modifier != pending.modifier))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn report(modifier: u8, keys: &[u8]) -> KeyboardReport {
+        let mut keycodes = [0; 6];
+        keycodes[..keys.len()].copy_from_slice(keys);
+        KeyboardReport {
+            modifier,
+            reserved: 0,
+            leds: 0,
+            keycodes,
+        }
+    }
+
+    #[test]
+    fn test_no_coalescing() {
+        let mut coalescer = ReportCoalescer::new(Duration::from_millis(0));
+        let now = Instant::from_millis(100);
+        assert!(coalescer.push(report(0, &[4]), now).is_some());
+        assert!(coalescer.push(report(0, &[]), now).is_some());
+        assert_eq!(coalescer.deadline(), None);
+    }
+
+    #[test]
+    fn test_coalesce_releases() {
+        let mut coalescer = ReportCoalescer::new(Duration::from_millis(5));
+        let start = Instant::from_millis(100);
+        // The first report is sent immediately
+        assert!(coalescer.push(report(0, &[4, 5]), start).is_some());
+        // Releases in the window are merged
+        assert!(coalescer.push(report(0, &[5]), start).is_none());
+        assert!(coalescer
+            .push(report(0, &[]), start + Duration::from_millis(1))
+            .is_none());
+        assert_eq!(coalescer.deadline(), Some(start + Duration::from_millis(5)));
+        let flushed = coalescer.flush(start + Duration::from_millis(5)).unwrap();
+        assert_eq!(flushed.keycodes, [0; 6]);
+        assert_eq!(coalescer.deadline(), None);
+    }
+
+    #[test]
+    fn test_never_drop_transition() {
+        let mut coalescer = ReportCoalescer::new(Duration::from_millis(5));
+        let start = Instant::from_millis(100);
+        assert!(coalescer.push(report(0, &[]), start).is_some());
+        // Tap a key in the window, the press must be sent before the release
+        assert!(coalescer.push(report(0, &[4]), start).is_none());
+        let sent = coalescer.push(report(0, &[]), start).unwrap();
+        assert_eq!(sent.keycodes[0], 4);
+        let sent = coalescer.flush(start + Duration::from_millis(5)).unwrap();
+        assert_eq!(sent.keycodes[0], 0);
+    }
+
+    #[test]
+    fn test_keep_press_order() {
+        let mut coalescer = ReportCoalescer::new(Duration::from_millis(5));
+        let start = Instant::from_millis(100);
+        assert!(coalescer.push(report(0, &[]), start).is_some());
+        assert!(coalescer.push(report(0, &[4]), start).is_none());
+        // Another press isn't merged, the first press is sent first
+        let sent = coalescer.push(report(0, &[4, 5]), start).unwrap();
+        assert_eq!(sent.keycodes, [4, 0, 0, 0, 0, 0]);
+        // A modifier change after a press isn't merged either
+        let sent = coalescer.push(report(0x02, &[4, 5]), start).unwrap();
+        assert_eq!(sent.keycodes, [4, 5, 0, 0, 0, 0]);
+        let sent = coalescer.flush(start + Duration::from_millis(5)).unwrap();
+        assert_eq!(sent.modifier, 0x02);
+    }
+}
@@ -12,7 +12,11 @@ use embassy_sync::{
     channel::{Receiver, Sender},
 };
 
-use crate::keyboard::{EVENT_CHANNEL_SIZE, REPORT_CHANNEL_SIZE};
+use crate::event::LayerRequest;
+use crate::keyboard::{
+    EVENT_CHANNEL_SIZE, KEY_TAP_CHANNEL, LAYER_REQUEST_CHANNEL, REPORT_CHANNEL_SIZE,
+};
+use crate::keycode::KeyCode;
 
 pub mod rotary_encoder;
 
@@ -103,6 +107,24 @@ pub trait InputProcessor {
         &self,
     ) -> Sender<CriticalSectionRawMutex, Self::ReportType, REPORT_CHANNEL_SIZE>;
 
+    /// Request a layer change, such as activating a layer when a pointing device moves.
+    ///
+    /// The request is applied by the keyboard, so the input processor doesn't need to access the keymap.
+    fn request_layer(&self, request: LayerRequest) -> impl Future<Output = ()> {
+        async move {
+            LAYER_REQUEST_CHANNEL.send(request).await;
+        }
+    }
+
+    /// Tap a key, the key is pressed and released by the keyboard, just like it's tapped in the keymap.
+    ///
+    /// The held keys and modifiers in the keyboard report are kept.
+    fn tap_key(&self, key: KeyCode) -> impl Future<Output = ()> {
+        async move {
+            KEY_TAP_CHANNEL.send(key).await;
+        }
+    }
+
     /// Default implementation of the input processor. It wait for a new event from the event channel,
     /// then process the event.
     ///
@@ -159,7 +181,7 @@ macro_rules! run_devices {
 /// # Example
 /// ```rust
 /// // `RotaryEncoderProcessor` and `TouchpadProcessor` should implement `InputProcessor` trait
-/// let d1 = RotaryEncoderProcessor{};
+/// let d1 = RotaryEncoderProcessor::new();
 /// let d2 = TouchpadProcessor{};
 ///
 /// // Run all input devices concurrently
@@ -1,5 +1,7 @@
 //! The rotary encoder implementation is adapted from: https://github.com/leshow/rotary-encoder-hal/blob/master/src/lib.rs
 
+use core::sync::atomic::{AtomicBool, AtomicU16, AtomicU8, Ordering};
+
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::channel::{Receiver, Sender};
 use embedded_hal::digital::InputPin;
@@ -8,12 +10,52 @@ use embedded_hal_async::digital::Wait;
 use postcard::experimental::max_size::MaxSize;
 use serde::{Deserialize, Serialize};
 
-use crate::event::{Event, RotaryEncoderEvent};
-use crate::keyboard::{KeyboardReportMessage, EVENT_CHANNEL, KEYBOARD_REPORT_CHANNEL};
+use crate::event::{Event, KeyEvent, RotaryEncoderEvent};
+use crate::keyboard::{
+    KeyboardReportMessage, EVENT_CHANNEL, KEYBOARD_REPORT_CHANNEL, KEY_EVENT_CHANNEL,
+};
+use crate::keycode::KeyCode;
 use crate::REPORT_CHANNEL_SIZE;
 
 use super::{InputDevice, InputProcessor, EVENT_CHANNEL_SIZE};
 
+/// Maximum number of rotary encoders whose resolution can be adjusted at runtime
+pub(crate) const MAX_ENCODER_NUM: usize = 8;
+
+/// Runtime resolution override of each encoder, set by the host and saved in storage.
+/// 0 means no override, the resolution set in the firmware is used.
+pub(crate) static ENCODER_RESOLUTION: [AtomicU8; MAX_ENCODER_NUM] =
+    [const { AtomicU8::new(0) }; MAX_ENCODER_NUM];
+
+/// Whether the encoder calibration mode is on
+pub(crate) static ENCODER_CALIBRATING: AtomicBool = AtomicBool::new(false);
+
+/// Number of pulses of each encoder counted in calibration mode
+pub(crate) static ENCODER_CALIBRATION_PULSES: [AtomicU16; MAX_ENCODER_NUM] =
+    [const { AtomicU16::new(0) }; MAX_ENCODER_NUM];
+
+/// Start the encoder calibration, the pulses of all encoders are counted until calibration stops.
+pub(crate) fn start_encoder_calibration() {
+    ENCODER_CALIBRATION_PULSES
+        .iter()
+        .for_each(|p| p.store(0, Ordering::Relaxed));
+    ENCODER_CALIBRATING.store(true, Ordering::Release);
+}
+
+/// Stop the encoder calibration, the counted pulses are kept until the next calibration starts.
+pub(crate) fn stop_encoder_calibration() {
+    ENCODER_CALIBRATING.store(false, Ordering::Release);
+}
+
+/// Number of pulses of the encoder `id` counted in the current or last calibration.
+///
+/// Rotate the encoder a full rotation in calibration mode, the suggested resolution is `pulses / detents_per_rotation`.
+pub(crate) fn encoder_calibration_pulses(id: u8) -> u16 {
+    ENCODER_CALIBRATION_PULSES
+        .get(id as usize)
+        .map_or(0, |p| p.load(Ordering::Relaxed))
+}
+
 /// Holds current/old state and both [`InputPin`](https://docs.rs/embedded-hal/latest/embedded_hal/digital/trait.InputPin.html)
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -24,6 +66,10 @@ pub struct RotaryEncoder<A, B, P> {
     phase: P,
     /// The index of the rotary encoder
     id: u8,
+    /// Number of pulses per step, can be overridden at runtime by the host
+    resolution: u8,
+    /// Accumulated pulses since last step, positive for clockwise
+    pulses: i8,
 }
 
 /// The encoder direction is either `Clockwise`, `CounterClockwise`, or `None`
@@ -74,6 +120,8 @@ where
             state: 0u8,
             phase: DefaultPhase,
             id,
+            resolution: 1,
+            pulses: 0,
         }
     }
 }
@@ -87,6 +135,60 @@ impl<A: InputPin, B: InputPin, P: Phase> RotaryEncoder<A, B, P> {
             state: 0u8,
             phase,
             id,
+            resolution: 1,
+            pulses: 0,
+        }
+    }
+
+    /// Set the number of pulses per step, a `Direction` is reported once every `resolution` pulses.
+    ///
+    /// This value can be overridden at runtime by the host.
+    pub fn set_resolution(&mut self, resolution: u8) {
+        self.resolution = resolution.max(1);
+    }
+
+    /// Get the effective resolution, the runtime override takes precedence
+    fn effective_resolution(&self) -> i8 {
+        let resolution = ENCODER_RESOLUTION
+            .get(self.id as usize)
+            .map_or(0, |r| r.load(Ordering::Relaxed));
+        let resolution = if resolution == 0 {
+            self.resolution
+        } else {
+            resolution
+        };
+        resolution.clamp(1, i8::MAX as u8) as i8
+    }
+
+    /// Accumulate a pulse, returns the direction when the pulses reach the resolution
+    fn accumulate(&mut self, direction: Direction) -> Direction {
+        let delta = match direction {
+            Direction::Clockwise => 1,
+            Direction::CounterClockwise => -1,
+            Direction::None => return Direction::None,
+        };
+
+        if ENCODER_CALIBRATING.load(Ordering::Acquire) {
+            if let Some(p) = ENCODER_CALIBRATION_PULSES.get(self.id as usize) {
+                // Only the encoder itself writes the counter, load + store is enough
+                p.store(
+                    p.load(Ordering::Relaxed).saturating_add(1),
+                    Ordering::Relaxed,
+                );
+            }
+        }
+
+        // Reset the accumulated pulses when the direction changes
+        if (self.pulses > 0 && delta < 0) || (self.pulses < 0 && delta > 0) {
+            self.pulses = 0;
+        }
+        self.pulses += delta;
+
+        if self.pulses.abs() >= self.effective_resolution() {
+            self.pulses = 0;
+            direction
+        } else {
+            Direction::None
         }
     }
 
@@ -162,6 +264,7 @@ impl<
             }
 
             let direction = self.update();
+            let direction = self.accumulate(direction);
 
             self.event_sender()
                 .send(Event::RotaryEncoder(RotaryEncoderEvent {
@@ -177,7 +280,106 @@ impl<
     }
 }
 
-pub struct RotaryEncoderProcessor {}
+/// The push switch of a rotary encoder which is wired to a dedicated pin.
+///
+/// The switch is mapped to a virtual key position `(row, col)` in the keymap, its press/release is sent as a normal key event.
+/// So the action at that position, including tap-hold actions like `LT` and `MT`, works the same as other keys.
+/// The virtual position should be a position that no physical key is using.
+pub struct RotaryEncoderSwitch<S> {
+    pin: S,
+    /// Virtual key position of the switch in the keymap
+    row: u8,
+    col: u8,
+    /// Whether the switch is pressed when the pin is low
+    low_active: bool,
+    /// Current debounced state
+    pressed: bool,
+}
+
+impl<S: InputPin> RotaryEncoderSwitch<S> {
+    /// Create a encoder switch which is mapped to `(row, col)` in the keymap
+    pub fn new(pin: S, row: u8, col: u8, low_active: bool) -> Self {
+        Self {
+            pin,
+            row,
+            col,
+            low_active,
+            pressed: false,
+        }
+    }
+
+    fn read_pressed(&mut self) -> bool {
+        let pin_state = if self.low_active {
+            self.pin.is_low()
+        } else {
+            self.pin.is_high()
+        };
+        pin_state.unwrap_or(self.pressed)
+    }
+}
+
+impl<
+        #[cfg(feature = "async_matrix")] S: InputPin + Wait,
+        #[cfg(not(feature = "async_matrix"))] S: InputPin,
+    > InputDevice for RotaryEncoderSwitch<S>
+{
+    type EventType = KeyEvent;
+
+    async fn run(&mut self) {
+        loop {
+            #[cfg(not(feature = "async_matrix"))]
+            embassy_time::Timer::after_millis(1).await;
+
+            #[cfg(feature = "async_matrix")]
+            let _ = self.pin.wait_for_any_edge().await;
+
+            if self.read_pressed() == self.pressed {
+                continue;
+            }
+
+            // Debounce: the new state should be stable for a while
+            embassy_time::Timer::after_millis(5).await;
+            let pressed = self.read_pressed();
+            if pressed != self.pressed {
+                self.pressed = pressed;
+                self.event_sender()
+                    .send(KeyEvent {
+                        row: self.row,
+                        col: self.col,
+                        pressed,
+                    })
+                    .await;
+            }
+        }
+    }
+
+    fn event_sender(&self) -> Sender<CriticalSectionRawMutex, Self::EventType, EVENT_CHANNEL_SIZE> {
+        KEY_EVENT_CHANNEL.sender()
+    }
+}
+
+/// Input processor of rotary encoders
+#[derive(Default)]
+pub struct RotaryEncoderProcessor {
+    /// Key which is tapped on every detent of each encoder, regardless of the direction
+    tick_keys: [Option<KeyCode>; MAX_ENCODER_NUM],
+}
+
+impl RotaryEncoderProcessor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Tap `key` on every detent of the encoder `id` regardless of the direction, `None` to disable it.
+    ///
+    /// It's useful for apps which scrub frame-by-frame, while the direction is handled elsewhere.
+    pub fn set_tick_key(&mut self, id: u8, key: Option<KeyCode>) {
+        match self.tick_keys.get_mut(id as usize) {
+            Some(tick_key) => *tick_key = key,
+            None => warn!("Encoder id {} exceeds the max number of encoders", id),
+        }
+    }
+}
 
 impl InputProcessor for RotaryEncoderProcessor {
     type EventType = Event;
@@ -185,16 +387,22 @@ impl InputProcessor for RotaryEncoderProcessor {
     type ReportType = KeyboardReportMessage;
 
     async fn process(&mut self, event: Self::EventType) {
+        trace_event!("event", event);
         match event {
-            Event::RotaryEncoder(RotaryEncoderEvent { id, direction }) => match direction {
-                Direction::Clockwise => {
-                    debug!("Encoder {} - Clockwise", id);
+            Event::RotaryEncoder(RotaryEncoderEvent { id, direction }) => {
+                match direction {
+                    Direction::Clockwise => {
+                        debug!("Encoder {} - Clockwise", id);
+                    }
+                    Direction::CounterClockwise => {
+                        debug!("Encoder {} - CounterClockwise", id);
+                    }
+                    Direction::None => return,
                 }
-                Direction::CounterClockwise => {
-                    debug!("Encoder {} - CounterClockwise", id);
+                if let Some(&Some(tick_key)) = self.tick_keys.get(id as usize) {
+                    self.tap_key(tick_key).await;
                 }
-                Direction::None => (),
-            },
+            }
             _ => {}
         }
     }
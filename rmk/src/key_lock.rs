@@ -0,0 +1,91 @@
+//! Key lock, which keeps the next pressed key held until it's pressed again.
+
+use heapless::FnvIndexMap;
+
+use crate::event::KeyEvent;
+
+/// Maximum number of keys which can be locked by `Action::KeyLock` at the same time
+const KEY_LOCK_NUM: usize = 8;
+
+/// State of `Action::KeyLock`
+#[derive(Default)]
+pub(crate) struct KeyLockState {
+    /// `KeyLock` is pressed, the next pressed key will be locked
+    armed: bool,
+    /// Positions of locked keys, the value is whether the key is being unlocked
+    locked: FnvIndexMap<(u8, u8), bool, KEY_LOCK_NUM>,
+}
+
+impl KeyLockState {
+    /// Arm the key lock, or cancel it if it's already armed
+    pub(crate) fn toggle_armed(&mut self) {
+        self.armed = !self.armed;
+    }
+
+    /// Filter the key event through the key lock.
+    ///
+    /// Returns the key event to be processed, or `None` if the event should be ignored:
+    /// - the physical release of a locked key is ignored, so that the key keeps held
+    /// - pressing a locked key again unlocks it, which is processed as the release of the key
+    pub(crate) fn filter(&mut self, key_event: KeyEvent) -> Option<KeyEvent> {
+        let pos = (key_event.row, key_event.col);
+        if let Some(&unlocking) = self.locked.get(&pos) {
+            return match (key_event.pressed, unlocking) {
+                (true, false) => {
+                    // Pressed again, unlock the key
+                    self.locked.insert(pos, true).ok();
+                    Some(KeyEvent {
+                        pressed: false,
+                        ..key_event
+                    })
+                }
+                (false, true) => {
+                    self.locked.remove(&pos);
+                    None
+                }
+                _ => None,
+            };
+        }
+
+        if self.armed && key_event.pressed {
+            self.armed = false;
+            if self.locked.insert(pos, false).is_err() {
+                warn!("Too many locked keys, ignore key lock");
+            }
+        }
+        Some(key_event)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn key_event(row: u8, col: u8, pressed: bool) -> KeyEvent {
+        KeyEvent { row, col, pressed }
+    }
+
+    #[test]
+    fn test_key_lock() {
+        let mut key_lock = KeyLockState::default();
+        key_lock.toggle_armed();
+
+        // Press the key, it's locked
+        let e = key_lock.filter(key_event(1, 2, true)).unwrap();
+        assert!(e.pressed);
+        // The physical release is ignored
+        assert!(key_lock.filter(key_event(1, 2, false)).is_none());
+        // Other keys are not affected
+        assert!(key_lock.filter(key_event(0, 0, true)).unwrap().pressed);
+        assert!(!key_lock.filter(key_event(0, 0, false)).unwrap().pressed);
+
+        // Press the locked key again, it's released
+        let e = key_lock.filter(key_event(1, 2, true)).unwrap();
+        assert!(!e.pressed);
+        assert!(key_lock.filter(key_event(1, 2, false)).is_none());
+
+        // Now the key works normally
+        assert!(key_lock.filter(key_event(1, 2, true)).unwrap().pressed);
+        assert!(!key_lock.filter(key_event(1, 2, false)).unwrap().pressed);
+    }
+}
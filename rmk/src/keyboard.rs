@@ -1,22 +1,41 @@
-use crate::config::BehaviorConfig;
-use crate::event::{Event, KeyEvent};
+#[cfg(feature = "autocorrect")]
+use crate::autocorrect::Autocorrect;
+use crate::channel_stats::{record_dropped, StatsChannel};
+use crate::config::{BehaviorConfig, HostConfig, LayerMouseConfig};
+use crate::double_tap::DoubleTapLockState;
+use crate::event::{Event, KeyEvent, LayerRequest};
+use crate::key_lock::KeyLockState;
+use crate::latency_trace::record_report;
+use crate::light::{GAME_MODE_SIGNAL, LAYER_INDICATOR_SIGNAL};
+use crate::magic::MagicState;
+use crate::modifier_chord::ModifierChordState;
+use crate::momentary_or_toggle::MomentaryOrToggleState;
+use crate::other_key_guard::OtherKeyReleaseGuard;
+use crate::press_interval::PressIntervalFilter;
+use crate::repeating::RepeatingAction;
+#[cfg(feature = "gamepad")]
+use crate::usb::descriptor::GamepadReport;
 use crate::CONNECTION_STATE;
 use crate::{
     action::{Action, KeyAction},
-    hid::{ConnectionType, HidWriterWrapper},
+    hid::{ConnectionType, HidWriterWrapper, ReportCoalescer},
     keyboard_macro::{MacroOperation, NUM_MACRO},
     keycode::{KeyCode, ModifierCombination},
     keymap::KeyMap,
+    storage::{FlashOperationMessage, FLASH_CHANNEL},
     usb::descriptor::{CompositeReport, CompositeReportType, ViaReport},
     KEYBOARD_STATE,
 };
 use core::cell::RefCell;
-use embassy_futures::{select::select, yield_now};
+use embassy_futures::{
+    select::{select, select4, Either, Either4},
+    yield_now,
+};
 use embassy_sync::{
     blocking_mutex::raw::CriticalSectionRawMutex,
     channel::{Channel, Receiver, Sender},
 };
-use embassy_time::{Instant, Timer};
+use embassy_time::{Duration, Instant, Timer};
 use heapless::{FnvIndexMap, Vec};
 use usbd_hid::descriptor::KeyboardReport;
 
@@ -27,6 +46,19 @@ pub static KEY_EVENT_CHANNEL: Channel<CriticalSectionRawMutex, KeyEvent, EVENT_C
 pub static EVENT_CHANNEL: Channel<CriticalSectionRawMutex, Event, EVENT_CHANNEL_SIZE> =
     Channel::new();
 
+pub const LAYER_REQUEST_CHANNEL_SIZE: usize = 4;
+/// Layer requests from input devices and processors, see [`LayerRequest`]
+pub static LAYER_REQUEST_CHANNEL: Channel<
+    CriticalSectionRawMutex,
+    LayerRequest,
+    LAYER_REQUEST_CHANNEL_SIZE,
+> = Channel::new();
+
+pub const KEY_TAP_CHANNEL_SIZE: usize = 4;
+/// Keys which are requested to be tapped by input devices and processors, such as the tick key of encoders
+pub static KEY_TAP_CHANNEL: Channel<CriticalSectionRawMutex, KeyCode, KEY_TAP_CHANNEL_SIZE> =
+    Channel::new();
+
 pub const REPORT_CHANNEL_SIZE: usize = 32;
 pub(crate) static KEYBOARD_REPORT_CHANNEL: Channel<
     CriticalSectionRawMutex,
@@ -58,41 +90,205 @@ impl<T> OneShotState<T> {
     }
 }
 
+impl OneShotState<u8> {
+    /// Update the one shot layer state when a one shot layer key is pressed.
+    ///
+    /// A different one shot layer replaces the pending one instead of stacking on it.
+    /// If `cancel_on_repeat` is set, pressing the pending one shot layer key again cancels it.
+    /// Returns the layer to deactivate and the layer to activate.
+    fn press_layer(&mut self, layer_num: u8, cancel_on_repeat: bool) -> (Option<u8>, Option<u8>) {
+        if let OneShotState::Single(l) = *self {
+            if l == layer_num && cancel_on_repeat {
+                *self = OneShotState::None;
+                return (Some(l), None);
+            }
+        }
+
+        let old = self.value().copied();
+        *self = match *self {
+            OneShotState::None | OneShotState::Initial(_) => OneShotState::Initial(layer_num),
+            OneShotState::Single(_) => OneShotState::Single(layer_num),
+            OneShotState::Held(_) => OneShotState::Held(layer_num),
+        };
+        (old, Some(layer_num))
+    }
+}
+
+/// Interval of the reports of held mouse keys
+const MOUSE_KEY_INTERVAL: Duration = Duration::from_millis(20);
+
+/// The on-connect macro isn't run again if the keyboard reconnects within this duration
+const CONNECT_MACRO_GUARD: Duration = Duration::from_secs(30);
+
+/// Max time to wait for the host connection before running the on-connect macro
+const CONNECT_MACRO_WAIT: Duration = Duration::from_secs(5);
+
+/// Interval of checking the host connection before running the on-connect macro
+const CONNECT_MACRO_POLL: Duration = Duration::from_millis(50);
+
+/// Delay of the on-connect macro after the host is connected, which gives the host some time to set up the HID interfaces
+const CONNECT_MACRO_DELAY: Duration = Duration::from_millis(500);
+
+/// On-connect macro which waits for the host connection
+#[derive(Clone, Copy, Debug)]
+struct PendingConnectMacro {
+    /// When the keyboard starts waiting
+    start: Instant,
+    /// When the host is connected
+    connected: Option<Instant>,
+}
+
+/// Maximum number of tap-hold keys which are held as their tap action in game mode at the same time
+const GAME_MODE_KEY_NUM: usize = 8;
+
+/// State of `Action::GameModeToggle`
+#[derive(Default)]
+struct GameModeState {
+    /// Whether the game mode is on
+    enabled: bool,
+    /// Positions of tap-hold keys pressed in game mode, they act as their tap action until released
+    taps: Vec<(u8, u8), GAME_MODE_KEY_NUM>,
+}
+
+impl GameModeState {
+    /// Resolve the key action of the key event.
+    ///
+    /// The decision is made when the key is pressed, so toggling the game mode while a tap-hold key is held
+    /// doesn't change how the key is released.
+    fn resolve(&mut self, key_event: KeyEvent, action: KeyAction) -> KeyAction {
+        let tap_action = match action {
+            KeyAction::TapHold(tap_action, _)
+            | KeyAction::LayerTapHold(tap_action, _)
+            | KeyAction::ModifierTapHold(tap_action, _) => tap_action,
+            _ => return action,
+        };
+        let pos = (key_event.row, key_event.col);
+        if key_event.pressed {
+            if self.enabled && self.taps.push(pos).is_ok() {
+                return KeyAction::Single(tap_action);
+            }
+        } else if let Some(i) = self.taps.iter().position(|&p| p == pos) {
+            self.taps.swap_remove(i);
+            return KeyAction::Single(tap_action);
+        }
+        action
+    }
+}
+
+/// Apply the mouse key settings of the active layer to the default deltas, returns (cursor delta, wheel delta)
+fn mouse_key_delta(
+    layer_mouse: &[(u8, LayerMouseConfig)],
+    layer: u8,
+    move_delta: i8,
+    wheel_delta: i8,
+) -> (i8, i8) {
+    match layer_mouse.iter().find(|&&(l, _)| l == layer) {
+        Some((_, config)) => {
+            let move_delta = (move_delta as i32 * config.speed_percent as i32 / 100)
+                .clamp(1, i8::MAX as i32) as i8;
+            let wheel_delta = if config.scroll_invert {
+                -wheel_delta
+            } else {
+                wheel_delta
+            };
+            (move_delta, wheel_delta)
+        }
+        None => (move_delta, wheel_delta),
+    }
+}
+
+/// Move the key event to the target position in `BehaviorConfig::position_remap`, if its position is remapped
+fn remap_position(remap: &[((u8, u8), (u8, u8))], key_event: KeyEvent) -> KeyEvent {
+    match remap
+        .iter()
+        .find(|&&(from, _)| from == (key_event.row, key_event.col))
+    {
+        Some(&(_, (row, col))) => KeyEvent {
+            row,
+            col,
+            pressed: key_event.pressed,
+        },
+        None => key_event,
+    }
+}
+
 /// Matrix scanning task sends this [KeyboardReportMessage] to communication task.
 pub enum KeyboardReportMessage {
     /// Normal keyboard hid report
     KeyboardReport(KeyboardReport),
     /// Other types of keyboard reports: mouse + media(consumer) + system control
     CompositeReport(CompositeReport, CompositeReportType),
+    /// Gamepad report, sent via the dedicated gamepad hid interface
+    #[cfg(feature = "gamepad")]
+    GamepadReport(GamepadReport),
 }
 
 /// This task processes all keyboard reports and send them to the host
-pub(crate) async fn communication_task<'a, W: HidWriterWrapper, W2: HidWriterWrapper>(
+pub(crate) async fn communication_task<
+    'a,
+    W: HidWriterWrapper,
+    W2: HidWriterWrapper,
+    #[cfg(feature = "gamepad")] W3: HidWriterWrapper,
+>(
     receiver: &Receiver<'a, CriticalSectionRawMutex, KeyboardReportMessage, REPORT_CHANNEL_SIZE>,
     keybooard_hid_writer: &mut W,
     other_hid_writer: &mut W2,
+    #[cfg(feature = "gamepad")] gamepad_hid_writer: &mut W3,
+    report_coalesce: Duration,
 ) {
     // This delay is necessary otherwise this task will stuck at the first send when the USB is suspended
     Timer::after_secs(2).await;
+    let mut coalescer = ReportCoalescer::new(report_coalesce);
     loop {
-        let report = receiver.receive().await;
+        let deadline = coalescer.deadline().unwrap_or(Instant::MAX);
+        let report = match select(receiver.receive(), Timer::at(deadline)).await {
+            Either::First(report) => report,
+            Either::Second(_) => {
+                // The coalescing window ends, send the pending keyboard report.
+                // If the connection is lost, the report is dropped: the write might block, and the report is stale for the next host
+                if let Some(report) = coalescer.flush(Instant::now()) {
+                    if CONNECTION_STATE.load(core::sync::atomic::Ordering::Acquire) {
+                        write_keyboard_report_to_host(&report, keybooard_hid_writer).await;
+                    }
+                }
+                continue;
+            }
+        };
         // Only send the report after the connection is established.
         if CONNECTION_STATE.load(core::sync::atomic::Ordering::Acquire) {
             match report {
                 KeyboardReportMessage::KeyboardReport(report) => {
-                    match keybooard_hid_writer.write_serialize(&report).await {
-                        Ok(()) => {}
-                        Err(e) => error!("Send keyboard report error: {:?}", e),
-                    };
+                    if let Some(report) = coalescer.push(report, Instant::now()) {
+                        write_keyboard_report_to_host(&report, keybooard_hid_writer).await;
+                    }
                 }
                 KeyboardReportMessage::CompositeReport(report, report_type) => {
+                    // Keep the order of reports, send the pending keyboard report first
+                    if let Some(pending) = coalescer.flush(Instant::now()) {
+                        write_keyboard_report_to_host(&pending, keybooard_hid_writer).await;
+                    }
                     write_other_report_to_host(report, report_type, other_hid_writer).await;
                 }
+                #[cfg(feature = "gamepad")]
+                KeyboardReportMessage::GamepadReport(report) => {
+                    if let Err(e) = gamepad_hid_writer.write_serialize(&report).await {
+                        error!("Send gamepad report error: {:?}", e);
+                    }
+                }
             }
         }
     }
 }
 
+pub(crate) async fn write_keyboard_report_to_host<W: HidWriterWrapper>(
+    report: &KeyboardReport,
+    keyboard_hid_writer: &mut W,
+) {
+    if let Err(e) = keyboard_hid_writer.write_serialize(report).await {
+        error!("Send keyboard report error: {:?}", e);
+    }
+}
+
 pub(crate) async fn write_other_report_to_host<W: HidWriterWrapper>(
     report: CompositeReport,
     report_type: CompositeReportType,
@@ -122,7 +318,7 @@ pub(crate) struct Keyboard<'a, const ROW: usize, const COL: usize, const NUM_LAY
 
     /// Report Sender
     pub(crate) sender:
-        &'a Sender<'a, CriticalSectionRawMutex, KeyboardReportMessage, REPORT_CHANNEL_SIZE>,
+        Sender<'a, CriticalSectionRawMutex, KeyboardReportMessage, REPORT_CHANNEL_SIZE>,
 
     /// Unprocessed events
     unprocessed_events: Vec<KeyEvent, 16>,
@@ -136,9 +332,60 @@ pub(crate) struct Keyboard<'a, const ROW: usize, const COL: usize, const NUM_LAY
     /// Record whether the keyboard is in hold-after-tap state
     hold_after_tap: [Option<KeyEvent>; 6],
 
+    /// Currently held repeating action
+    repeating: Option<RepeatingAction>,
+
+    /// Key lock state
+    key_lock: KeyLockState,
+
+    /// State of `Action::LayerMomentaryOrToggle`
+    momentary_or_toggle: MomentaryOrToggleState,
+
+    /// Double tap state of the modifiers in `double_tap_lock`
+    double_tap_lock: DoubleTapLockState,
+
+    /// Consumer and system control keys whose release is sent again after `consumer_max_hold`
+    other_key_guard: OtherKeyReleaseGuard,
+    consumer_max_hold: Option<Duration>,
+
+    /// Filter of too fast presses of the same key
+    press_interval: PressIntervalFilter,
+
+    /// Held real modifiers and the tap-hold keys which act as hold because of them
+    modifier_chord: ModifierChordState,
+
+    /// Game mode state, tap-hold is bypassed in game mode
+    game_mode: GameModeState,
+
+    /// Global key remaps set by magic keycodes
+    magic: MagicState,
+
+    /// Remapped bits which are registered for each modifier bit, so that a held modifier is released
+    /// as it's registered, even if the magic state is changed during the hold
+    registered_modifiers: [u8; 8],
+
+    /// Autocorrect state
+    #[cfg(feature = "autocorrect")]
+    autocorrect: Autocorrect,
+
     /// Options for configurable action behavior
     behavior: BehaviorConfig,
 
+    /// Transport which the keyboard is currently running on
+    transport: Option<ConnectionType>,
+
+    /// The keyboard is (re)connected by `set_transport`, the on-connect macro is started in the next `run`
+    connect_macro_armed: bool,
+
+    /// On-connect macro waiting to be run
+    pending_connect_macro: Option<PendingConnectMacro>,
+
+    /// Time of the last run of the on-connect macro
+    last_connect_macro: Option<Instant>,
+
+    /// Active layer which is sent to the layer indicator, (layer, whether it's the default layer)
+    indicated_layer: Option<(u8, bool)>,
+
     /// One shot modifier state
     osm_state: OneShotState<ModifierCombination>,
 
@@ -157,11 +404,14 @@ pub(crate) struct Keyboard<'a, const ROW: usize, const COL: usize, const NUM_LAY
     /// Via report
     via_report: ViaReport,
 
+    /// Gamepad report
+    #[cfg(feature = "gamepad")]
+    gamepad_report: GamepadReport,
+
     /// Mouse key is different from other keyboard keys, it should be sent continuously while the key is pressed.
-    /// `last_mouse_tick` tracks at most 8 mouse keys, with its recent state.
-    /// It can be used to control the mouse report rate and release mouse key properly.
-    /// The key is mouse keycode, the value is the last action and its timestamp.
-    last_mouse_tick: FnvIndexMap<KeyCode, (bool, Instant), 4>,
+    /// `last_mouse_tick` tracks at most 4 held mouse keys, the value is the time of the last report of the key.
+    /// The next report is sent `MOUSE_KEY_INTERVAL` after the last one, until the key is released.
+    last_mouse_tick: FnvIndexMap<KeyCode, Instant, 4>,
 
     /// The current distance of mouse key moving
     mouse_key_move_delta: i8,
@@ -173,12 +423,16 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
 {
     pub(crate) fn new(
         keymap: &'a RefCell<KeyMap<'a, ROW, COL, NUM_LAYER>>,
-        sender: &'a Sender<'a, CriticalSectionRawMutex, KeyboardReportMessage, REPORT_CHANNEL_SIZE>,
+        sender: &Sender<'a, CriticalSectionRawMutex, KeyboardReportMessage, REPORT_CHANNEL_SIZE>,
         behavior: BehaviorConfig,
     ) -> Self {
+        for &(layer, modifiers) in behavior.layer_base_modifiers.iter() {
+            keymap.borrow_mut().set_base_modifiers(layer, modifiers);
+        }
+
         Keyboard {
             keymap,
-            sender,
+            sender: *sender,
             timer: [[None; ROW]; COL],
             last_release: (
                 KeyEvent {
@@ -190,7 +444,25 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
                 None,
             ),
             hold_after_tap: Default::default(),
+            repeating: None,
+            key_lock: KeyLockState::default(),
+            momentary_or_toggle: MomentaryOrToggleState::default(),
+            double_tap_lock: DoubleTapLockState::default(),
+            other_key_guard: OtherKeyReleaseGuard::default(),
+            consumer_max_hold: HostConfig::default().consumer_max_hold,
+            press_interval: PressIntervalFilter::default(),
+            modifier_chord: ModifierChordState::default(),
+            game_mode: GameModeState::default(),
+            magic: MagicState::load(behavior.magic),
+            registered_modifiers: [0; 8],
+            #[cfg(feature = "autocorrect")]
+            autocorrect: Autocorrect::new(behavior.autocorrect.words),
             behavior,
+            transport: None,
+            connect_macro_armed: false,
+            pending_connect_macro: None,
+            last_connect_macro: None,
+            indicated_layer: None,
             osm_state: OneShotState::default(),
             osl_state: OneShotState::default(),
             unprocessed_events: Vec::new(),
@@ -206,16 +478,42 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
                 input_data: [0; 32],
                 output_data: [0; 32],
             },
+            #[cfg(feature = "gamepad")]
+            gamepad_report: GamepadReport::default(),
             last_mouse_tick: FnvIndexMap::new(),
             mouse_key_move_delta: 8,
             mouse_wheel_move_delta: 1,
         }
     }
 
+    /// Set the transport which the keyboard is running on.
+    ///
+    /// When the transport changes, the default layer configured for the new transport is applied.
+    /// Reconnecting over the same transport keeps the current default layer, so that a default layer
+    /// toggled by the user isn't overwritten.
+    ///
+    /// It's called before `run` on every connection, which also arms the on-connect macro.
+    pub(crate) fn set_transport(&mut self, transport: ConnectionType) {
+        self.connect_macro_armed = true;
+        if self.transport == Some(transport) {
+            return;
+        }
+        self.transport = Some(transport);
+        let default_layer = match transport {
+            ConnectionType::Usb => self.behavior.default_layer_usb,
+            ConnectionType::Ble => self.behavior.default_layer_ble,
+        };
+        if let Some(layer) = default_layer {
+            debug!("Set default layer to {} for new transport", layer);
+            self.keymap.borrow_mut().set_default_layer(layer);
+        }
+    }
+
     pub(crate) async fn send_keyboard_report(&mut self) {
         self.sender
             .send(KeyboardReportMessage::KeyboardReport(self.report))
             .await;
+        record_report();
         // Yield once after sending the report to channel
         yield_now().await;
     }
@@ -228,7 +526,6 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
                 CompositeReportType::System,
             ))
             .await;
-        self.other_report.system_usage_id = 0;
         yield_now().await;
     }
 
@@ -240,7 +537,6 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
                 CompositeReportType::Media,
             ))
             .await;
-        self.other_report.media_usage_id = 0;
         yield_now().await;
     }
 
@@ -256,13 +552,62 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
         yield_now().await;
     }
 
+    /// Send gamepad report
+    #[cfg(feature = "gamepad")]
+    pub(crate) async fn send_gamepad_report(&mut self) {
+        self.sender
+            .send(KeyboardReportMessage::GamepadReport(self.gamepad_report))
+            .await;
+        yield_now().await;
+    }
+
     /// Main keyboard task, it receives input devices result, processes keys.
     /// The report is sent to communication task via `KEYBOARD_REPORT_CHANNEL`, and finally sent to the host
     /// TODO: make keyboard an `InputProcessor`
     pub(crate) async fn run(&mut self) {
         KEYBOARD_STATE.store(true, core::sync::atomic::Ordering::Release);
+        self.start_connect_macro();
         loop {
-            let key_event = KEY_EVENT_CHANNEL.receive().await;
+            self.update_mod_layers();
+            self.update_layer_indicator();
+
+            // The on-connect macro waits for the host without blocking the key processing
+            let connect_macro_deadline = self.poll_connect_macro().await;
+
+            // Wait for the key event, or the earliest of the deadlines
+            let deadline = [self.next_timer_deadline(), connect_macro_deadline]
+                .into_iter()
+                .flatten()
+                .min();
+            let result = select4(
+                self.next_key_event(),
+                LAYER_REQUEST_CHANNEL.receive(),
+                KEY_TAP_CHANNEL.receive(),
+                Timer::at(deadline.unwrap_or(Instant::MAX)),
+            )
+            .await;
+            let key_event = match result {
+                Either4::First(e) => e,
+                Either4::Second(request) => {
+                    trace_event!("layer request", request);
+                    self.process_layer_request(request);
+                    continue;
+                }
+                Either4::Third(key) => {
+                    self.process_key_tap_request(key).await;
+                    continue;
+                }
+                Either4::Fourth(_) => {
+                    let now = Instant::now();
+                    // The on-connect macro is checked at the beginning of the loop
+                    if !connect_macro_deadline.is_some_and(|t| t <= now) {
+                        self.process_timer(now).await;
+                    }
+                    continue;
+                }
+            };
+
+            trace_event!("key event", key_event);
 
             // Process the key change
             self.process_key_change(key_event).await;
@@ -280,21 +625,270 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
         }
     }
 
+    /// The earliest deadline of the timed behaviors: idle layer reset, held repeating action,
+    /// release guard of consumer and system control keys, and held mouse keys
+    fn next_timer_deadline(&self) -> Option<Instant> {
+        // If idle layer reset is enabled, wait for the key event or the idle timeout
+        let idle_deadline = self
+            .behavior
+            .idle_layer_reset
+            .and_then(|timeout| self.keymap.borrow().idle_reset_deadline(timeout));
+        [
+            idle_deadline,
+            self.repeating.map(|r| r.next),
+            self.guard_deadline(),
+            self.mouse_deadline(),
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+    }
+
+    /// If a consumer or system control key is pressed, the time when the release guard is checked
+    fn guard_deadline(&self) -> Option<Instant> {
+        self.consumer_max_hold
+            .and_then(|max_hold| self.other_key_guard.deadline(max_hold))
+    }
+
+    /// Held mouse keys are reported continuously, the time of the next report
+    fn mouse_deadline(&self) -> Option<Instant> {
+        self.last_mouse_tick
+            .values()
+            .min()
+            .map(|&t| t + MOUSE_KEY_INTERVAL)
+    }
+
+    /// Process the timed behavior whose deadline is reached at `now`
+    async fn process_timer(&mut self, now: Instant) {
+        if self.mouse_deadline().is_some_and(|t| t <= now) {
+            self.tick_mouse_keys(now).await;
+        } else if self.repeating.is_some_and(|r| r.next <= now) {
+            self.repeat_action().await;
+        } else if self.guard_deadline().is_some_and(|t| t <= now) {
+            self.release_stuck_other_keys(now).await;
+        } else {
+            self.keymap.borrow_mut().reset_idle_layers();
+        }
+    }
+
+    /// Receive the next key event from input devices.
+    ///
+    /// Every key event from input devices passes here exactly once, including the events consumed by the tap-hold
+    /// and one shot processing, so the position remap and the filters of physical key events are applied here.
+    /// Events which are re-queued to `unprocessed_events` have been received already, they must not pass here again.
+    async fn next_key_event(&mut self) -> KeyEvent {
+        loop {
+            let key_event = KEY_EVENT_CHANNEL.receive().await;
+            // Remapped positions are applied before everything else, as if the key were wired to the target position
+            let key_event = remap_position(&self.behavior.position_remap, key_event);
+
+            // Suppress mechanical double actuation of the same key
+            if let Some(min_interval) = self.behavior.per_key_min_interval {
+                if !self
+                    .press_interval
+                    .filter(key_event, Instant::now(), min_interval)
+                {
+                    continue;
+                }
+            }
+
+            // Locked keys ignore the physical release, and are released when pressed again
+            match self.key_lock.filter(key_event) {
+                Some(e) => return e,
+                None => continue,
+            }
+        }
+    }
+
+    /// Report the held mouse keys whose next report is due
+    async fn tick_mouse_keys(&mut self, now: Instant) {
+        let due: Vec<KeyCode, 4> = self
+            .last_mouse_tick
+            .iter()
+            .filter(|(_, &t)| t + MOUSE_KEY_INTERVAL <= now)
+            .map(|(&k, _)| k)
+            .collect();
+        for key in due {
+            let key_event = KeyEvent {
+                row: u8::MAX,
+                col: u8::MAX,
+                pressed: true,
+            };
+            self.process_action_mouse(key, key_event).await;
+        }
+    }
+
+    /// Set how long a consumer or system control key is pressed before its release is sent again, see `HostConfig::consumer_max_hold`
+    pub(crate) fn set_consumer_max_hold(&mut self, max_hold: Option<Duration>) {
+        self.consumer_max_hold = max_hold;
+    }
+
+    /// Send the release of consumer and system control keys again, in case that the host missed it
+    async fn release_stuck_other_keys(&mut self, now: Instant) {
+        let max_hold = match self.consumer_max_hold {
+            Some(max_hold) => max_hold,
+            None => return,
+        };
+        let (media, system) = self.other_key_guard.expire(
+            now,
+            max_hold,
+            self.other_report.media_usage_id != 0,
+            self.other_report.system_usage_id != 0,
+        );
+        if media {
+            debug!("Send consumer key release again");
+            self.send_media_report().await;
+        }
+        if system {
+            debug!("Send system control key release again");
+            self.send_system_control_report().await;
+        }
+    }
+
+    /// Activate or deactivate the layers which follow the held modifiers
+    fn update_mod_layers(&mut self) {
+        if self.behavior.mod_layers.is_empty() {
+            return;
+        }
+        let mut keymap = self.keymap.borrow_mut();
+        keymap.update_mod_layers(&self.behavior.mod_layers, self.report.modifier);
+        if let Some(ref tri_layer) = self.behavior.tri_layer {
+            keymap.update_tri_layer(tri_layer);
+        }
+    }
+
+    /// Send the active layer to the layer indicator if it's changed
+    fn update_layer_indicator(&mut self) {
+        let keymap = self.keymap.borrow();
+        let layer = keymap.get_activated_layer();
+        let indicated = (layer, layer == keymap.get_default_layer());
+        if self.indicated_layer != Some(indicated) {
+            self.indicated_layer = Some(indicated);
+            LAYER_INDICATOR_SIGNAL.signal(indicated);
+        }
+    }
+
+    /// Start waiting for the host to run the on-connect macro, if the keyboard is (re)connected by `set_transport`.
+    ///
+    /// `run` without a connection, e.g. when the keyboard is waiting for a profile switch, doesn't start the macro.
+    /// A reconnection within `CONNECT_MACRO_GUARD` after the last run doesn't trigger the macro again,
+    /// so that it isn't typed repeatedly on a flaky connection.
+    fn start_connect_macro(&mut self) {
+        let armed = core::mem::take(&mut self.connect_macro_armed);
+        self.pending_connect_macro = None;
+        if !armed
+            || self.behavior.on_connect_macro.is_none()
+            || self
+                .last_connect_macro
+                .is_some_and(|t| t.elapsed() < CONNECT_MACRO_GUARD)
+        {
+            return;
+        }
+        self.pending_connect_macro = Some(PendingConnectMacro {
+            start: Instant::now(),
+            connected: None,
+        });
+    }
+
+    /// Check the pending on-connect macro, and run it when it's due.
+    ///
+    /// Returns the time of the next check, `None` if there's no pending on-connect macro.
+    async fn poll_connect_macro(&mut self) -> Option<Instant> {
+        let mut pending = self.pending_connect_macro?;
+        let now = Instant::now();
+        let connected = match pending.connected {
+            Some(t) => t,
+            // USB is configured by the host a while after the keyboard starts
+            None if CONNECTION_STATE.load(core::sync::atomic::Ordering::Acquire) => now,
+            None if now.duration_since(pending.start) >= CONNECT_MACRO_WAIT => {
+                warn!("Host isn't connected, skip the on-connect macro");
+                self.pending_connect_macro = None;
+                return None;
+            }
+            None => return Some(now + CONNECT_MACRO_POLL),
+        };
+        if now < connected + CONNECT_MACRO_DELAY {
+            pending.connected = Some(connected);
+            self.pending_connect_macro = Some(pending);
+            return Some(connected + CONNECT_MACRO_DELAY);
+        }
+
+        self.pending_connect_macro = None;
+        let macro_idx = self.behavior.on_connect_macro?;
+        debug!("Run on-connect macro {}", macro_idx);
+        let key_event = KeyEvent {
+            row: u8::MAX,
+            col: u8::MAX,
+            pressed: false,
+        };
+        self.execute_macro(macro_idx, key_event).await;
+        self.last_connect_macro = Some(Instant::now());
+        None
+    }
+
+    /// Tap a key requested by input devices or processors.
+    ///
+    /// The key isn't bound to a matrix position, so a virtual position out of the matrix is used.
+    async fn process_key_tap_request(&mut self, key: KeyCode) {
+        debug!("Key tap request: {:?}", key);
+        let mut key_event = KeyEvent {
+            row: u8::MAX,
+            col: u8::MAX,
+            pressed: true,
+        };
+        self.process_action_keycode(key, key_event).await;
+        Timer::after_millis(10).await;
+        key_event.pressed = false;
+        self.process_action_keycode(key, key_event).await;
+    }
+
+    /// Apply a layer request from input devices or processors
+    fn process_layer_request(&mut self, request: LayerRequest) {
+        debug!("Layer request: {:?}", request);
+        match request {
+            LayerRequest::Activate(layer_num) => self.keymap.borrow_mut().activate_layer(layer_num),
+            LayerRequest::Deactivate(layer_num) => {
+                self.keymap.borrow_mut().deactivate_layer(layer_num)
+            }
+            LayerRequest::Toggle(layer_num) => self.keymap.borrow_mut().toggle_layer(layer_num),
+        }
+
+        // Tri Layer
+        if let Some(ref tri_layer) = self.behavior.tri_layer {
+            self.keymap.borrow_mut().update_tri_layer(tri_layer);
+        }
+    }
+
     /// Process key changes at (row, col)
     async fn process_key_change(&mut self, key_event: KeyEvent) {
         // Matrix should process key pressed event first, record the timestamp of key changes
         if key_event.pressed {
             self.timer[key_event.col as usize][key_event.row as usize] = Some(Instant::now());
+            self.momentary_or_toggle.interrupt(key_event);
         }
 
         // Process key
-        let action = self
+        let mut action = self
             .keymap
             .borrow_mut()
             .get_action_with_layer_cache(key_event);
+        // Deliberate modifier chords aren't interfered by the tap-hold resolution
+        if self.behavior.tap_hold.disable_during_modifier {
+            action = self.modifier_chord.resolve(key_event, action);
+        }
+        // In game mode, all tap-hold keys act as their tap action
+        action = self.game_mode.resolve(key_event, action);
+        let double_tap_output = self.update_double_tap_lock(action, key_event);
         match action {
             KeyAction::No | KeyAction::Transparent => (),
-            KeyAction::Single(a) => self.process_key_action_normal(a, key_event).await,
+            KeyAction::Single(a) => match (a, self.ble_consumer_repeat()) {
+                // Some BLE hosts don't auto-repeat consumer keys, repeat them in the firmware
+                (Action::Key(key), Some(interval)) if key.is_consumer() => {
+                    self.process_key_action_repeating(a, interval, key_event)
+                        .await
+                }
+                _ => self.process_key_action_normal(a, key_event).await,
+            },
             KeyAction::WithModifier(a, m) => {
                 self.process_key_action_with_modifier(a, m, key_event).await
             }
@@ -317,6 +911,18 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
                 self.process_key_action_tap_hold(tap_action, modifier_action, key_event)
                     .await;
             }
+            KeyAction::Repeating(a, interval) => {
+                let interval = Duration::from_millis(interval as u64);
+                self.process_key_action_repeating(a, interval, key_event)
+                    .await
+            }
+            KeyAction::OnRelease(a) => self.process_key_action_on_release(a, key_event).await,
+        }
+
+        // The modifier is released normally before its double tap output is tapped
+        if let Some(output) = double_tap_output {
+            debug!("Double tap lock: {:?}", output);
+            self.process_key_tap_request(output).await;
         }
 
         // Record release of current key, which will be used in tap/hold processing
@@ -338,6 +944,52 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
         }
     }
 
+    /// Track double taps of the modifiers in `double_tap_lock`.
+    ///
+    /// Returns the output key to be tapped when a double tap is completed.
+    fn update_double_tap_lock(
+        &mut self,
+        action: KeyAction,
+        key_event: KeyEvent,
+    ) -> Option<KeyCode> {
+        if self.behavior.double_tap_lock.is_empty() {
+            return None;
+        }
+        let lock = match action {
+            KeyAction::Single(Action::Key(key)) => self
+                .behavior
+                .double_tap_lock
+                .iter()
+                .find(|&&(modifier, _)| modifier == key)
+                .copied(),
+            _ => None,
+        };
+        match lock {
+            Some((modifier, output)) => {
+                let now = Instant::now();
+                if key_event.pressed {
+                    self.double_tap_lock.press(modifier, now);
+                    None
+                } else if self.double_tap_lock.release(
+                    modifier,
+                    now,
+                    self.behavior.tap_hold.hold_timeout,
+                    self.behavior.double_tap.timeout,
+                ) {
+                    Some(output)
+                } else {
+                    None
+                }
+            }
+            None => {
+                if key_event.pressed {
+                    self.double_tap_lock.interrupt();
+                }
+                None
+            }
+        }
+    }
+
     async fn update_osm(&mut self, key_event: KeyEvent) {
         match self.osm_state {
             OneShotState::Initial(m) => self.osm_state = OneShotState::Held(m),
@@ -402,6 +1054,26 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
                     self.keymap.borrow_mut().activate_layer(layer_num);
                 }
             }
+            Action::StickyLayer(layer_num) => {
+                if key_event.pressed {
+                    self.keymap.borrow_mut().activate_sticky_layer(layer_num);
+                }
+            }
+            Action::LayerMomentaryOrToggle(layer_num) => {
+                let now = Instant::now();
+                if key_event.pressed {
+                    self.momentary_or_toggle.press(key_event, layer_num, now);
+                    self.keymap.borrow_mut().activate_layer(layer_num);
+                } else if !self.momentary_or_toggle.release(
+                    key_event,
+                    layer_num,
+                    now,
+                    self.behavior.tap_hold.hold_timeout,
+                    self.behavior.double_tap.timeout,
+                ) {
+                    self.keymap.borrow_mut().deactivate_layer(layer_num);
+                }
+            }
             Action::DefaultLayer(layer_num) => {
                 // Set the default layer
                 self.keymap.borrow_mut().set_default_layer(layer_num);
@@ -414,6 +1086,39 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
 
                 self.update_osl(key_event);
             }
+            Action::KeyLock => {
+                if key_event.pressed {
+                    self.key_lock.toggle_armed();
+                }
+            }
+            Action::GameModeToggle => {
+                if key_event.pressed {
+                    self.game_mode.enabled = !self.game_mode.enabled;
+                    info!("Game mode: {}", self.game_mode.enabled);
+                    GAME_MODE_SIGNAL.signal(self.game_mode.enabled);
+                }
+            }
+            #[cfg(feature = "gamepad")]
+            Action::GamepadButton(button) => {
+                if button < 32 {
+                    if key_event.pressed {
+                        self.gamepad_report.buttons |= 1 << button;
+                    } else {
+                        self.gamepad_report.buttons &= !(1 << button);
+                    }
+                    self.send_gamepad_report().await;
+                }
+            }
+            #[cfg(feature = "gamepad")]
+            Action::GamepadAxis(axis, positive) => {
+                let value = match (key_event.pressed, positive) {
+                    (false, _) => 0,
+                    (true, true) => i8::MAX,
+                    (true, false) => -i8::MAX,
+                };
+                self.gamepad_report.set_axis(axis, value);
+                self.send_gamepad_report().await;
+            }
         }
     }
 
@@ -465,6 +1170,62 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
         }
     }
 
+    /// OnRelease action, nothing happens when the key is pressed, tap the action when the key is released.
+    async fn process_key_action_on_release(&mut self, action: Action, key_event: KeyEvent) {
+        if !key_event.pressed {
+            let key_event = KeyEvent {
+                pressed: true,
+                ..key_event
+            };
+            self.process_key_action_tap(action, key_event).await;
+        }
+    }
+
+    /// Repeating action, tap the action when the key is pressed, then tap it again at every interval until the key is released.
+    ///
+    /// The repeat is driven by the main loop, so other keys are processed normally while the key is held.
+    async fn process_key_action_repeating(
+        &mut self,
+        action: Action,
+        interval: Duration,
+        key_event: KeyEvent,
+    ) {
+        if key_event.pressed {
+            self.process_key_action_tap(action, key_event).await;
+            // At least 10ms, which is the time of a tap
+            let interval = interval.max(Duration::from_millis(10));
+            self.repeating = Some(RepeatingAction {
+                key_event,
+                action,
+                interval,
+                next: Instant::now() + interval,
+            });
+        } else if self
+            .repeating
+            .is_some_and(|r| r.key_event.row == key_event.row && r.key_event.col == key_event.col)
+        {
+            self.repeating = None;
+        }
+    }
+
+    /// Interval of the firmware-side repeat of held consumer keys, available only over BLE
+    fn ble_consumer_repeat(&self) -> Option<Duration> {
+        match self.transport {
+            Some(ConnectionType::Ble) => self.behavior.ble_consumer_repeat,
+            _ => None,
+        }
+    }
+
+    /// Trigger the held repeating action once, and schedule the next repeat
+    async fn repeat_action(&mut self) {
+        if let Some(mut repeating) = self.repeating {
+            self.process_key_action_tap(repeating.action, repeating.key_event)
+                .await;
+            repeating.next = Instant::now() + repeating.interval;
+            self.repeating = Some(repeating);
+        }
+    }
+
     /// Process tap/hold action for home row mods(HRM)
     ///
     /// For HRMs, the "tap" action actually has higher priority, especially when typing fast.
@@ -524,13 +1285,22 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
             // Press
             self.timer[col][row] = Some(Instant::now());
 
+            // Eager hold: apply the hold modifier immediately, retract it if the key turns out to be a tap
+            let eager_hold =
+                self.behavior.tap_hold.eager_hold && matches!(hold_action, Action::Modifier(_));
+            if eager_hold {
+                self.process_key_action_normal(hold_action, key_event).await;
+            }
+
             let hold_timeout =
                 embassy_time::Timer::after_millis(self.behavior.tap_hold.hold_timeout.as_millis());
-            match select(hold_timeout, KEY_EVENT_CHANNEL.receive()).await {
+            match select(hold_timeout, self.next_key_event()).await {
                 embassy_futures::select::Either::First(_) => {
                     // Timeout, trigger hold
                     debug!("Hold timeout, got HOLD: {:?}, {:?}", hold_action, key_event);
-                    self.process_key_action_normal(hold_action, key_event).await;
+                    if !eager_hold {
+                        self.process_key_action_normal(hold_action, key_event).await;
+                    }
                 }
                 embassy_futures::select::Either::Second(e) => {
                     if e.row == key_event.row && e.col == key_event.col {
@@ -538,6 +1308,10 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
                         if !e.pressed {
                             let elapsed = self.timer[col][row].unwrap().elapsed().as_millis();
                             debug!("TAP action: {:?}, time elapsed: {}ms", tap_action, elapsed);
+                            if eager_hold {
+                                // Retract the eagerly applied hold modifier before sending the tap key
+                                self.process_key_action_normal(hold_action, e).await;
+                            }
                             self.process_key_action_tap(tap_action, key_event).await;
 
                             // Clear timer
@@ -549,13 +1323,21 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
                         self.unprocessed_events.push(e).ok();
                         if !e.pressed {
                             // we push the current tap/hold event again, the loop will process the release first, then re-process current tap/hold
+                            if eager_hold {
+                                // The tap/hold key is re-processed later, retract the hold modifier now
+                                let release = KeyEvent {
+                                    pressed: false,
+                                    ..key_event
+                                };
+                                self.process_key_action_normal(hold_action, release).await;
+                            }
                             self.unprocessed_events.push(key_event).ok();
                             return;
                         }
 
                         // Wait for key release, record all pressed keys during this
                         loop {
-                            let next_key_event = KEY_EVENT_CHANNEL.receive().await;
+                            let next_key_event = self.next_key_event().await;
                             self.unprocessed_events.push(next_key_event).ok();
                             if !next_key_event.pressed {
                                 break;
@@ -563,7 +1345,9 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
                         }
 
                         // Process hold action
-                        self.process_key_action_normal(hold_action, key_event).await;
+                        if !eager_hold {
+                            self.process_key_action_normal(hold_action, key_event).await;
+                        }
 
                         // All other unprocessed events will be processed later
                     }
@@ -591,9 +1375,12 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
                     "HOLD releasing: {:?}, {}, wait for `post_wait_time` for new releases",
                     hold_action, key_event.pressed
                 );
+                let wait_timeout = embassy_time::Timer::after_millis(
+                    self.behavior.tap_hold.post_wait_time.as_millis(),
+                );
                 let wait_release = async {
                     loop {
-                        let next_key_event = KEY_EVENT_CHANNEL.receive().await;
+                        let next_key_event = self.next_key_event().await;
                         if !next_key_event.pressed {
                             self.unprocessed_events.push(next_key_event).ok();
                         } else {
@@ -601,10 +1388,6 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
                         }
                     }
                 };
-
-                let wait_timeout = embassy_time::Timer::after_millis(
-                    self.behavior.tap_hold.post_wait_time.as_millis(),
-                );
                 match select(wait_timeout, wait_release).await {
                     embassy_futures::select::Either::First(_) => {
                         // Wait timeout, release the hold key finally
@@ -658,7 +1441,7 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
                     self.osm_state = OneShotState::Single(m);
 
                     let timeout = embassy_time::Timer::after(self.behavior.one_shot.timeout);
-                    match select(timeout, KEY_EVENT_CHANNEL.receive()).await {
+                    match select(timeout, self.next_key_event()).await {
                         embassy_futures::select::Either::First(_) => {
                             // Timeout, release modifier
                             self.process_key_action_normal(Action::Modifier(modifier), key_event)
@@ -688,28 +1471,26 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
     async fn process_action_osl(&mut self, layer_num: u8, key_event: KeyEvent) {
         // Update one shot state
         if key_event.pressed {
+            let (old, new) = self
+                .osl_state
+                .press_layer(layer_num, self.behavior.one_shot.cancel_on_repeat);
+
             // Deactivate old layer if any
-            if let Some(&l) = self.osl_state.value() {
+            if let Some(l) = old {
                 self.keymap.borrow_mut().deactivate_layer(l);
             }
 
-            // Update layer of one shot
-            self.osl_state = match self.osl_state {
-                OneShotState::None => OneShotState::Initial(layer_num),
-                OneShotState::Initial(_) => OneShotState::Initial(layer_num),
-                OneShotState::Single(_) => OneShotState::Single(layer_num),
-                OneShotState::Held(_) => OneShotState::Held(layer_num),
-            };
-
-            // Activate new layer
-            self.keymap.borrow_mut().activate_layer(layer_num);
+            // Activate new layer, it's None if the one shot layer is cancelled
+            if let Some(l) = new {
+                self.keymap.borrow_mut().activate_layer(l);
+            }
         } else {
             match self.osl_state {
                 OneShotState::Initial(l) | OneShotState::Single(l) => {
                     self.osl_state = OneShotState::Single(l);
 
                     let timeout = embassy_time::Timer::after(self.behavior.one_shot.timeout);
-                    match select(timeout, KEY_EVENT_CHANNEL.receive()).await {
+                    match select(timeout, self.next_key_event()).await {
                         embassy_futures::select::Either::First(_) => {
                             // Timeout, deactivate layer
                             self.keymap.borrow_mut().deactivate_layer(layer_num);
@@ -776,6 +1557,11 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
                 }
             }
         } else if key.is_basic() {
+            let key = self.magic.remap_keycode(key);
+            #[cfg(feature = "autocorrect")]
+            if key_event.pressed {
+                self.process_autocorrect(key, key_event).await;
+            }
             if key_event.pressed {
                 self.register_key(key, key_event);
             } else {
@@ -785,11 +1571,29 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
         } else if key.is_macro() {
             // Process macro
             self.process_action_macro(key, key_event).await;
+        } else if key.is_magic() {
+            if key_event.pressed {
+                self.process_action_magic(key);
+            }
         } else {
             warn!("Unsupported key: {:?}", key);
         }
     }
 
+    /// Process magic keycodes, which change the global key remaps.
+    fn process_action_magic(&mut self, key: KeyCode) {
+        if self.magic.process(key) {
+            info!("Magic state changed: {:#X}", self.magic.into_bits());
+            if FLASH_CHANNEL
+                .try_send(FlashOperationMessage::MagicState(self.magic.into_bits()))
+                .is_err()
+            {
+                record_dropped(StatsChannel::FlashOperation);
+                error!("Failed to save magic state, the storage task is busy");
+            }
+        }
+    }
+
     /// Process layer switch action.
     fn process_action_layer_switch(&mut self, layer_num: u8, key_event: KeyEvent) {
         // Change layer state only when the key's state is changed
@@ -801,13 +1605,17 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
     }
 
     /// Process consumer control action. Consumer control keys are keys in hid consumer page, such as media keys.
+    /// The consumer key is held until it's released, the report is kept when another consumer key is released.
     async fn process_action_consumer_control(&mut self, key: KeyCode, key_event: KeyEvent) {
         if key.is_consumer() {
-            self.other_report.media_usage_id = if key_event.pressed {
-                key.as_consumer_control_usage_id() as u16
-            } else {
-                0
-            };
+            let usage_id = key.as_consumer_control_usage_id() as u16;
+            if key_event.pressed {
+                self.other_report.media_usage_id = usage_id;
+                self.other_key_guard
+                    .press(CompositeReportType::Media, Instant::now());
+            } else if !self.other_report.release_media(usage_id) {
+                return;
+            }
 
             self.send_media_report().await;
         }
@@ -816,13 +1624,14 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
     /// Process system control action. System control keys are keys in system page, such as power key.
     async fn process_action_system_control(&mut self, key: KeyCode, key_event: KeyEvent) {
         if key.is_system() {
-            if key_event.pressed {
-                if let Some(system_key) = key.as_system_control_usage_id() {
+            if let Some(system_key) = key.as_system_control_usage_id() {
+                if key_event.pressed {
                     self.other_report.system_usage_id = system_key as u8;
-                    self.send_system_control_report().await;
+                    self.other_key_guard
+                        .press(CompositeReportType::System, Instant::now());
+                } else if !self.other_report.release_system(system_key as u8) {
+                    return;
                 }
-            } else {
-                self.other_report.system_usage_id = 0;
                 self.send_system_control_report().await;
             }
         }
@@ -831,37 +1640,36 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
     /// Process mouse key action.
     async fn process_action_mouse(&mut self, key: KeyCode, key_event: KeyEvent) {
         if key.is_mouse_key() {
-            // Check whether the key is held, or it's released within the time interval
-            if let Some((pressed, last_tick)) = self.last_mouse_tick.get(&key) {
-                if !pressed && last_tick.elapsed().as_millis() <= 30 {
-                    // The key is just released, ignore the key event, ues a slightly longer time interval
-                    self.last_mouse_tick.remove(&key);
-                    return;
-                }
-            }
             // Reference(qmk): https://github.com/qmk/qmk_firmware/blob/382c3bd0bd49fc0d53358f45477c48f5ae47f2ff/quantum/mousekey.c#L410
             // https://github.com/qmk/qmk_firmware/blob/fb598e7e617692be0bf562afaf3c852c8db1c349/quantum/action.c#L332
             if key_event.pressed {
+                let layer = self.keymap.borrow().get_activated_layer();
+                let (move_delta, wheel_delta) = mouse_key_delta(
+                    &self.behavior.layer_mouse,
+                    layer,
+                    self.mouse_key_move_delta,
+                    self.mouse_wheel_move_delta,
+                );
                 match key {
                     // TODO: Add accerated mode when pressing the mouse key
                     // https://github.com/qmk/qmk_firmware/blob/master/docs/feature_mouse_keys.md#accelerated-mode
                     KeyCode::MouseUp => {
-                        self.other_report.y = -self.mouse_key_move_delta;
+                        self.other_report.y = -move_delta;
                     }
                     KeyCode::MouseDown => {
-                        self.other_report.y = self.mouse_key_move_delta;
+                        self.other_report.y = move_delta;
                     }
                     KeyCode::MouseLeft => {
-                        self.other_report.x = -self.mouse_key_move_delta;
+                        self.other_report.x = -move_delta;
                     }
                     KeyCode::MouseRight => {
-                        self.other_report.x = self.mouse_key_move_delta;
+                        self.other_report.x = move_delta;
                     }
                     KeyCode::MouseWheelUp => {
-                        self.other_report.wheel = self.mouse_wheel_move_delta;
+                        self.other_report.wheel = wheel_delta;
                     }
                     KeyCode::MouseWheelDown => {
-                        self.other_report.wheel = -self.mouse_wheel_move_delta;
+                        self.other_report.wheel = -wheel_delta;
                     }
                     KeyCode::MouseBtn1 => self.other_report.buttons |= 0b1,
                     KeyCode::MouseBtn2 => self.other_report.buttons |= 0b10,
@@ -872,10 +1680,10 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
                     KeyCode::MouseBtn7 => self.other_report.buttons |= 0b1000000,
                     KeyCode::MouseBtn8 => self.other_report.buttons |= 0b10000000,
                     KeyCode::MouseWheelLeft => {
-                        self.other_report.pan = -self.mouse_wheel_move_delta;
+                        self.other_report.pan = -wheel_delta;
                     }
                     KeyCode::MouseWheelRight => {
-                        self.other_report.pan = self.mouse_wheel_move_delta;
+                        self.other_report.pan = wheel_delta;
                     }
                     KeyCode::MouseAccel0 => {}
                     KeyCode::MouseAccel1 => {}
@@ -909,21 +1717,13 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
             }
             self.send_mouse_report().await;
 
-            if let Err(_) = self
-                .last_mouse_tick
-                .insert(key, (key_event.pressed, Instant::now()))
-            {
-                error!("The buffer for last moust tick is full");
-            }
-
-            // Send the key event back to channel again, to keep processing the mouse key until release
+            // Keep reporting the mouse key until release, the next report is driven by the main loop
             if key_event.pressed {
-                // FIXME: The ideal approach is to spawn another task and send the event after 20ms.
-                // But it requires embassy-executor, which is not available for esp-idf-svc.
-                // So now we just block for 20ms for mouse keys.
-                // In the future, we're going to use esp-hal once it have good support for BLE
-                embassy_time::Timer::after_millis(20).await;
-                KEY_EVENT_CHANNEL.try_send(key_event).ok();
+                if self.last_mouse_tick.insert(key, Instant::now()).is_err() {
+                    error!("The buffer for last moust tick is full");
+                }
+            } else {
+                self.last_mouse_tick.remove(&key);
             }
         }
     }
@@ -936,69 +1736,118 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
 
         // Get macro index
         if let Some(macro_idx) = key.as_macro_index() {
-            if macro_idx as usize >= NUM_MACRO {
-                error!("Macro idx invalid: {}", macro_idx);
-                return;
-            }
-            // Read macro operations untill the end of the macro
-            let macro_idx = self.keymap.borrow().get_macro_start(macro_idx);
-            if let Some(macro_start_idx) = macro_idx {
-                let mut offset = 0;
-                loop {
-                    // First, get the next macro operation
-                    let (operation, new_offset) = self
-                        .keymap
-                        .borrow()
-                        .get_next_macro_operation(macro_start_idx, offset);
-                    // Execute the operation
-                    match operation {
-                        MacroOperation::Press(k) => {
-                            self.register_key(k, key_event);
-                        }
-                        MacroOperation::Release(k) => {
-                            self.unregister_key(k, key_event);
-                        }
-                        MacroOperation::Tap(k) => {
-                            self.register_key(k, key_event);
-                            self.send_keyboard_report().await;
-                            embassy_time::Timer::after_millis(2).await;
-                            self.unregister_key(k, key_event);
-                        }
-                        MacroOperation::Text(k, is_cap) => {
-                            if is_cap {
-                                // If it's a capital letter, send shift first
-                                self.register_modifier(KeyCode::LShift.as_modifier_bit());
-                                self.send_keyboard_report().await;
-                            }
-                            self.register_keycode(k, key_event);
-                            self.send_keyboard_report().await;
-
-                            self.unregister_keycode(k, key_event);
-                            if is_cap {
-                                self.send_keyboard_report().await;
-                                self.unregister_modifier(KeyCode::LShift.as_modifier_bit());
-                            }
-                        }
-                        MacroOperation::Delay(t) => {
-                            embassy_time::Timer::after_millis(t as u64).await;
-                        }
-                        MacroOperation::End => {
-                            self.send_keyboard_report().await;
-                            break;
-                        }
-                    };
-
-                    // Send the item in the macro sequence
-                    self.send_keyboard_report().await;
+            self.execute_macro(macro_idx, key_event).await;
+        }
+    }
 
-                    offset = new_offset;
-                    if offset > self.keymap.borrow().macro_cache.len() {
+    /// Execute the macro at given index
+    async fn execute_macro(&mut self, macro_idx: u8, key_event: KeyEvent) {
+        if macro_idx as usize >= NUM_MACRO {
+            error!("Macro idx invalid: {}", macro_idx);
+            return;
+        }
+        // Read macro operations untill the end of the macro
+        let macro_idx = self.keymap.borrow().get_macro_start(macro_idx);
+        if let Some(macro_start_idx) = macro_idx {
+            let mut offset = 0;
+            loop {
+                // First, get the next macro operation
+                let (operation, new_offset) = self
+                    .keymap
+                    .borrow()
+                    .get_next_macro_operation(macro_start_idx, offset);
+                // Execute the operation
+                match operation {
+                    MacroOperation::Press(k) => {
+                        self.register_key(k, key_event);
+                    }
+                    MacroOperation::Release(k) => {
+                        self.unregister_key(k, key_event);
+                    }
+                    MacroOperation::Tap(k) => {
+                        self.register_key(k, key_event);
+                        self.send_keyboard_report().await;
+                        embassy_time::Timer::after_millis(2).await;
+                        self.unregister_key(k, key_event);
+                    }
+                    MacroOperation::Text(c) => self.type_ascii(c, key_event).await,
+                    MacroOperation::Delay(t) => {
+                        embassy_time::Timer::after_millis(t as u64).await;
+                    }
+                    MacroOperation::End => {
+                        self.send_keyboard_report().await;
                         break;
                     }
+                };
+
+                // Send the item in the macro sequence
+                self.send_keyboard_report().await;
+
+                offset = new_offset;
+                if offset > self.keymap.borrow().macro_cache.len() {
+                    break;
                 }
-            } else {
-                error!("Macro not found");
             }
+        } else {
+            error!("Macro not found");
+        }
+    }
+
+    /// Type an ascii char according to the host layout.
+    ///
+    /// The last report of the char is NOT sent, it's left to the caller.
+    async fn type_ascii(&mut self, c: u8, key_event: KeyEvent) {
+        let host_layout = self.behavior.host_layout;
+        let (k, modifier) = host_layout.from_ascii(c);
+        if modifier != 0 {
+            // If the char requires modifiers(shift/altgr), send modifiers first
+            self.register_modifier(modifier);
+            self.send_keyboard_report().await;
+        }
+        self.register_keycode(k, key_event);
+        self.send_keyboard_report().await;
+
+        self.unregister_keycode(k, key_event);
+        if modifier != 0 {
+            self.send_keyboard_report().await;
+            self.unregister_modifier(modifier);
+        }
+
+        if host_layout.is_dead_key(c) {
+            // Tap space after a dead key to type the char itself
+            self.send_keyboard_report().await;
+            self.register_keycode(KeyCode::Space, key_event);
+            self.send_keyboard_report().await;
+            self.unregister_keycode(KeyCode::Space, key_event);
+        }
+    }
+
+    /// Check the pressed key against the autocorrect word list.
+    /// If the key ends a typo, erase the typo and type the correction before the key is sent.
+    #[cfg(feature = "autocorrect")]
+    async fn process_autocorrect(&mut self, key: KeyCode, key_event: KeyEvent) {
+        if let Some(correction) = self.autocorrect.process_key(key, self.report.modifier) {
+            debug!("Autocorrect: {}", correction.text);
+            // Modifiers of the current key, such as shift of `!`, shouldn't be applied to the correction
+            let modifier = self.report.modifier;
+            self.report.modifier = 0;
+            // Release the keys which are still held, otherwise the correction is typed while the last letters of the typo are down
+            if self.report.keycodes.iter().any(|&k| k != 0) {
+                self.report.keycodes = [0; 6];
+                self.registered_keys = Default::default();
+                self.send_keyboard_report().await;
+            }
+            for _ in 0..correction.backspaces {
+                self.register_keycode(KeyCode::Backspace, key_event);
+                self.send_keyboard_report().await;
+                self.unregister_keycode(KeyCode::Backspace, key_event);
+                self.send_keyboard_report().await;
+            }
+            for &c in correction.text.as_bytes() {
+                self.type_ascii(c, key_event).await;
+                self.send_keyboard_report().await;
+            }
+            self.report.modifier = modifier;
         }
     }
 
@@ -1072,11 +1921,344 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
 
     /// Register a modifier to be sent in hid report.
     fn register_modifier(&mut self, modifier_bit: u8) {
-        self.report.modifier |= modifier_bit;
+        for (i, registered) in self.registered_modifiers.iter_mut().enumerate() {
+            if modifier_bit & (1 << i) != 0 {
+                *registered = self.magic.remap_modifiers(1 << i);
+                self.report.modifier |= *registered;
+            }
+        }
     }
 
     /// Unregister a modifier from hid report.
     fn unregister_modifier(&mut self, modifier_bit: u8) {
-        self.report.modifier &= !modifier_bit;
+        for (i, registered) in self.registered_modifiers.iter_mut().enumerate() {
+            if modifier_bit & (1 << i) != 0 {
+                // Release the bits registered at press, the magic state might be changed since then
+                let released = match *registered {
+                    0 => self.magic.remap_released_modifiers(1 << i),
+                    bits => bits,
+                };
+                self.report.modifier &= !released;
+                *registered = 0;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use embassy_futures::block_on;
+
+    type ReportChannel =
+        Channel<CriticalSectionRawMutex, KeyboardReportMessage, REPORT_CHANNEL_SIZE>;
+
+    fn key_event(row: u8, col: u8, pressed: bool) -> KeyEvent {
+        KeyEvent { row, col, pressed }
+    }
+
+    /// (modifier, first keycode) of all keyboard reports which are sent to the channel
+    fn received_keys(channel: &ReportChannel) -> std::vec::Vec<(u8, u8)> {
+        let mut keys = std::vec::Vec::new();
+        while let Ok(message) = channel.try_receive() {
+            if let KeyboardReportMessage::KeyboardReport(r) = message {
+                keys.push((r.modifier, r.keycodes[0]));
+            }
+        }
+        keys
+    }
+
+    #[test]
+    fn test_on_release() {
+        block_on(async {
+            let mut layers = [[[
+                KeyAction::OnRelease(Action::Key(KeyCode::A)),
+                KeyAction::Single(Action::Key(KeyCode::B)),
+            ]]];
+            let keymap = RefCell::new(KeyMap::new(&mut layers).await);
+            let channel = ReportChannel::new();
+            let mut keyboard = Keyboard::new(&keymap, &channel.sender(), BehaviorConfig::default());
+
+            // Nothing is sent when the key is pressed
+            keyboard.process_key_change(key_event(0, 0, true)).await;
+            assert!(received_keys(&channel).is_empty());
+
+            // The key is tapped when it's released
+            keyboard.process_key_change(key_event(0, 0, false)).await;
+            assert_eq!(received_keys(&channel), [(0, KeyCode::A as u8), (0, 0)]);
+        });
+    }
+
+    #[cfg(feature = "autocorrect")]
+    #[test]
+    fn test_autocorrect_with_held_key() {
+        use crate::config::AutocorrectConfig;
+
+        block_on(async {
+            let mut layers = [[[
+                KeyAction::Single(Action::Key(KeyCode::T)),
+                KeyAction::Single(Action::Key(KeyCode::E)),
+                KeyAction::Single(Action::Key(KeyCode::H)),
+                KeyAction::Single(Action::Key(KeyCode::Space)),
+            ]]];
+            let keymap = RefCell::new(KeyMap::new(&mut layers).await);
+            let channel = ReportChannel::new();
+            let behavior = BehaviorConfig {
+                autocorrect: AutocorrectConfig {
+                    words: &[("teh", "the")],
+                },
+                ..Default::default()
+            };
+            let mut keyboard = Keyboard::new(&keymap, &channel.sender(), behavior);
+
+            for col in 0..2 {
+                keyboard.process_key_change(key_event(0, col, true)).await;
+                keyboard.process_key_change(key_event(0, col, false)).await;
+            }
+            // `h` is still held when space is pressed
+            keyboard.process_key_change(key_event(0, 2, true)).await;
+            received_keys(&channel);
+            keyboard.process_key_change(key_event(0, 3, true)).await;
+
+            let backspace = (0, KeyCode::Backspace as u8);
+            assert_eq!(
+                received_keys(&channel),
+                [
+                    // `h` is released before the correction
+                    (0, 0),
+                    backspace,
+                    (0, 0),
+                    backspace,
+                    (0, 0),
+                    backspace,
+                    (0, 0),
+                    (0, KeyCode::T as u8),
+                    (0, 0),
+                    (0, KeyCode::E as u8),
+                    (0, 0),
+                    (0, KeyCode::H as u8),
+                    (0, 0),
+                    (0, KeyCode::Space as u8),
+                ]
+            );
+
+            // Releasing `h` doesn't affect the space
+            keyboard.process_key_change(key_event(0, 2, false)).await;
+            assert_eq!(received_keys(&channel), [(0, KeyCode::Space as u8)]);
+        });
+    }
+
+    /// Usage ids of the received media reports
+    fn received_media(channel: &ReportChannel) -> std::vec::Vec<u16> {
+        let mut usages = std::vec::Vec::new();
+        while let Ok(message) = channel.try_receive() {
+            if let KeyboardReportMessage::CompositeReport(r, CompositeReportType::Media) = message {
+                usages.push(r.media_usage_id);
+            }
+        }
+        usages
+    }
+
+    #[test]
+    fn test_hold_consumer_on_encoder_switch() {
+        block_on(async {
+            // The encoder switch is mapped to the virtual position (0, 1)
+            let mut layers = [[[
+                KeyAction::Single(Action::Key(KeyCode::A)),
+                KeyAction::Single(Action::Key(KeyCode::MediaFastForward)),
+            ]]];
+            let keymap = RefCell::new(KeyMap::new(&mut layers).await);
+            let channel = ReportChannel::new();
+            let mut keyboard = Keyboard::new(&keymap, &channel.sender(), BehaviorConfig::default());
+
+            // Press the encoder switch, fast forward is held
+            keyboard.process_key_change(key_event(0, 1, true)).await;
+            assert_eq!(received_media(&channel), [0xB3]);
+            // Nothing is scheduled before the release guard, which keeps the held key as well
+            let deadline = keyboard.next_timer_deadline();
+            assert!(deadline.is_some());
+            assert_eq!(deadline, keyboard.guard_deadline());
+            keyboard.process_timer(deadline.unwrap()).await;
+            assert!(received_media(&channel).is_empty());
+
+            // Other keys don't affect the held consumer key
+            keyboard.process_key_change(key_event(0, 0, true)).await;
+            keyboard.process_key_change(key_event(0, 0, false)).await;
+            assert!(received_media(&channel).is_empty());
+
+            // Release the encoder switch
+            keyboard.process_key_change(key_event(0, 1, false)).await;
+            assert_eq!(received_media(&channel), [0]);
+        });
+    }
+
+    #[test]
+    fn test_magic_swap_with_held_modifier() {
+        block_on(async {
+            let mut layers = [[[
+                KeyAction::Single(Action::Key(KeyCode::LAlt)),
+                KeyAction::Single(Action::Key(KeyCode::MagicToggleAltGui)),
+            ]]];
+            let keymap = RefCell::new(KeyMap::new(&mut layers).await);
+            let channel = ReportChannel::new();
+            let mut keyboard = Keyboard::new(&keymap, &channel.sender(), BehaviorConfig::default());
+
+            // Hold LAlt, then swap Alt and GUI
+            keyboard.process_key_change(key_event(0, 0, true)).await;
+            assert_eq!(received_keys(&channel).last(), Some(&(1 << 2, 0)));
+            keyboard.process_key_change(key_event(0, 1, true)).await;
+            keyboard.process_key_change(key_event(0, 1, false)).await;
+            received_keys(&channel);
+
+            // The held LAlt is released, not the swapped LGUI
+            keyboard.process_key_change(key_event(0, 0, false)).await;
+            assert_eq!(received_keys(&channel).last(), Some(&(0, 0)));
+
+            // New presses are swapped
+            keyboard.process_key_change(key_event(0, 0, true)).await;
+            assert_eq!(received_keys(&channel).last(), Some(&(1 << 3, 0)));
+            keyboard.process_key_change(key_event(0, 0, false)).await;
+            assert_eq!(received_keys(&channel).last(), Some(&(0, 0)));
+        });
+    }
+
+    #[test]
+    fn test_game_mode() {
+        let shift = ModifierCombination::new_from(false, false, false, true, false);
+        let hrm = KeyAction::ModifierTapHold(Action::Key(KeyCode::F), shift);
+        let lt = KeyAction::LayerTapHold(Action::Key(KeyCode::Space), 1);
+        let mut state = GameModeState::default();
+
+        // A tap-hold key pressed in normal mode stays tap-hold after the game mode is turned on
+        assert!(matches!(
+            state.resolve(key_event(1, 3, true), hrm),
+            KeyAction::ModifierTapHold(_, _)
+        ));
+        state.enabled = true;
+        assert!(matches!(
+            state.resolve(key_event(1, 3, false), hrm),
+            KeyAction::ModifierTapHold(_, _)
+        ));
+
+        // In game mode, tap-hold keys act as their tap action
+        assert!(matches!(
+            state.resolve(key_event(3, 4, true), lt),
+            KeyAction::Single(Action::Key(KeyCode::Space))
+        ));
+        // The key is released as its tap action even if the game mode is turned off
+        state.enabled = false;
+        assert!(matches!(
+            state.resolve(key_event(3, 4, false), lt),
+            KeyAction::Single(Action::Key(KeyCode::Space))
+        ));
+        assert!(matches!(
+            state.resolve(key_event(3, 4, true), lt),
+            KeyAction::LayerTapHold(_, 1)
+        ));
+    }
+
+    #[test]
+    fn test_osl_cancel_on_repeat() {
+        let mut state = OneShotState::default();
+        assert_eq!(state.press_layer(1, true), (None, Some(1)));
+        // Released before any other key
+        state = OneShotState::Single(1);
+
+        // Tap the same key again, the one shot layer is cancelled
+        assert_eq!(state.press_layer(1, true), (Some(1), None));
+        assert!(state.value().is_none());
+
+        // Without `cancel_on_repeat`, the layer stays active
+        state = OneShotState::Single(1);
+        assert_eq!(state.press_layer(1, false), (Some(1), Some(1)));
+        assert_eq!(state.value(), Some(&1));
+    }
+
+    #[test]
+    fn test_osl_switch() {
+        let mut state = OneShotState::Single(1);
+
+        // Tap a different one shot layer key, the pending layer is switched
+        assert_eq!(state.press_layer(2, true), (Some(1), Some(2)));
+        assert!(matches!(state, OneShotState::Single(2)));
+
+        // The switched layer can be cancelled by its own key
+        assert_eq!(state.press_layer(2, true), (Some(2), None));
+        assert!(state.value().is_none());
+    }
+
+    #[test]
+    fn test_layer_mouse() {
+        let layer_mouse = [(
+            3,
+            LayerMouseConfig {
+                speed_percent: 50,
+                scroll_invert: true,
+            },
+        )];
+
+        // Default settings on other layers
+        assert_eq!(mouse_key_delta(&layer_mouse, 0, 8, 1), (8, 1));
+        // Slower cursor and natural scrolling while layer 3 is active
+        assert_eq!(mouse_key_delta(&layer_mouse, 3, 8, 1), (4, -1));
+        // Back to the defaults after the layer is turned off
+        assert_eq!(mouse_key_delta(&layer_mouse, 1, 8, 1), (8, 1));
+
+        // The cursor always moves, even with a tiny speed
+        let slow = [(
+            1,
+            LayerMouseConfig {
+                speed_percent: 1,
+                scroll_invert: false,
+            },
+        )];
+        assert_eq!(mouse_key_delta(&slow, 1, 8, 1), (1, 1));
+    }
+
+    #[test]
+    fn test_remap_position() {
+        let remap = [((0, 0), (2, 0)), ((2, 0), (0, 0)), ((1, 3), (1, 4))];
+
+        // Remapped positions, the pressed state is kept
+        let e = remap_position(&remap, key_event(0, 0, true));
+        assert_eq!((e.row, e.col, e.pressed), (2, 0, true));
+        let e = remap_position(&remap, key_event(2, 0, false));
+        assert_eq!((e.row, e.col, e.pressed), (0, 0, false));
+        // Remapping is applied once, it isn't chained
+        let e = remap_position(&remap, key_event(1, 3, true));
+        assert_eq!((e.row, e.col), (1, 4));
+        let e = remap_position(&remap, key_event(1, 4, true));
+        assert_eq!((e.row, e.col), (1, 4));
+        // Other positions are untouched
+        let e = remap_position(&remap, key_event(3, 2, true));
+        assert_eq!((e.row, e.col, e.pressed), (3, 2, true));
+    }
+
+    #[test]
+    fn test_remapped_tap_hold() {
+        block_on(async {
+            let shift = ModifierCombination::new_from(false, false, false, true, false);
+            let mut layers = [[[
+                KeyAction::No,
+                KeyAction::ModifierTapHold(Action::Key(KeyCode::A), shift),
+            ]]];
+            let keymap = RefCell::new(KeyMap::new(&mut layers).await);
+            let channel = ReportChannel::new();
+            let mut behavior = BehaviorConfig::default();
+            behavior.position_remap.push(((0, 0), (0, 1))).ok();
+            behavior.position_remap.push(((0, 1), (0, 0))).ok();
+            let mut keyboard = Keyboard::new(&keymap, &channel.sender(), behavior);
+
+            // Tap the physical key which is remapped to the tap-hold key
+            KEY_EVENT_CHANNEL.try_send(key_event(0, 0, true)).unwrap();
+            KEY_EVENT_CHANNEL.try_send(key_event(0, 0, false)).unwrap();
+            let e = keyboard.next_key_event().await;
+            assert_eq!((e.row, e.col, e.pressed), (0, 1, true));
+            // The release received while waiting for the tap-hold resolution is remapped as well
+            keyboard.process_key_change(e).await;
+            assert!(KEY_EVENT_CHANNEL.is_empty());
+            assert!(keyboard.unprocessed_events.is_empty());
+            assert_eq!(received_keys(&channel), [(0, KeyCode::A as u8), (0, 0)]);
+        });
     }
 }
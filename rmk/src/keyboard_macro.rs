@@ -1,3 +1,4 @@
+use crate::config::HostLayout;
 use crate::keycode::KeyCode;
 
 // Default macro space size
@@ -10,7 +11,234 @@ pub(crate) enum MacroOperation {
     Press(KeyCode),
     Release(KeyCode),
     Tap(KeyCode),
-    Text(KeyCode, bool),
+    /// An ascii char, which is converted to keycode according to the host layout
+    Text(u8),
     Delay(u16),
     End,
 }
+
+/// Modifier bit of left shift
+const SHIFT: u8 = 1 << 1;
+/// Modifier bit of right alt, aka AltGr
+const ALTGR: u8 = 1 << 6;
+
+impl HostLayout {
+    /// Convert an ascii char to the keycode and the modifier bits which type the char on the host
+    pub(crate) fn from_ascii(self, ascii: u8) -> (KeyCode, u8) {
+        let (keycode, modifier) = match self {
+            HostLayout::Us => {
+                let (keycode, is_caps) = KeyCode::from_ascii(ascii);
+                return (keycode, if is_caps { SHIFT } else { 0 });
+            }
+            HostLayout::Uk => uk_from_ascii(ascii),
+            HostLayout::De => de_from_ascii(ascii),
+            HostLayout::Fr => fr_from_ascii(ascii),
+        };
+
+        match (keycode, modifier) {
+            // Not in the layout specific table, fallback to US
+            (KeyCode::No, _) => {
+                let (keycode, is_caps) = KeyCode::from_ascii(ascii);
+                (keycode, if is_caps { SHIFT } else { 0 })
+            }
+            _ => (keycode, modifier),
+        }
+    }
+
+    /// Whether the char is typed by a dead key on the host.
+    /// A dead key should be followed by a space to type the char itself.
+    pub(crate) fn is_dead_key(self, ascii: u8) -> bool {
+        match self {
+            HostLayout::Us | HostLayout::Uk => false,
+            HostLayout::De => matches!(ascii, b'^' | b'`'),
+            HostLayout::Fr => matches!(ascii, b'`' | b'~'),
+        }
+    }
+}
+
+/// UK ISO layout, only the chars which differ from US are listed
+fn uk_from_ascii(ascii: u8) -> (KeyCode, u8) {
+    match ascii {
+        b'"' => (KeyCode::Kc2, SHIFT),
+        b'@' => (KeyCode::Quote, SHIFT),
+        b'#' => (KeyCode::NonusHash, 0),
+        b'~' => (KeyCode::NonusHash, SHIFT),
+        b'\\' => (KeyCode::NonusBackslash, 0),
+        b'|' => (KeyCode::NonusBackslash, SHIFT),
+        _ => (KeyCode::No, 0),
+    }
+}
+
+/// German QWERTZ layout, only the chars which differ from US are listed
+fn de_from_ascii(ascii: u8) -> (KeyCode, u8) {
+    match ascii {
+        b'y' => (KeyCode::Z, 0),
+        b'z' => (KeyCode::Y, 0),
+        b'Y' => (KeyCode::Z, SHIFT),
+        b'Z' => (KeyCode::Y, SHIFT),
+        b'"' => (KeyCode::Kc2, SHIFT),
+        b'&' => (KeyCode::Kc6, SHIFT),
+        b'/' => (KeyCode::Kc7, SHIFT),
+        b'(' => (KeyCode::Kc8, SHIFT),
+        b')' => (KeyCode::Kc9, SHIFT),
+        b'=' => (KeyCode::Kc0, SHIFT),
+        b'?' => (KeyCode::Minus, SHIFT),
+        b'\\' => (KeyCode::Minus, ALTGR),
+        b'`' => (KeyCode::Equal, SHIFT),
+        b'+' => (KeyCode::RightBracket, 0),
+        b'*' => (KeyCode::RightBracket, SHIFT),
+        b'~' => (KeyCode::RightBracket, ALTGR),
+        b'#' => (KeyCode::NonusHash, 0),
+        b'\'' => (KeyCode::NonusHash, SHIFT),
+        b'^' => (KeyCode::Grave, 0),
+        b'-' => (KeyCode::Slash, 0),
+        b'_' => (KeyCode::Slash, SHIFT),
+        b';' => (KeyCode::Comma, SHIFT),
+        b':' => (KeyCode::Dot, SHIFT),
+        b'<' => (KeyCode::NonusBackslash, 0),
+        b'>' => (KeyCode::NonusBackslash, SHIFT),
+        b'|' => (KeyCode::NonusBackslash, ALTGR),
+        b'@' => (KeyCode::Q, ALTGR),
+        b'{' => (KeyCode::Kc7, ALTGR),
+        b'[' => (KeyCode::Kc8, ALTGR),
+        b']' => (KeyCode::Kc9, ALTGR),
+        b'}' => (KeyCode::Kc0, ALTGR),
+        _ => (KeyCode::No, 0),
+    }
+}
+
+/// French AZERTY layout, only the chars which differ from US are listed
+fn fr_from_ascii(ascii: u8) -> (KeyCode, u8) {
+    match ascii {
+        b'a' => (KeyCode::Q, 0),
+        b'q' => (KeyCode::A, 0),
+        b'z' => (KeyCode::W, 0),
+        b'w' => (KeyCode::Z, 0),
+        b'm' => (KeyCode::Semicolon, 0),
+        b'A' => (KeyCode::Q, SHIFT),
+        b'Q' => (KeyCode::A, SHIFT),
+        b'Z' => (KeyCode::W, SHIFT),
+        b'W' => (KeyCode::Z, SHIFT),
+        b'M' => (KeyCode::Semicolon, SHIFT),
+        b'1' => (KeyCode::Kc1, SHIFT),
+        b'2' => (KeyCode::Kc2, SHIFT),
+        b'3' => (KeyCode::Kc3, SHIFT),
+        b'4' => (KeyCode::Kc4, SHIFT),
+        b'5' => (KeyCode::Kc5, SHIFT),
+        b'6' => (KeyCode::Kc6, SHIFT),
+        b'7' => (KeyCode::Kc7, SHIFT),
+        b'8' => (KeyCode::Kc8, SHIFT),
+        b'9' => (KeyCode::Kc9, SHIFT),
+        b'0' => (KeyCode::Kc0, SHIFT),
+        b'&' => (KeyCode::Kc1, 0),
+        b'"' => (KeyCode::Kc3, 0),
+        b'\'' => (KeyCode::Kc4, 0),
+        b'(' => (KeyCode::Kc5, 0),
+        b'-' => (KeyCode::Kc6, 0),
+        b'_' => (KeyCode::Kc8, 0),
+        b')' => (KeyCode::Minus, 0),
+        b'=' => (KeyCode::Equal, 0),
+        b'+' => (KeyCode::Equal, SHIFT),
+        b'~' => (KeyCode::Kc2, ALTGR),
+        b'#' => (KeyCode::Kc3, ALTGR),
+        b'{' => (KeyCode::Kc4, ALTGR),
+        b'[' => (KeyCode::Kc5, ALTGR),
+        b'|' => (KeyCode::Kc6, ALTGR),
+        b'`' => (KeyCode::Kc7, ALTGR),
+        b'\\' => (KeyCode::Kc8, ALTGR),
+        b'^' => (KeyCode::Kc9, ALTGR),
+        b'@' => (KeyCode::Kc0, ALTGR),
+        b']' => (KeyCode::Minus, ALTGR),
+        b'}' => (KeyCode::Equal, ALTGR),
+        b'$' => (KeyCode::RightBracket, 0),
+        b'*' => (KeyCode::NonusHash, 0),
+        b'%' => (KeyCode::Quote, SHIFT),
+        b',' => (KeyCode::M, 0),
+        b'?' => (KeyCode::M, SHIFT),
+        b';' => (KeyCode::Comma, 0),
+        b'.' => (KeyCode::Comma, SHIFT),
+        b':' => (KeyCode::Dot, 0),
+        b'/' => (KeyCode::Dot, SHIFT),
+        b'!' => (KeyCode::Slash, 0),
+        b'<' => (KeyCode::NonusBackslash, 0),
+        b'>' => (KeyCode::NonusBackslash, SHIFT),
+        _ => (KeyCode::No, 0),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_us_from_ascii() {
+        let us = HostLayout::Us;
+        assert_eq!(us.from_ascii(b'a'), (KeyCode::A, 0));
+        assert_eq!(us.from_ascii(b'A'), (KeyCode::A, SHIFT));
+        assert_eq!(us.from_ascii(b'\\'), (KeyCode::Backslash, 0));
+        assert_eq!(us.from_ascii(b'|'), (KeyCode::Backslash, SHIFT));
+        assert_eq!(us.from_ascii(b'/'), (KeyCode::Slash, 0));
+        assert_eq!(us.from_ascii(b'?'), (KeyCode::Slash, SHIFT));
+    }
+
+    #[test]
+    fn test_uk_from_ascii() {
+        let uk = HostLayout::Uk;
+        assert_eq!(uk.from_ascii(b'"'), (KeyCode::Kc2, SHIFT));
+        assert_eq!(uk.from_ascii(b'@'), (KeyCode::Quote, SHIFT));
+        assert_eq!(uk.from_ascii(b'#'), (KeyCode::NonusHash, 0));
+        assert_eq!(uk.from_ascii(b'~'), (KeyCode::NonusHash, SHIFT));
+        assert_eq!(uk.from_ascii(b'\\'), (KeyCode::NonusBackslash, 0));
+        assert_eq!(uk.from_ascii(b'|'), (KeyCode::NonusBackslash, SHIFT));
+        // Same as US
+        assert_eq!(uk.from_ascii(b'z'), (KeyCode::Z, 0));
+        assert_eq!(uk.from_ascii(b'?'), (KeyCode::Slash, SHIFT));
+    }
+
+    #[test]
+    fn test_de_from_ascii() {
+        let de = HostLayout::De;
+        assert_eq!(de.from_ascii(b'y'), (KeyCode::Z, 0));
+        assert_eq!(de.from_ascii(b'Z'), (KeyCode::Y, SHIFT));
+        assert_eq!(de.from_ascii(b'-'), (KeyCode::Slash, 0));
+        assert_eq!(de.from_ascii(b'?'), (KeyCode::Minus, SHIFT));
+        assert_eq!(de.from_ascii(b'@'), (KeyCode::Q, ALTGR));
+        assert_eq!(de.from_ascii(b'{'), (KeyCode::Kc7, ALTGR));
+        assert_eq!(de.from_ascii(b'|'), (KeyCode::NonusBackslash, ALTGR));
+        // Same as US
+        assert_eq!(de.from_ascii(b'a'), (KeyCode::A, 0));
+        assert_eq!(de.from_ascii(b'!'), (KeyCode::Kc1, SHIFT));
+        assert_eq!(de.from_ascii(b'1'), (KeyCode::Kc1, 0));
+    }
+
+    #[test]
+    fn test_fr_from_ascii() {
+        let fr = HostLayout::Fr;
+        assert_eq!(fr.from_ascii(b'a'), (KeyCode::Q, 0));
+        assert_eq!(fr.from_ascii(b'Q'), (KeyCode::A, SHIFT));
+        assert_eq!(fr.from_ascii(b'm'), (KeyCode::Semicolon, 0));
+        // Digits are shifted on AZERTY
+        assert_eq!(fr.from_ascii(b'1'), (KeyCode::Kc1, SHIFT));
+        assert_eq!(fr.from_ascii(b'&'), (KeyCode::Kc1, 0));
+        assert_eq!(fr.from_ascii(b'@'), (KeyCode::Kc0, ALTGR));
+        assert_eq!(fr.from_ascii(b'.'), (KeyCode::Comma, SHIFT));
+        // Same as US
+        assert_eq!(fr.from_ascii(b'b'), (KeyCode::B, 0));
+        assert_eq!(fr.from_ascii(b' '), (KeyCode::Space, 0));
+    }
+
+    #[test]
+    fn test_is_dead_key() {
+        for ascii in [b'^', b'`', b'~', b'a', b'"'] {
+            assert!(!HostLayout::Us.is_dead_key(ascii));
+            assert!(!HostLayout::Uk.is_dead_key(ascii));
+        }
+        assert!(HostLayout::De.is_dead_key(b'^'));
+        assert!(HostLayout::De.is_dead_key(b'`'));
+        assert!(!HostLayout::De.is_dead_key(b'~'));
+        assert!(HostLayout::Fr.is_dead_key(b'`'));
+        assert!(HostLayout::Fr.is_dead_key(b'~'));
+        assert!(!HostLayout::Fr.is_dead_key(b'^'));
+        assert!(!HostLayout::Fr.is_dead_key(b'a'));
+    }
+}
@@ -138,8 +138,11 @@ pub enum ConsumerKey {
     Email = 0x18A,
     Calculator = 0x192,
     LocalBrowser = 0x194,
+    InternetBrowser = 0x196,
     Lock = 0x19E,
     ControlPanel = 0x19F,
+    ScreenSaver = 0x1B1,
+    FileBrowser = 0x1B4,
     Assistant = 0x1CB,
     // 15.16 Generic GUI Application Controls
     New = 0x201,
@@ -164,6 +167,8 @@ pub enum ConsumerKey {
     Stop = 0x226,
     Refresh = 0x227,
     Bookmarks = 0x22A,
+    ZoomIn = 0x22D,
+    ZoomOut = 0x22E,
     NextKeyboardLayoutSelect = 0x29D,
     DesktopShowAllWindows = 0x29F,
     AcSoftKeyLeft = 0x2A0,
@@ -391,6 +396,12 @@ pub enum KeyCode {
     Assistant = 0x00C0,
     MissionControl = 0x00C1,
     Launchpad = 0x00C2,
+    AlInternetBrowser = 0x00C3,
+    AlFileBrowser = 0x00C4,
+    AlScreenSaver = 0x00C5,
+    AlTerminalLock = 0x00C6,
+    AcZoomIn = 0x00C7,
+    AcZoomOut = 0x00C8,
     MouseUp = 0x00CD,
     MouseDown = 0x00CE,
     MouseLeft = 0x00CF,
@@ -454,6 +465,7 @@ pub enum KeyCode {
     MagicSwapEscapeCapsLock = 0x120,
     MagicUnswapEscapeCapsLock = 0x121,
     MagicToggleEscapeCapsLock = 0x122,
+    MagicClear = 0x123,
     // Midi keycodes, use 0x200 ~ 0x2FF
     MidiOn = 0x200,
     MidiOff = 0x201,
@@ -903,6 +915,22 @@ pub enum KeyCode {
     User31 = 0x85F,
 }
 
+/// Aliases of consumer keys, named after the usages in the consumer page:
+/// `Al` for "Application Launch" and `Ac` for "Application Control"
+impl KeyCode {
+    pub const AlEmail: KeyCode = KeyCode::Mail;
+    pub const AlCalculator: KeyCode = KeyCode::Calculator;
+    pub const AlLocalBrowser: KeyCode = KeyCode::MyComputer;
+    pub const AlControlPanel: KeyCode = KeyCode::ControlPanel;
+    pub const AcSearch: KeyCode = KeyCode::WwwSearch;
+    pub const AcHome: KeyCode = KeyCode::WwwHome;
+    pub const AcBack: KeyCode = KeyCode::WwwBack;
+    pub const AcForward: KeyCode = KeyCode::WwwForward;
+    pub const AcStop: KeyCode = KeyCode::WwwStop;
+    pub const AcRefresh: KeyCode = KeyCode::WwwRefresh;
+    pub const AcBookmarks: KeyCode = KeyCode::WwwFavorites;
+}
+
 impl KeyCode {
     /// Returns `true` if the keycode is basic keycode
     pub(crate) fn is_basic(self) -> bool {
@@ -931,7 +959,7 @@ impl KeyCode {
 
     /// Returns `true` if the keycode is a keycode in consumer page
     pub(crate) fn is_consumer(self) -> bool {
-        KeyCode::AudioMute <= self && self <= KeyCode::Launchpad
+        KeyCode::AudioMute <= self && self <= KeyCode::AcZoomOut
     }
 
     /// Returns `true` if the keycode is a mouse keycode
@@ -941,7 +969,7 @@ impl KeyCode {
 
     /// Returns `true` if the keycode is a magic keycode
     pub(crate) fn is_magic(self) -> bool {
-        KeyCode::MagicSwapControlCapsLock <= self && self <= KeyCode::MagicToggleEscapeCapsLock
+        KeyCode::MagicSwapControlCapsLock <= self && self <= KeyCode::MagicClear
     }
 
     /// Returns `true` if the keycode is a midi keycode
@@ -1044,6 +1072,12 @@ impl KeyCode {
             KeyCode::Assistant => ConsumerKey::Assistant,
             KeyCode::MissionControl => ConsumerKey::DesktopShowAllWindows,
             KeyCode::Launchpad => ConsumerKey::AcSoftKeyLeft,
+            KeyCode::AlInternetBrowser => ConsumerKey::InternetBrowser,
+            KeyCode::AlFileBrowser => ConsumerKey::FileBrowser,
+            KeyCode::AlScreenSaver => ConsumerKey::ScreenSaver,
+            KeyCode::AlTerminalLock => ConsumerKey::Lock,
+            KeyCode::AcZoomIn => ConsumerKey::ZoomIn,
+            KeyCode::AcZoomOut => ConsumerKey::ZoomOut,
             _ => ConsumerKey::Zero,
         }
     }
@@ -1147,14 +1181,14 @@ impl KeyCode {
             b'"' => (KeyCode::Quote, true),
             b'`' => (KeyCode::Grave, false),
             b'~' => (KeyCode::Grave, true),
-            b'\\' => (KeyCode::Backslash, true),
-            b'|' => (KeyCode::Backslash, false),
+            b'\\' => (KeyCode::Backslash, false),
+            b'|' => (KeyCode::Backslash, true),
             b',' => (KeyCode::Comma, false),
             b'<' => (KeyCode::Comma, true),
             b'.' => (KeyCode::Dot, false),
             b'>' => (KeyCode::Dot, true),
             b'/' => (KeyCode::Slash, false),
-            b'?' => (KeyCode::Slash, false),
+            b'?' => (KeyCode::Slash, true),
             b' ' => (KeyCode::Space, false),
             b'\n' => (KeyCode::Enter, false),
             b'\t' => (KeyCode::Tab, false),
@@ -1,21 +1,113 @@
 use crate::{
-    action::KeyAction,
+    action::{Action, KeyAction},
+    channel_stats::{record_dropped, StatsChannel},
     event::KeyEvent,
     keyboard_macro::{MacroOperation, MACRO_SPACE_SIZE},
-    keycode::KeyCode,
+    keycode::{KeyCode, ModifierCombination},
     reboot_keyboard,
-    storage::Storage,
+    storage::{FlashOperationMessage, Storage, FLASH_CHANNEL},
 };
+use embassy_time::{Duration, Instant};
 use embedded_storage_async::nor_flash::NorFlash;
 use num_enum::FromPrimitive;
 
+/// Apply the base modifiers of a layer to an action on the layer.
+///
+/// Only key actions are affected, the base modifiers are combined with the modifiers of `WithModifier` actions.
+fn apply_base_modifiers(action: KeyAction, base_modifiers: ModifierCombination) -> KeyAction {
+    if base_modifiers.into_bits() == 0 {
+        return action;
+    }
+    match action {
+        KeyAction::Single(Action::Key(k)) => {
+            KeyAction::WithModifier(Action::Key(k), base_modifiers)
+        }
+        KeyAction::WithModifier(Action::Key(k), m) => {
+            KeyAction::WithModifier(Action::Key(k), m | base_modifiers)
+        }
+        _ => action,
+    }
+}
+
+/// The sticky layer is deactivated after this time, even if no key on it is used
+const STICKY_LAYER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// State of the sticky layer, see `Action::StickyLayer`
+#[derive(Default)]
+struct StickyLayerState {
+    /// The activated sticky layer, and the time when it's activated
+    active: Option<(u8, Instant)>,
+}
+
+impl StickyLayerState {
+    fn activate(&mut self, layer_num: u8, now: Instant) {
+        self.active = Some((layer_num, now));
+    }
+
+    /// Returns the sticky layer to deactivate, if it's timed out
+    fn expire(&mut self, now: Instant) -> Option<u8> {
+        match self.active {
+            Some((layer_num, start)) if now >= start + STICKY_LAYER_TIMEOUT => {
+                self.active = None;
+                Some(layer_num)
+            }
+            _ => None,
+        }
+    }
+
+    /// A key is pressed, `layer_num` is the layer where the key's action comes from.
+    ///
+    /// Returns the sticky layer to deactivate, if the key is on it.
+    /// Transparent keys fall through to lower layers, so they don't drop the sticky layer.
+    fn used(&mut self, layer_num: u8) -> Option<u8> {
+        match self.active {
+            Some((sticky, _)) if sticky == layer_num => {
+                self.active = None;
+                Some(sticky)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Activate the layers in `mod_layers` whose modifiers are all held, and deactivate them after the modifiers are released.
+///
+/// `mod_layer_state` records the layers activated here. A layer which is already activated by another key is left untouched,
+/// so releasing the modifier doesn't turn off a layer which is still held.
+fn update_mod_layers<const NUM_LAYER: usize>(
+    layer_state: &mut [bool; NUM_LAYER],
+    mod_layer_state: &mut [bool; NUM_LAYER],
+    mod_layers: &[(ModifierCombination, u8)],
+    modifier_bits: u8,
+) {
+    let mut held = [false; NUM_LAYER];
+    for &(modifiers, layer_num) in mod_layers {
+        let bits = modifiers.to_hid_modifier_bits();
+        if (layer_num as usize) < NUM_LAYER && bits != 0 && modifier_bits & bits == bits {
+            held[layer_num as usize] = true;
+        }
+    }
+
+    for (layer, &held) in held.iter().enumerate() {
+        if held && !mod_layer_state[layer] && !layer_state[layer] {
+            layer_state[layer] = true;
+            mod_layer_state[layer] = true;
+        } else if !held && mod_layer_state[layer] {
+            layer_state[layer] = false;
+            mod_layer_state[layer] = false;
+        }
+    }
+}
+
 /// Keymap represents the stack of layers.
 ///
 /// The conception of Keymap in rmk is borrowed from qmk: <https://docs.qmk.fm/#/keymap>.
 ///
 /// Keymap should be binded to the actual pcb matrix definition.
 /// RMK detects hardware key strokes, uses tuple `(row, col, layer)` to retrieve the action from Keymap.
-pub(crate) struct KeyMap<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize> {
+///
+/// The keymap is shared between RMK tasks as `RefCell<KeyMap>`, so don't hold the borrow across an `.await`.
+pub struct KeyMap<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize> {
     /// Layers
     pub(crate) layers: &'a mut [[[KeyAction; COL]; ROW]; NUM_LAYER],
     // TODO: Rotary encoders, each rotary encoder is represented as (Clockwise, CounterClockwise)
@@ -28,6 +120,16 @@ pub(crate) struct KeyMap<'a, const ROW: usize, const COL: usize, const NUM_LAYER
     layer_cache: [[u8; COL]; ROW],
     /// Macro cache
     pub(crate) macro_cache: [u8; MACRO_SPACE_SIZE],
+    /// Positions which are being held
+    held_keys: [[bool; COL]; ROW],
+    /// Time of the last key activity
+    last_activity: Instant,
+    /// Sticky layer state
+    sticky_layer: StickyLayerState,
+    /// Modifiers which are applied to the keys of each layer automatically
+    base_modifiers: [ModifierCombination; NUM_LAYER],
+    /// Layers which are activated by held modifiers, see `BehaviorConfig::mod_layers`
+    mod_layer_state: [bool; NUM_LAYER],
 }
 
 impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
@@ -40,6 +142,11 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
             default_layer: 0,
             layer_cache: [[0; COL]; ROW],
             macro_cache: [0; MACRO_SPACE_SIZE],
+            held_keys: [[false; COL]; ROW],
+            last_activity: Instant::now(),
+            sticky_layer: StickyLayerState::default(),
+            base_modifiers: [ModifierCombination::new(); NUM_LAYER],
+            mod_layer_state: [false; NUM_LAYER],
         }
     }
 
@@ -81,6 +188,11 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
             default_layer: 0,
             layer_cache: [[0; COL]; ROW],
             macro_cache,
+            held_keys: [[false; COL]; ROW],
+            last_activity: Instant::now(),
+            sticky_layer: StickyLayerState::default(),
+            base_modifiers: [ModifierCombination::new(); NUM_LAYER],
+            mod_layer_state: [false; NUM_LAYER],
         }
     }
 
@@ -93,6 +205,18 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
         self.default_layer
     }
 
+    /// Set the modifiers which are applied to the keys of the given layer automatically
+    pub(crate) fn set_base_modifiers(&mut self, layer_num: u8, modifiers: ModifierCombination) {
+        if layer_num as usize >= NUM_LAYER {
+            warn!(
+                "Not a valid layer {}, keyboard supports only {} layers",
+                layer_num, NUM_LAYER
+            );
+            return;
+        }
+        self.base_modifiers[layer_num as usize] = modifiers;
+    }
+
     /// Set the default layer number
     pub(crate) fn set_default_layer(&mut self, layer_num: u8) {
         self.default_layer = layer_num;
@@ -153,9 +277,8 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
                 (MacroOperation::Delay(0), offset + 4)
             }
             _ => {
-                // Current byte is the ascii code, convert it to keyboard keycode(with caps state)
-                let (keycode, is_caps) = KeyCode::from_ascii(self.macro_cache[idx]);
-                (MacroOperation::Text(keycode, is_caps), offset + 1)
+                // Current byte is the ascii code, it's converted to keycode according to the host layout
+                (MacroOperation::Text(self.macro_cache[idx]), offset + 1)
             }
         }
     }
@@ -195,14 +318,78 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
         self.layers[layer_num][row][col]
     }
 
+    /// Get the action at the given layer and position.
+    ///
+    /// Returns `None` if the layer or position is out of range.
+    pub fn get_action(&self, layer: u8, row: u8, col: u8) -> Option<KeyAction> {
+        self.layers
+            .get(layer as usize)
+            .and_then(|l| l.get(row as usize))
+            .and_then(|r| r.get(col as usize))
+            .copied()
+    }
+
+    /// Set the action at the given layer and position. The change takes effect immediately, but it's not saved,
+    /// use [`KeyMap::persist`] to save it to the storage.
+    ///
+    /// Returns `false` if the layer or position is out of range.
+    pub fn set_action(&mut self, layer: u8, row: u8, col: u8, action: KeyAction) -> bool {
+        match self
+            .layers
+            .get_mut(layer as usize)
+            .and_then(|l| l.get_mut(row as usize))
+            .and_then(|r| r.get_mut(col as usize))
+        {
+            Some(a) => {
+                *a = action;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Save the current action at the given layer and position to the storage.
+    ///
+    /// The action is sent to the storage task without waiting, so it's safe to call while the keymap is borrowed.
+    /// Returns `false` if the position is out of range, or the storage task is busy.
+    pub fn persist(&self, layer: u8, row: u8, col: u8) -> bool {
+        let action = match self.get_action(layer, row, col) {
+            Some(a) => a,
+            None => return false,
+        };
+        match FLASH_CHANNEL.try_send(FlashOperationMessage::KeymapKey {
+            layer,
+            col,
+            row,
+            action,
+        }) {
+            Ok(_) => true,
+            Err(_) => {
+                record_dropped(StatsChannel::FlashOperation);
+                error!("Failed to save keymap key, the storage task is busy");
+                false
+            }
+        }
+    }
+
     /// Fetch the action in keymap, with layer cache
     pub(crate) fn get_action_with_layer_cache(&mut self, key_event: KeyEvent) -> KeyAction {
         let row = key_event.row as usize;
         let col = key_event.col as usize;
+
+        // Record key activity
+        self.last_activity = Instant::now();
+        self.held_keys[row][col] = key_event.pressed;
+
         if !key_event.pressed {
             // Releasing a pressed key, use cached layer and restore the cache
-            let layer = self.pop_layer_from_cache(row, col);
-            return self.layers[layer as usize][row][col];
+            // The base modifiers are applied in the same way as pressing
+            let layer = self.pop_layer_from_cache(row, col) as usize;
+            return apply_base_modifiers(self.layers[layer][row][col], self.base_modifiers[layer]);
+        }
+
+        if let Some(layer_num) = self.sticky_layer.expire(self.last_activity) {
+            self.deactivate_layer(layer_num);
         }
 
         // Iterate from higher layer to lower layer, the lowest checked layer is the default layer
@@ -217,7 +404,12 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
                 // Found a valid action in the layer, cache it
                 self.save_layer_cache(row, col, layer_idx as u8);
 
-                return action;
+                // A key on the sticky layer is used, drop the sticky layer
+                if let Some(layer_num) = self.sticky_layer.used(layer_idx as u8) {
+                    self.deactivate_layer(layer_num);
+                }
+
+                return apply_base_modifiers(action, self.base_modifiers[layer_idx]);
             }
 
             if layer_idx as u8 == self.default_layer {
@@ -229,7 +421,7 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
         KeyAction::No
     }
 
-    fn get_activated_layer(&self) -> u8 {
+    pub(crate) fn get_activated_layer(&self) -> u8 {
         for (layer_idx, _) in self.layers.iter().enumerate().rev() {
             if self.layer_state[layer_idx] || layer_idx as u8 == self.default_layer {
                 return layer_idx as u8;
@@ -254,12 +446,55 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
         self.layer_cache[row][col] = layer_num;
     }
 
+    /// Get the time when the activated layers should be reset because of inactivity.
+    ///
+    /// Returns `None` if no layer is activated, or there're keys being held, because the held keys might be momentary layer keys.
+    pub(crate) fn idle_reset_deadline(&self, timeout: Duration) -> Option<Instant> {
+        if self.held_keys.iter().flatten().any(|&h| h) || !self.layer_state.iter().any(|&l| l) {
+            return None;
+        }
+        Some(self.last_activity + timeout)
+    }
+
+    /// Deactivate all layers after inactivity, back to the default layer
+    pub(crate) fn reset_idle_layers(&mut self) {
+        info!(
+            "Keyboard is idle, reset to default layer {}",
+            self.default_layer
+        );
+        self.layer_state = [false; NUM_LAYER];
+        self.mod_layer_state = [false; NUM_LAYER];
+        self.sticky_layer = StickyLayerState::default();
+    }
+
+    /// Activate given layer as a sticky layer, it's deactivated after a key on it is used
+    pub(crate) fn activate_sticky_layer(&mut self, layer_num: u8) {
+        self.activate_layer(layer_num);
+        if (layer_num as usize) < NUM_LAYER {
+            self.sticky_layer.activate(layer_num, Instant::now());
+        }
+    }
+
     /// Update given Tri Layer state
     pub(crate) fn update_tri_layer(&mut self, tri_layer: &[u8; 3]) {
         self.layer_state[tri_layer[2] as usize] =
             self.layer_state[tri_layer[0] as usize] && self.layer_state[tri_layer[1] as usize];
     }
 
+    /// Update the layers which are active while modifiers are held, `modifier_bits` is the modifier byte of the hid report
+    pub(crate) fn update_mod_layers(
+        &mut self,
+        mod_layers: &[(ModifierCombination, u8)],
+        modifier_bits: u8,
+    ) {
+        update_mod_layers(
+            &mut self.layer_state,
+            &mut self.mod_layer_state,
+            mod_layers,
+            modifier_bits,
+        );
+    }
+
     /// Activate given layer
     pub(crate) fn activate_layer(&mut self, layer_num: u8) {
         if layer_num as usize >= NUM_LAYER {
@@ -297,3 +532,115 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
         self.layer_state[layer_num as usize] = !self.layer_state[layer_num as usize];
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use embassy_futures::block_on;
+
+    #[test]
+    fn test_idle_reset_deadline() {
+        block_on(async {
+            let timeout = Duration::from_secs(10);
+            let mut layers = [[[KeyAction::Single(Action::LayerOn(1)), KeyAction::No]]; 2];
+            let mut keymap = KeyMap::new(&mut layers).await;
+            let press = KeyEvent {
+                row: 0,
+                col: 0,
+                pressed: true,
+            };
+            keymap.get_action_with_layer_cache(press);
+            keymap.activate_layer(1);
+            assert!(keymap.idle_reset_deadline(timeout).is_none());
+
+            // The same key pressed twice is released by a single release
+            keymap.get_action_with_layer_cache(press);
+            keymap.get_action_with_layer_cache(KeyEvent {
+                pressed: false,
+                ..press
+            });
+            assert!(keymap.idle_reset_deadline(timeout).is_some());
+        });
+    }
+
+    #[test]
+    fn test_sticky_layer() {
+        let start = Instant::from_secs(0);
+        let mut sticky = StickyLayerState::default();
+        sticky.activate(1, start);
+
+        // A transparent position on the sticky layer falls through to layer 0, the sticky layer stays
+        assert_eq!(sticky.used(0), None);
+        assert_eq!(sticky.expire(start + Duration::from_secs(1)), None);
+
+        // A real key on the sticky layer drops it
+        assert_eq!(sticky.used(1), Some(1));
+        assert_eq!(sticky.used(1), None);
+
+        // The sticky layer is dropped after the timeout
+        sticky.activate(2, start);
+        assert_eq!(sticky.expire(start + STICKY_LAYER_TIMEOUT), Some(2));
+        assert_eq!(sticky.used(2), None);
+    }
+
+    #[test]
+    fn test_base_modifiers() {
+        let ctrl = ModifierCombination::new_from(false, false, false, false, true);
+        let shift = ModifierCombination::new_from(false, false, false, true, false);
+
+        // Entering a layer with Ctrl as the base modifier applies Ctrl to its keys
+        assert_eq!(
+            apply_base_modifiers(KeyAction::Single(Action::Key(KeyCode::F1)), ctrl),
+            KeyAction::WithModifier(Action::Key(KeyCode::F1), ctrl)
+        );
+        // Combined with the modifiers of the key
+        assert_eq!(
+            apply_base_modifiers(
+                KeyAction::WithModifier(Action::Key(KeyCode::F2), shift),
+                ctrl
+            ),
+            KeyAction::WithModifier(Action::Key(KeyCode::F2), shift | ctrl)
+        );
+        // Layer actions are not affected
+        assert_eq!(
+            apply_base_modifiers(KeyAction::Single(Action::LayerOn(1)), ctrl),
+            KeyAction::Single(Action::LayerOn(1))
+        );
+        // No base modifiers on other layers
+        assert_eq!(
+            apply_base_modifiers(
+                KeyAction::Single(Action::Key(KeyCode::F1)),
+                ModifierCombination::new()
+            ),
+            KeyAction::Single(Action::Key(KeyCode::F1))
+        );
+    }
+
+    #[test]
+    fn test_mod_layers() {
+        let gui = ModifierCombination::new_from(false, true, false, false, false);
+        let mod_layers = [(gui, 2)];
+        let mut layer_state = [false; 4];
+        let mut mod_layer_state = [false; 4];
+
+        // Holding left GUI activates layer 2
+        update_mod_layers(&mut layer_state, &mut mod_layer_state, &mod_layers, 0x08);
+        assert_eq!(layer_state, [false, false, true, false]);
+        // Other modifiers held together don't matter, other held layers are kept
+        layer_state[1] = true;
+        update_mod_layers(&mut layer_state, &mut mod_layer_state, &mod_layers, 0x0A);
+        assert_eq!(layer_state, [false, true, true, false]);
+        // Releasing GUI deactivates layer 2 only
+        update_mod_layers(&mut layer_state, &mut mod_layer_state, &mod_layers, 0x02);
+        assert_eq!(layer_state, [false, true, false, false]);
+        // Right GUI doesn't match left GUI
+        update_mod_layers(&mut layer_state, &mut mod_layer_state, &mod_layers, 0x80);
+        assert_eq!(layer_state, [false, true, false, false]);
+
+        // A layer which is already held by a layer key isn't turned off by releasing the modifier
+        layer_state[2] = true;
+        update_mod_layers(&mut layer_state, &mut mod_layer_state, &mod_layers, 0x08);
+        update_mod_layers(&mut layer_state, &mut mod_layer_state, &mod_layers, 0x00);
+        assert_eq!(layer_state, [false, true, true, false]);
+    }
+}
@@ -0,0 +1,109 @@
+//! Scan-to-report latency of key events.
+//!
+//! When `latency_trace` feature is enabled, RMK records the time when a key change is detected by the matrix,
+//! and measures the time until the keyboard report carrying that change is sent.
+//! Min/avg/max latency can be read by `rmk::latency_trace::stats` or by Vial's custom get value command.
+//! When the feature is disabled, recording is a no-op.
+
+#[cfg(feature = "latency_trace")]
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// Detection time of the pending key change in microseconds, 0 means there's no pending key change
+#[cfg(feature = "latency_trace")]
+static PENDING: AtomicU32 = AtomicU32::new(0);
+
+#[cfg(feature = "latency_trace")]
+static MIN: AtomicU32 = AtomicU32::new(u32::MAX);
+
+#[cfg(feature = "latency_trace")]
+static MAX: AtomicU32 = AtomicU32::new(0);
+
+#[cfg(feature = "latency_trace")]
+static SUM: AtomicU32 = AtomicU32::new(0);
+
+#[cfg(feature = "latency_trace")]
+static COUNT: AtomicU32 = AtomicU32::new(0);
+
+/// Latency statistics, in microseconds
+#[cfg(feature = "latency_trace")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LatencyStats {
+    pub min: u32,
+    pub avg: u32,
+    pub max: u32,
+    /// Number of measured key changes
+    pub count: u32,
+}
+
+#[cfg(feature = "latency_trace")]
+fn now_micros() -> u32 {
+    // 0 is reserved for "no pending key change"
+    (embassy_time::Instant::now().as_micros() as u32).max(1)
+}
+
+/// Record that a key change is detected by the matrix.
+///
+/// Only the first change before a report is sent is kept, so the measured latency includes the time waiting in the channel.
+#[inline(always)]
+pub(crate) fn record_scan() {
+    #[cfg(feature = "latency_trace")]
+    if PENDING.load(Ordering::Relaxed) == 0 {
+        PENDING.store(now_micros(), Ordering::Relaxed);
+    }
+}
+
+/// Record that a keyboard report is sent
+#[inline(always)]
+pub(crate) fn record_report() {
+    #[cfg(feature = "latency_trace")]
+    {
+        let detected = PENDING.load(Ordering::Relaxed);
+        if detected == 0 {
+            return;
+        }
+        PENDING.store(0, Ordering::Relaxed);
+        let latency = now_micros().wrapping_sub(detected);
+        if latency < MIN.load(Ordering::Relaxed) {
+            MIN.store(latency, Ordering::Relaxed);
+        }
+        if latency > MAX.load(Ordering::Relaxed) {
+            MAX.store(latency, Ordering::Relaxed);
+        }
+        // Only load/store are used, because atomic RMW is not available on all targets
+        SUM.store(
+            SUM.load(Ordering::Relaxed).saturating_add(latency),
+            Ordering::Relaxed,
+        );
+        COUNT.store(
+            COUNT.load(Ordering::Relaxed).saturating_add(1),
+            Ordering::Relaxed,
+        );
+        trace!("Scan-to-report latency: {}us", latency);
+    }
+}
+
+/// Latency statistics since boot or the last `reset`, `None` if nothing is measured yet
+#[cfg(feature = "latency_trace")]
+pub fn stats() -> Option<LatencyStats> {
+    let count = COUNT.load(Ordering::Relaxed);
+    if count == 0 {
+        return None;
+    }
+    Some(LatencyStats {
+        min: MIN.load(Ordering::Relaxed),
+        avg: SUM.load(Ordering::Relaxed) / count,
+        max: MAX.load(Ordering::Relaxed),
+        count,
+    })
+}
+
+/// Reset all statistics
+#[cfg(feature = "latency_trace")]
+pub fn reset() {
+    PENDING.store(0, Ordering::Relaxed);
+    MIN.store(u32::MAX, Ordering::Relaxed);
+    MAX.store(0, Ordering::Relaxed);
+    SUM.store(0, Ordering::Relaxed);
+    COUNT.store(0, Ordering::Relaxed);
+}
@@ -82,6 +82,27 @@ macro_rules! th {
     };
 }
 
+/// Create a repeating action, the key is triggered repeatedly at the given interval(ms) while held
+#[macro_export]
+macro_rules! rep {
+    ($interval: literal, $k: ident) => {
+        $crate::action::KeyAction::Repeating(
+            $crate::action::Action::Key($crate::keycode::KeyCode::$k),
+            $interval,
+        )
+    };
+}
+
+/// Create an action which is tapped when the key is released
+#[macro_export]
+macro_rules! on_release {
+    ($k: ident) => {
+        $crate::action::KeyAction::OnRelease($crate::action::Action::Key(
+            $crate::keycode::KeyCode::$k,
+        ))
+    };
+}
+
 /// Create an oneshot layer key in keymap
 #[macro_export]
 macro_rules! osl {
@@ -90,6 +111,22 @@ macro_rules! osl {
     };
 }
 
+/// Create a sticky layer key in keymap, the layer stays activated until a key on it is used
+#[macro_export]
+macro_rules! sl {
+    ($x: literal) => {
+        $crate::action::KeyAction::Single($crate::action::Action::StickyLayer($x))
+    };
+}
+
+/// Create a layer key which activates the layer while held, and toggles the layer on by double tap
+#[macro_export]
+macro_rules! motg {
+    ($x: literal) => {
+        $crate::action::KeyAction::Single($crate::action::Action::LayerMomentaryOrToggle($x))
+    };
+}
+
 /// Create an oneshot modifier key in keymap
 #[macro_export]
 macro_rules! osm {
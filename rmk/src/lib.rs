@@ -18,11 +18,14 @@ pub(crate) mod fmt;
 use crate::ble::esp::initialize_esp_ble_keyboard_with_config_and_run;
 #[cfg(feature = "_nrf_ble")]
 use crate::ble::nrf::initialize_nrf_ble_keyboard_and_run;
-use crate::config::RmkConfig;
-#[cfg(not(feature = "rapid_debouncer"))]
+use crate::config::{BehaviorConfig, RmkConfig, VialConfig};
+#[cfg(not(any(feature = "rapid_debouncer", feature = "verified_debouncer")))]
 use crate::debounce::default_bouncer::DefaultDebouncer;
 #[cfg(feature = "rapid_debouncer")]
 use crate::debounce::fast_debouncer::RapidDebouncer;
+#[cfg(feature = "verified_debouncer")]
+use crate::debounce::verified_eager_debouncer::VerifiedEagerDebouncer;
+use crate::hid::ConnectionType;
 use crate::{
     light::{led_hid_task, LightService},
     via::vial_task,
@@ -30,6 +33,7 @@ use crate::{
 use action::KeyAction;
 use core::{
     cell::RefCell,
+    future::Future,
     sync::atomic::{AtomicBool, AtomicU8},
 };
 use debounce::DebouncerTrait;
@@ -37,7 +41,7 @@ use debounce::DebouncerTrait;
 use embassy_executor::Spawner;
 use embassy_futures::select::{select, select4, Either4};
 pub use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::*};
-use embassy_time::Timer;
+use embassy_time::{Duration, Timer};
 use embassy_usb::driver::Driver;
 pub use embedded_hal;
 use embedded_hal::digital::{InputPin, OutputPin};
@@ -49,31 +53,52 @@ pub use flash::EmptyFlashWrapper;
 use futures::pin_mut;
 use keyboard::{communication_task, Keyboard, KeyboardReportMessage, KEYBOARD_REPORT_CHANNEL};
 pub use keyboard::{EVENT_CHANNEL, EVENT_CHANNEL_SIZE, REPORT_CHANNEL_SIZE};
-use keymap::KeyMap;
+pub use keymap::KeyMap;
 use matrix::{Matrix, MatrixTrait};
 pub use rmk_macro as macros;
+pub use storage::nor_flash::eeprom::{EepromFlash, EepromFlashError};
+#[cfg(feature = "usb_battery")]
+pub use usb::set_battery_level;
 use usb::KeyboardUsbDevice;
 use via::process::VialService;
 #[cfg(any(feature = "_nrf_ble", not(feature = "_no_external_storage")))]
 use {embedded_storage_async::nor_flash::NorFlash as AsyncNorFlash, storage::Storage};
 
 pub mod action;
+#[cfg(feature = "autocorrect")]
+mod autocorrect;
 #[cfg(feature = "_ble")]
 pub mod ble;
+#[cfg(feature = "channel_stats")]
+pub mod channel_stats;
+#[cfg(not(feature = "channel_stats"))]
+mod channel_stats;
 pub mod config;
 pub mod debounce;
 pub mod direct_pin;
+mod double_tap;
 pub mod event;
 mod flash;
 mod hid;
 pub mod input_device;
+mod key_lock;
 pub mod keyboard;
 mod keyboard_macro;
 pub mod keycode;
 mod keymap;
+#[cfg(feature = "latency_trace")]
+pub mod latency_trace;
+#[cfg(not(feature = "latency_trace"))]
+mod latency_trace;
 mod layout_macro;
 mod light;
+mod magic;
 pub mod matrix;
+mod modifier_chord;
+mod momentary_or_toggle;
+mod other_key_guard;
+mod press_interval;
+mod repeating;
 #[cfg(feature = "split")]
 pub mod split;
 mod storage;
@@ -90,6 +115,9 @@ pub(crate) static CONNECTION_TYPE: AtomicU8 = AtomicU8::new(0);
 /// Whether the connection is ready.
 /// After the connection is ready, the matrix starts scanning
 pub(crate) static CONNECTION_STATE: AtomicBool = AtomicBool::new(false);
+/// Whether the battery saver is on.
+/// While it's on, the matrix scans slower when idle, the LEDs are turned off and BLE uses a longer connection interval
+pub(crate) static BATTERY_SAVER: AtomicBool = AtomicBool::new(false);
 
 /// Run RMK keyboard service. This function should never return.
 ///
@@ -179,18 +207,35 @@ pub async fn run_rmk_with_async_flash<
     // Create the debouncer, use COL2ROW by default
     #[cfg(all(feature = "col2row", feature = "rapid_debouncer"))]
     let debouncer = RapidDebouncer::<ROW, COL>::new();
-    #[cfg(all(feature = "col2row", not(feature = "rapid_debouncer")))]
+    #[cfg(all(feature = "col2row", feature = "verified_debouncer"))]
+    let debouncer = VerifiedEagerDebouncer::<ROW, COL>::new();
+    #[cfg(all(
+        feature = "col2row",
+        not(any(feature = "rapid_debouncer", feature = "verified_debouncer"))
+    ))]
     let debouncer = DefaultDebouncer::<ROW, COL>::new();
     #[cfg(all(not(feature = "col2row"), feature = "rapid_debouncer"))]
     let debouncer = RapidDebouncer::<COL, ROW>::new();
-    #[cfg(all(not(feature = "col2row"), not(feature = "rapid_debouncer")))]
+    #[cfg(all(not(feature = "col2row"), feature = "verified_debouncer"))]
+    let debouncer = VerifiedEagerDebouncer::<COL, ROW>::new();
+    #[cfg(all(
+        not(feature = "col2row"),
+        not(any(feature = "rapid_debouncer", feature = "verified_debouncer"))
+    ))]
     let debouncer = DefaultDebouncer::<COL, ROW>::new();
 
     // Keyboard matrix, use COL2ROW by default
     #[cfg(feature = "col2row")]
-    let matrix = Matrix::<_, _, _, ROW, COL>::new(input_pins, output_pins, debouncer);
+    let mut matrix = Matrix::<_, _, _, ROW, COL>::new(input_pins, output_pins, debouncer);
     #[cfg(not(feature = "col2row"))]
-    let matrix = Matrix::<_, _, _, COL, ROW>::new(input_pins, output_pins, debouncer);
+    let mut matrix = Matrix::<_, _, _, COL, ROW>::new(input_pins, output_pins, debouncer);
+    matrix.disable_positions(keyboard_config.matrix_config.disabled_positions);
+    matrix.set_scan_interval(
+        keyboard_config.matrix_config.scan_fast_interval,
+        keyboard_config.matrix_config.scan_idle_interval,
+    );
+    matrix.set_boot_settle(keyboard_config.matrix_config.boot_settle_time);
+    matrix.set_discharge_time(keyboard_config.matrix_config.discharge_time);
 
     run_rmk_with_async_flash_and_matrix(
         matrix,
@@ -279,52 +324,59 @@ pub async fn initialize_usb_keyboard_and_run<
     const COL: usize,
     const NUM_LAYER: usize,
 >(
-    mut matrix: M,
+    matrix: M,
     usb_driver: D,
     #[cfg(any(feature = "_nrf_ble", not(feature = "_no_external_storage")))] flash: F,
     default_keymap: &mut [[[KeyAction; COL]; ROW]; NUM_LAYER],
 
     keyboard_config: RmkConfig<'static, Out>,
 ) -> ! {
-    // Initialize storage and keymap
     // For USB keyboard, the "external" storage means the storage initialized by the user.
     #[cfg(any(feature = "_nrf_ble", not(feature = "_no_external_storage")))]
-    let (mut storage, keymap) = {
-        let mut s = Storage::new(flash, default_keymap, keyboard_config.storage_config).await;
-        let keymap = RefCell::new(KeyMap::new_from_storage(default_keymap, Some(&mut s)).await);
-        (s, keymap)
-    };
+    {
+        let mut usb_keyboard =
+            UsbKeyboard::new(matrix, usb_driver, flash, default_keymap, keyboard_config).await;
+        let keymap = RefCell::new(usb_keyboard.load_keymap(default_keymap).await);
+        usb_keyboard.run(&keymap).await
+    }
+
     #[cfg(all(not(feature = "_nrf_ble"), feature = "_no_external_storage"))]
-    let keymap = RefCell::new(KeyMap::<ROW, COL, NUM_LAYER>::new(default_keymap).await);
-
-    let keyboard_report_sender = KEYBOARD_REPORT_CHANNEL.sender();
-    let keyboard_report_receiver = KEYBOARD_REPORT_CHANNEL.receiver();
-
-    // Create keyboard services and devices
-    let (mut keyboard, mut usb_device, mut vial_service, mut light_service) = (
-        Keyboard::new(
-            &keymap,
-            &keyboard_report_sender,
-            keyboard_config.behavior_config,
-        ),
-        KeyboardUsbDevice::new(usb_driver, keyboard_config.usb_config),
-        VialService::new(&keymap, keyboard_config.vial_config),
-        LightService::from_config(keyboard_config.light_config),
-    );
+    {
+        let mut matrix = matrix;
+        let keymap = RefCell::new(KeyMap::<ROW, COL, NUM_LAYER>::new(default_keymap).await);
+        let keyboard_report_sender = KEYBOARD_REPORT_CHANNEL.sender();
+        let keyboard_report_receiver = KEYBOARD_REPORT_CHANNEL.receiver();
 
-    KEYBOARD_STATE.store(false, core::sync::atomic::Ordering::Release);
-    // Run all tasks, if one of them fails, wait 1 second and then restart
-    run_usb_keyboard(
-        &mut usb_device,
-        &mut keyboard,
-        &mut matrix,
-        #[cfg(any(feature = "_nrf_ble", not(feature = "_no_external_storage")))]
-        &mut storage,
-        &mut light_service,
-        &mut vial_service,
-        &keyboard_report_receiver,
-    )
-    .await
+        // Create keyboard services and devices
+        let (mut keyboard, mut usb_device, mut vial_service, mut light_service) = (
+            Keyboard::new(
+                &keymap,
+                &keyboard_report_sender,
+                keyboard_config.behavior_config,
+            ),
+            KeyboardUsbDevice::new(
+                usb_driver,
+                keyboard_config.usb_config,
+                keyboard_config.host_config.primary_device,
+            ),
+            VialService::new(&keymap, keyboard_config.vial_config),
+            LightService::from_config(keyboard_config.light_config),
+        );
+        keyboard.set_consumer_max_hold(keyboard_config.host_config.consumer_max_hold);
+
+        KEYBOARD_STATE.store(false, core::sync::atomic::Ordering::Release);
+        // Run all tasks, if one of them fails, wait 1 second and then restart
+        run_usb_keyboard(
+            &mut usb_device,
+            &mut keyboard,
+            &mut matrix,
+            &mut light_service,
+            &mut vial_service,
+            &keyboard_report_receiver,
+            keyboard_config.host_config.report_coalesce,
+        )
+        .await
+    }
 }
 
 // Run usb keyboard task for once
@@ -356,9 +408,11 @@ pub(crate) async fn run_usb_keyboard<
         KeyboardReportMessage,
         REPORT_CHANNEL_SIZE,
     >,
+    report_coalesce: Duration,
 ) -> ! {
     loop {
         CONNECTION_STATE.store(false, core::sync::atomic::Ordering::Release);
+        keyboard.set_transport(ConnectionType::Usb);
         let usb_fut = usb_device.device.run();
         let keyboard_fut = keyboard.run();
         let matrix_fut = matrix.run();
@@ -366,8 +420,16 @@ pub(crate) async fn run_usb_keyboard<
             keyboard_report_receiver,
             &mut usb_device.keyboard_hid_writer,
             &mut usb_device.other_hid_writer,
+            #[cfg(feature = "gamepad")]
+            &mut usb_device.gamepad_hid_writer,
+            report_coalesce,
         );
         let led_fut = led_hid_task(&mut usb_device.keyboard_hid_reader, light_service);
+        #[cfg(feature = "usb_battery")]
+        let led_fut = select(
+            led_fut,
+            usb::usb_battery_task(&mut usb_device.battery_hid_writer),
+        );
         let via_fut = vial_task(&mut usb_device.via_hid, vial_service);
 
         pin_mut!(usb_fut);
@@ -396,7 +458,7 @@ pub(crate) async fn run_usb_keyboard<
         {
             Either4::First(_) => error!("Usb or keyboard task has died"),
             Either4::Second(_) => error!("Storage or vial task has died"),
-            Either4::Third(_) => error!("Led task has died"),
+            Either4::Third(_) => error!("Led or battery task has died"),
             Either4::Fourth(_) => error!("Communication task has died"),
         }
 
@@ -405,6 +467,267 @@ pub(crate) async fn run_usb_keyboard<
     }
 }
 
+/// USB keyboard with storage, which is used by [`run_rmk`] when the keyboard uses USB only.
+///
+/// [`UsbKeyboard::run`] runs all services like [`run_rmk`]. The services can also be run by the user,
+/// which is useful when RMK runs alongside other tasks, such as displays or sensors, on the same executor:
+///
+/// ```ignore
+/// let mut usb_keyboard = UsbKeyboard::new(matrix, driver, flash, &keymap, keyboard_config).await;
+/// let keymap = RefCell::new(usb_keyboard.load_keymap(&mut keymap).await);
+/// let tasks = usb_keyboard.into_tasks(&keymap);
+/// // Run RMK until `shutdown` completes
+/// select(tasks.run(), shutdown).await;
+/// ```
+#[cfg(any(feature = "_nrf_ble", not(feature = "_no_external_storage")))]
+pub struct UsbKeyboard<
+    Out: OutputPin,
+    D: Driver<'static>,
+    M: MatrixTrait,
+    F: AsyncNorFlash,
+    const ROW: usize,
+    const COL: usize,
+    const NUM_LAYER: usize,
+> {
+    matrix: M,
+    usb_device: KeyboardUsbDevice<'static, D>,
+    storage: Storage<F, ROW, COL, NUM_LAYER>,
+    light_service: LightService<Out>,
+    behavior_config: BehaviorConfig,
+    vial_config: VialConfig<'static>,
+    report_coalesce: Duration,
+    consumer_max_hold: Option<Duration>,
+}
+
+#[cfg(any(feature = "_nrf_ble", not(feature = "_no_external_storage")))]
+impl<
+        Out: OutputPin,
+        D: Driver<'static>,
+        M: MatrixTrait,
+        F: AsyncNorFlash,
+        const ROW: usize,
+        const COL: usize,
+        const NUM_LAYER: usize,
+    > UsbKeyboard<Out, D, M, F, ROW, COL, NUM_LAYER>
+{
+    /// Create the USB keyboard and initialize the storage
+    ///
+    /// # Arguments
+    ///
+    /// * `matrix` - the matrix scanning implementation to use.
+    /// * `usb_driver` - embassy usb driver instance
+    /// * `flash` - async flash storage, which is used for storing keymap and keyboard configs
+    /// * `default_keymap` - default keymap definition, which is used to initialize the storage
+    /// * `keyboard_config` - other configurations of the keyboard, check [RmkConfig] struct for details
+    pub async fn new(
+        matrix: M,
+        usb_driver: D,
+        flash: F,
+        default_keymap: &[[[KeyAction; COL]; ROW]; NUM_LAYER],
+        keyboard_config: RmkConfig<'static, Out>,
+    ) -> Self {
+        Self {
+            matrix,
+            usb_device: KeyboardUsbDevice::new(
+                usb_driver,
+                keyboard_config.usb_config,
+                keyboard_config.host_config.primary_device,
+            ),
+            storage: Storage::new(flash, default_keymap, keyboard_config.storage_config).await,
+            light_service: LightService::from_config(keyboard_config.light_config),
+            behavior_config: keyboard_config.behavior_config,
+            vial_config: keyboard_config.vial_config,
+            report_coalesce: keyboard_config.host_config.report_coalesce,
+            consumer_max_hold: keyboard_config.host_config.consumer_max_hold,
+        }
+    }
+
+    /// Load the keymap, the keymap saved in storage overrides `default_keymap`
+    pub async fn load_keymap<'a>(
+        &mut self,
+        default_keymap: &'a mut [[[KeyAction; COL]; ROW]; NUM_LAYER],
+    ) -> KeyMap<'a, ROW, COL, NUM_LAYER> {
+        KeyMap::new_from_storage(default_keymap, Some(&mut self.storage)).await
+    }
+
+    /// Run RMK services until the keyboard is powered off, this function never returns.
+    ///
+    /// If one of the services fails, all services are restarted after 1 second.
+    pub async fn run<'a>(mut self, keymap: &'a RefCell<KeyMap<'a, ROW, COL, NUM_LAYER>>) -> ! {
+        let keyboard_report_sender = KEYBOARD_REPORT_CHANNEL.sender();
+        let mut keyboard = Keyboard::new(keymap, &keyboard_report_sender, self.behavior_config);
+        keyboard.set_consumer_max_hold(self.consumer_max_hold);
+        let mut vial_service = VialService::new(keymap, self.vial_config);
+
+        KEYBOARD_STATE.store(false, core::sync::atomic::Ordering::Release);
+        run_usb_keyboard(
+            &mut self.usb_device,
+            &mut keyboard,
+            &mut self.matrix,
+            &mut self.storage,
+            &mut self.light_service,
+            &mut vial_service,
+            &KEYBOARD_REPORT_CHANNEL.receiver(),
+            self.report_coalesce,
+        )
+        .await
+    }
+
+    /// Split the keyboard into futures of RMK services.
+    ///
+    /// All returned futures must be polled concurrently, and they're not restarted if one of them stops.
+    pub fn into_tasks<'a>(
+        self,
+        keymap: &'a RefCell<KeyMap<'a, ROW, COL, NUM_LAYER>>,
+    ) -> RmkTasks<
+        impl Future<Output = ()>,
+        impl Future<Output = ()> + 'a,
+        impl Future<Output = ()>,
+        impl Future<Output = ()>,
+        impl Future<Output = ()>,
+        impl Future<Output = ()> + 'a,
+        impl Future<Output = ()>,
+    >
+    where
+        Out: 'a,
+        D: 'a,
+        M: 'a,
+        F: 'a,
+    {
+        let Self {
+            mut matrix,
+            usb_device,
+            mut storage,
+            mut light_service,
+            behavior_config,
+            vial_config,
+            report_coalesce,
+            consumer_max_hold,
+        } = self;
+        let KeyboardUsbDevice {
+            mut device,
+            mut keyboard_hid_writer,
+            mut keyboard_hid_reader,
+            mut other_hid_writer,
+            mut via_hid,
+            #[cfg(feature = "gamepad")]
+            mut gamepad_hid_writer,
+            #[cfg(feature = "usb_battery")]
+            mut battery_hid_writer,
+        } = usb_device;
+
+        KEYBOARD_STATE.store(false, core::sync::atomic::Ordering::Release);
+        CONNECTION_STATE.store(false, core::sync::atomic::Ordering::Release);
+
+        RmkTasks {
+            usb: async move {
+                device.run().await;
+            },
+            keyboard: async move {
+                let keyboard_report_sender = KEYBOARD_REPORT_CHANNEL.sender();
+                let mut keyboard = Keyboard::new(keymap, &keyboard_report_sender, behavior_config);
+                keyboard.set_consumer_max_hold(consumer_max_hold);
+                keyboard.set_transport(ConnectionType::Usb);
+                keyboard.run().await;
+            },
+            matrix: async move {
+                matrix.run().await;
+            },
+            communication: async move {
+                communication_task(
+                    &KEYBOARD_REPORT_CHANNEL.receiver(),
+                    &mut keyboard_hid_writer,
+                    &mut other_hid_writer,
+                    #[cfg(feature = "gamepad")]
+                    &mut gamepad_hid_writer,
+                    report_coalesce,
+                )
+                .await;
+            },
+            led: async move {
+                let led_fut = led_hid_task(&mut keyboard_hid_reader, &mut light_service);
+                #[cfg(feature = "usb_battery")]
+                let led_fut = select(led_fut, usb::usb_battery_task(&mut battery_hid_writer));
+                led_fut.await;
+            },
+            vial: async move {
+                let mut vial_service = VialService::new(keymap, vial_config);
+                vial_task(&mut via_hid, &mut vial_service).await;
+            },
+            storage: async move {
+                storage.run().await;
+            },
+        }
+    }
+}
+
+/// Futures of RMK services, created by [`UsbKeyboard::into_tasks`].
+///
+/// The futures can be composed with other tasks by `join`/`select`, or be run together by [`RmkTasks::run`].
+#[cfg(any(feature = "_nrf_ble", not(feature = "_no_external_storage")))]
+pub struct RmkTasks<Usb, Kb, Mx, Comm, Led, Via, St> {
+    /// USB device task
+    pub usb: Usb,
+    /// Key processing task
+    pub keyboard: Kb,
+    /// Matrix scanning task
+    pub matrix: Mx,
+    /// Task which sends reports to the host
+    pub communication: Comm,
+    /// Led indicator task, it also reports battery level if `usb_battery` is enabled
+    pub led: Led,
+    /// Vial task
+    pub vial: Via,
+    /// Storage task
+    pub storage: St,
+}
+
+#[cfg(any(feature = "_nrf_ble", not(feature = "_no_external_storage")))]
+impl<Usb, Kb, Mx, Comm, Led, Via, St> RmkTasks<Usb, Kb, Mx, Comm, Led, Via, St>
+where
+    Usb: Future<Output = ()>,
+    Kb: Future<Output = ()>,
+    Mx: Future<Output = ()>,
+    Comm: Future<Output = ()>,
+    Led: Future<Output = ()>,
+    Via: Future<Output = ()>,
+    St: Future<Output = ()>,
+{
+    /// Run all tasks, returns when one of them stops
+    pub async fn run(self) {
+        let Self {
+            usb,
+            keyboard,
+            matrix,
+            communication,
+            led,
+            vial,
+            storage,
+        } = self;
+        pin_mut!(usb);
+        pin_mut!(keyboard);
+        pin_mut!(matrix);
+        pin_mut!(communication);
+        pin_mut!(led);
+        pin_mut!(vial);
+        pin_mut!(storage);
+
+        match select4(
+            select(usb, keyboard),
+            select(storage, vial),
+            led,
+            select(matrix, communication),
+        )
+        .await
+        {
+            Either4::First(_) => error!("Usb or keyboard task has died"),
+            Either4::Second(_) => error!("Storage or vial task has died"),
+            Either4::Third(_) => error!("Led or battery task has died"),
+            Either4::Fourth(_) => error!("Communication task has died"),
+        }
+    }
+}
+
 pub(crate) fn reboot_keyboard() {
     warn!("Rebooting keyboard!");
     // For cortex-m:
@@ -1,18 +1,89 @@
-use crate::config::{LightConfig, LightPinConfig};
+use crate::config::{LayerIndicator, LightConfig, LightPinConfig, LockLed};
 use crate::hid::HidReaderWrapper;
 use bitfield_struct::bitfield;
-use embassy_futures::select::select;
-use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel};
+#[cfg(feature = "split")]
+use core::sync::atomic::{AtomicU8, Ordering};
+use embassy_futures::select::{select, select4, Either4};
+#[cfg(feature = "split")]
+use embassy_sync::pubsub::PubSubChannel;
+use embassy_sync::{
+    blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel, signal::Signal,
+};
 use embedded_hal::digital::{Error, OutputPin, PinState};
 
 pub(crate) static LED_CHANNEL: Channel<CriticalSectionRawMutex, LedIndicator, 8> = Channel::new();
 
+/// Active layer to be shown by the layer indicator, (layer, whether it's the default layer)
+pub(crate) static LAYER_INDICATOR_SIGNAL: Signal<CriticalSectionRawMutex, (u8, bool)> =
+    Signal::new();
+
+/// Battery saver state, all LEDs are turned off while the battery saver is on
+pub(crate) static BATTERY_SAVER_SIGNAL: Signal<CriticalSectionRawMutex, bool> = Signal::new();
+
+/// Game mode state, shown by the game mode indicator
+pub(crate) static GAME_MODE_SIGNAL: Signal<CriticalSectionRawMutex, bool> = Signal::new();
+
+/// Maximum number of split peripherals which mirror the led indicator of the central
+#[cfg(feature = "split")]
+pub(crate) const LED_MIRROR_MAX_PERIPHERALS: usize = 4;
+
+/// Latest led indicator received from the host, in bits
+#[cfg(feature = "split")]
+pub(crate) static LED_INDICATOR_STATE: AtomicU8 = AtomicU8::new(0);
+
+/// Led indicator updates from the host, which are forwarded to split peripherals
+#[cfg(feature = "split")]
+pub(crate) static SPLIT_LED_INDICATOR_CHANNEL: PubSubChannel<
+    CriticalSectionRawMutex,
+    u8,
+    1,
+    LED_MIRROR_MAX_PERIPHERALS,
+    1,
+> = PubSubChannel::new();
+
+/// Record the led indicator received from the host, so that it can be forwarded to split peripherals
+#[cfg(feature = "split")]
+pub(crate) fn update_split_led_indicator(led_indicator: LedIndicator) {
+    LED_INDICATOR_STATE.store(led_indicator.into_bits(), Ordering::Release);
+    SPLIT_LED_INDICATOR_CHANNEL
+        .immediate_publisher()
+        .publish_immediate(led_indicator.into_bits());
+}
+
 /// LED control task
 pub(crate) async fn led_service_task<P: OutputPin>(light_service: &mut LightService<P>) {
+    let layer_indicator_enabled = light_service.layer_indicator.is_some();
+    let game_mode_indicator_enabled = light_service.game_mode_indicator.is_some();
     loop {
-        let led_indicator = LED_CHANNEL.receive().await;
+        let layer_fut = async {
+            if layer_indicator_enabled {
+                LAYER_INDICATOR_SIGNAL.wait().await
+            } else {
+                core::future::pending().await
+            }
+        };
+        let game_mode_fut = async {
+            if game_mode_indicator_enabled {
+                GAME_MODE_SIGNAL.wait().await
+            } else {
+                core::future::pending().await
+            }
+        };
+        let result = match select4(
+            LED_CHANNEL.receive(),
+            layer_fut,
+            BATTERY_SAVER_SIGNAL.wait(),
+            game_mode_fut,
+        )
+        .await
+        {
+            Either4::First(led_indicator) => light_service.set_leds(led_indicator),
+            Either4::Second((layer, is_default)) => light_service.set_layer(layer, is_default),
+            Either4::Third(battery_saver) => light_service.set_battery_saver(battery_saver),
+            Either4::Fourth(game_mode) => light_service.set_game_mode(game_mode),
+        };
         if light_service.enabled {
-            if let Err(e) = light_service.set_leds(led_indicator) {
+            if let Err(e) = result {
                 error!("Set led error {:?}", e.kind());
                 // If there's an error, wait for a while
                 embassy_time::Timer::after_millis(500).await;
@@ -26,7 +97,10 @@ pub(crate) async fn led_service_task<P: OutputPin>(light_service: &mut LightServ
 /// Check led indicator and send the led status to LED channel
 ///
 /// If there's an error, print a message and ignore error types
-pub(crate) async fn hid_read_led<R: HidReaderWrapper>(keyboard_hid_reader: &mut R) -> ! {
+pub(crate) async fn hid_read_led<R: HidReaderWrapper>(
+    keyboard_hid_reader: &mut R,
+    led_service_enabled: bool,
+) -> ! {
     loop {
         let mut led_indicator_data = [0; 1];
         match keyboard_hid_reader.read(&mut led_indicator_data).await {
@@ -34,7 +108,11 @@ pub(crate) async fn hid_read_led<R: HidReaderWrapper>(keyboard_hid_reader: &mut
                 // Read led indicator data and send to LED channel
                 let indicator = LedIndicator::from_bits(led_indicator_data[0]);
                 debug!("Read keyboard state: {:?}", indicator);
-                LED_CHANNEL.send(indicator).await;
+                #[cfg(feature = "split")]
+                update_split_led_indicator(indicator);
+                if led_service_enabled {
+                    LED_CHANNEL.send(indicator).await;
+                }
             }
             Err(e) => {
                 error!("Read keyboard state error: {:?}", e);
@@ -49,12 +127,16 @@ pub(crate) async fn led_hid_task<R: HidReaderWrapper, Out: OutputPin>(
     light_service: &mut LightService<Out>,
 ) {
     if !light_service.enabled {
+        // The led indicator is still read, split peripherals might mirror it
+        #[cfg(feature = "split")]
+        hid_read_led(keyboard_hid_reader, false).await;
+        #[cfg(not(feature = "split"))]
         loop {
             embassy_time::Timer::after_secs(u32::MAX as u64).await;
         }
     } else {
         select(
-            hid_read_led(keyboard_hid_reader),
+            hid_read_led(keyboard_hid_reader, true),
             led_service_task(light_service),
         )
         .await;
@@ -81,6 +163,39 @@ pub(crate) struct LedIndicator {
     _reserved: u8,
 }
 
+impl LedIndicator {
+    /// Binary-coded layer number on capslock(bit 0), numslock(bit 1) and scrolllock(bit 2)
+    fn from_layer(layer: u8) -> Self {
+        Self::new()
+            .with_capslock(layer & 0b001 != 0)
+            .with_numslock(layer & 0b010 != 0)
+            .with_scrolllock(layer & 0b100 != 0)
+    }
+
+    /// Set the state of the given lock LED
+    fn with_lock_led(self, led: LockLed, state: bool) -> Self {
+        match led {
+            LockLed::CapsLock => self.with_capslock(state),
+            LockLed::NumsLock => self.with_numslock(state),
+            LockLed::ScrollLock => self.with_scrolllock(state),
+        }
+    }
+}
+
+impl LayerIndicator {
+    /// Whether the layer, instead of the lock states, is shown on the lock LEDs
+    fn show_layer(self, led_indicator: LedIndicator, is_default_layer: bool) -> bool {
+        match self {
+            LayerIndicator::LockFirst => {
+                !(led_indicator.capslock()
+                    || led_indicator.numslock()
+                    || led_indicator.scrolllock())
+            }
+            LayerIndicator::LayerFirst => !is_default_layer,
+        }
+    }
+}
+
 /// A single LED
 ///
 /// In general, a single LED can be used for capslock/numslock, or in a LED matrix.
@@ -143,6 +258,17 @@ pub(crate) struct LightService<P: OutputPin> {
     capslock: Option<SingleLED<P>>,
     scrolllock: Option<SingleLED<P>>,
     numslock: Option<SingleLED<P>>,
+    layer_indicator: Option<LayerIndicator>,
+    /// Latest led indicator from the host
+    led_indicator: LedIndicator,
+    /// Active layer, and whether it's the default layer
+    layer: (u8, bool),
+    /// All LEDs are off while the battery saver is on
+    battery_saver: bool,
+    /// Lock LED which shows the game mode
+    game_mode_indicator: Option<LockLed>,
+    /// Whether the game mode is on
+    game_mode: bool,
 }
 
 // Implement on/off function for LightService
@@ -177,6 +303,12 @@ impl<P: OutputPin> LightService<P> {
             capslock: capslock_pin.map(|p| SingleLED::new(p)),
             scrolllock: scrolllock_pin.map(|p| SingleLED::new(p)),
             numslock: numslock_pin.map(|p| SingleLED::new(p)),
+            layer_indicator: None,
+            led_indicator: LedIndicator::new(),
+            layer: (0, true),
+            battery_saver: false,
+            game_mode_indicator: None,
+            game_mode: false,
         }
     }
 
@@ -194,6 +326,12 @@ impl<P: OutputPin> LightService<P> {
             capslock: light_config.capslock.map(|p| SingleLED::new(p)),
             scrolllock: light_config.scrolllock.map(|p| SingleLED::new(p)),
             numslock: light_config.numslock.map(|p| SingleLED::new(p)),
+            layer_indicator: light_config.layer_indicator,
+            led_indicator: LedIndicator::new(),
+            layer: (0, true),
+            battery_saver: false,
+            game_mode_indicator: light_config.game_mode_indicator,
+            game_mode: false,
         }
     }
 }
@@ -204,10 +342,73 @@ impl<P: OutputPin> LightService<P> {
     impl_led_on_off!(numslock, set_numslock);
 
     pub(crate) fn set_leds(&mut self, led_indicator: LedIndicator) -> Result<(), P::Error> {
-        self.set_capslock(led_indicator.capslock())?;
-        self.set_numslock(led_indicator.numslock())?;
-        self.set_scrolllock(led_indicator.scrolllock())?;
+        self.led_indicator = led_indicator;
+        self.update_leds()
+    }
+
+    pub(crate) fn set_layer(&mut self, layer: u8, is_default: bool) -> Result<(), P::Error> {
+        self.layer = (layer, is_default);
+        self.update_leds()
+    }
+
+    pub(crate) fn set_battery_saver(&mut self, battery_saver: bool) -> Result<(), P::Error> {
+        self.battery_saver = battery_saver;
+        self.update_leds()
+    }
+
+    pub(crate) fn set_game_mode(&mut self, game_mode: bool) -> Result<(), P::Error> {
+        self.game_mode = game_mode;
+        self.update_leds()
+    }
+
+    /// Update the LEDs with the lock states or the active layer, all LEDs are off while the battery saver is on
+    fn update_leds(&mut self) -> Result<(), P::Error> {
+        let mut leds = match self.layer_indicator {
+            _ if self.battery_saver => LedIndicator::new(),
+            Some(mode) if mode.show_layer(self.led_indicator, self.layer.1) => {
+                LedIndicator::from_layer(self.layer.0)
+            }
+            _ => self.led_indicator,
+        };
+        // The game mode indicator takes over its LED
+        if let Some(led) = self.game_mode_indicator {
+            if !self.battery_saver {
+                leds = leds.with_lock_led(led, self.game_mode);
+            }
+        }
+        self.set_capslock(leds.capslock())?;
+        self.set_numslock(leds.numslock())?;
+        self.set_scrolllock(leds.scrolllock())?;
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_layer_indicator() {
+        let layer_3 = LedIndicator::from_layer(3);
+        assert!(layer_3.capslock() && layer_3.numslock() && !layer_3.scrolllock());
+
+        // Lock first: the layer is shown only when all locks are off
+        let caps = LedIndicator::new().with_capslock(true);
+        assert!(LayerIndicator::LockFirst.show_layer(LedIndicator::new(), false));
+        assert!(LayerIndicator::LockFirst.show_layer(LedIndicator::new(), true));
+        assert!(!LayerIndicator::LockFirst.show_layer(caps, false));
+
+        // Layer first: the lock states are shown only on the default layer
+        assert!(LayerIndicator::LayerFirst.show_layer(caps, false));
+        assert!(!LayerIndicator::LayerFirst.show_layer(caps, true));
+    }
+
+    #[test]
+    fn test_game_mode_indicator() {
+        let caps = LedIndicator::new().with_capslock(true);
+        let leds = caps.with_lock_led(LockLed::ScrollLock, true);
+        assert!(leds.capslock() && leds.scrolllock() && !leds.numslock());
+        assert!(!caps.with_lock_led(LockLed::CapsLock, false).capslock());
+    }
+}
@@ -0,0 +1,237 @@
+//! Magic keycodes, which remap keys globally, such as swapping Ctrl and CapsLock.
+//!
+//! The remap is applied to keycodes after keymap resolution on every layer, so it affects all layers.
+//! The state can be changed at runtime by magic keycodes and it's saved to storage.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use bitfield_struct::bitfield;
+
+use crate::config::MagicConfig;
+use crate::keycode::KeyCode;
+
+/// Value of `SAVED_MAGIC_STATE` when no magic state is saved in storage
+pub(crate) const NO_SAVED_MAGIC_STATE: u32 = u32::MAX;
+
+/// Magic state loaded from storage, it overrides the initial state in `MagicConfig`
+pub(crate) static SAVED_MAGIC_STATE: AtomicU32 = AtomicU32::new(NO_SAVED_MAGIC_STATE);
+
+/// Modifier bits, in the order of hid modifier byte
+const LCTRL: u8 = 1 << 0;
+const LALT: u8 = 1 << 2;
+const LGUI: u8 = 1 << 3;
+const RCTRL: u8 = 1 << 4;
+const RALT: u8 = 1 << 6;
+const RGUI: u8 = 1 << 7;
+
+#[bitfield(u16, order = Lsb)]
+#[derive(Eq, PartialEq)]
+pub(crate) struct MagicState {
+    swap_ctrl_caps: bool,
+    capslock_as_ctrl: bool,
+    swap_lalt_lgui: bool,
+    swap_ralt_rgui: bool,
+    no_gui: bool,
+    swap_grave_esc: bool,
+    swap_backslash_backspace: bool,
+    swap_lctl_lgui: bool,
+    swap_rctl_rgui: bool,
+    swap_escape_caps: bool,
+    #[bits(6)]
+    _reserved: u8,
+}
+
+impl From<MagicConfig> for MagicState {
+    fn from(config: MagicConfig) -> Self {
+        MagicState::new()
+            .with_swap_ctrl_caps(config.swap_ctrl_caps)
+            .with_capslock_as_ctrl(config.capslock_as_ctrl)
+            .with_swap_lalt_lgui(config.swap_lalt_lgui)
+            .with_swap_ralt_rgui(config.swap_ralt_rgui)
+            .with_no_gui(config.no_gui)
+            .with_swap_grave_esc(config.swap_grave_esc)
+            .with_swap_backslash_backspace(config.swap_backslash_backspace)
+            .with_swap_lctl_lgui(config.swap_lctl_lgui)
+            .with_swap_rctl_rgui(config.swap_rctl_rgui)
+            .with_swap_escape_caps(config.swap_escape_caps)
+    }
+}
+
+impl MagicState {
+    /// Load the magic state, the state saved in storage takes precedence over the config
+    pub(crate) fn load(config: MagicConfig) -> Self {
+        match SAVED_MAGIC_STATE.load(Ordering::Relaxed) {
+            NO_SAVED_MAGIC_STATE => config.into(),
+            saved => MagicState::from_bits(saved as u16),
+        }
+    }
+
+    /// Remap a basic keycode
+    pub(crate) fn remap_keycode(self, key: KeyCode) -> KeyCode {
+        match key {
+            KeyCode::CapsLock if self.swap_ctrl_caps() || self.capslock_as_ctrl() => KeyCode::LCtrl,
+            KeyCode::CapsLock if self.swap_escape_caps() => KeyCode::Escape,
+            KeyCode::LCtrl if self.swap_ctrl_caps() => KeyCode::CapsLock,
+            KeyCode::Escape if self.swap_escape_caps() => KeyCode::CapsLock,
+            KeyCode::Escape if self.swap_grave_esc() => KeyCode::Grave,
+            KeyCode::Grave if self.swap_grave_esc() => KeyCode::Escape,
+            KeyCode::Backslash if self.swap_backslash_backspace() => KeyCode::Backspace,
+            KeyCode::Backspace if self.swap_backslash_backspace() => KeyCode::Backslash,
+            _ => key,
+        }
+    }
+
+    /// Remap modifier bits which are going to be registered
+    pub(crate) fn remap_modifiers(self, modifiers: u8) -> u8 {
+        let mut modifiers = self.swap_modifiers(modifiers);
+        if self.no_gui() {
+            modifiers &= !(LGUI | RGUI);
+        }
+        modifiers
+    }
+
+    /// Remap modifier bits which are going to be unregistered.
+    /// Disabled GUI is not masked here, so that a GUI registered before `no_gui` is turned on can be released.
+    pub(crate) fn remap_released_modifiers(self, modifiers: u8) -> u8 {
+        self.swap_modifiers(modifiers)
+    }
+
+    fn swap_modifiers(self, modifiers: u8) -> u8 {
+        let mut modifiers = modifiers;
+        if self.swap_lalt_lgui() {
+            modifiers = swap_bits(modifiers, LALT, LGUI);
+        }
+        if self.swap_ralt_rgui() {
+            modifiers = swap_bits(modifiers, RALT, RGUI);
+        }
+        if self.swap_lctl_lgui() {
+            modifiers = swap_bits(modifiers, LCTRL, LGUI);
+        }
+        if self.swap_rctl_rgui() {
+            modifiers = swap_bits(modifiers, RCTRL, RGUI);
+        }
+        modifiers
+    }
+
+    /// Process a magic keycode, returns whether the state is changed
+    pub(crate) fn process(&mut self, key: KeyCode) -> bool {
+        let old = *self;
+        match key {
+            KeyCode::MagicSwapControlCapsLock => self.set_swap_ctrl_caps(true),
+            KeyCode::MagicUnswapControlCapsLock => self.set_swap_ctrl_caps(false),
+            KeyCode::MagicToggleControlCapsLock => self.set_swap_ctrl_caps(!self.swap_ctrl_caps()),
+            KeyCode::MagicCapsLockAsControlOff => self.set_capslock_as_ctrl(false),
+            KeyCode::MagicCapsLockAsControlOn => self.set_capslock_as_ctrl(true),
+            KeyCode::MagicSwapLaltLGui => self.set_swap_lalt_lgui(true),
+            KeyCode::MagicUnswapLaltLGui => self.set_swap_lalt_lgui(false),
+            KeyCode::MagicSwapRaltRGui => self.set_swap_ralt_rgui(true),
+            KeyCode::MagicUnswapRaltRGui => self.set_swap_ralt_rgui(false),
+            KeyCode::MagicGuiOn => self.set_no_gui(false),
+            KeyCode::MagicGuiOff => self.set_no_gui(true),
+            KeyCode::MagicToggleGui => self.set_no_gui(!self.no_gui()),
+            KeyCode::MagicSwapGraveEsc => self.set_swap_grave_esc(true),
+            KeyCode::MagicUnswapGraveEsc => self.set_swap_grave_esc(false),
+            KeyCode::MagicSwapBackslashBackspace => self.set_swap_backslash_backspace(true),
+            KeyCode::MagicUnswapBackslashBackspace => self.set_swap_backslash_backspace(false),
+            KeyCode::MagicToggleBackslashBackspace => {
+                self.set_swap_backslash_backspace(!self.swap_backslash_backspace())
+            }
+            KeyCode::MagicSwapAltGui => {
+                self.set_swap_lalt_lgui(true);
+                self.set_swap_ralt_rgui(true);
+            }
+            KeyCode::MagicUnswapAltGui => {
+                self.set_swap_lalt_lgui(false);
+                self.set_swap_ralt_rgui(false);
+            }
+            KeyCode::MagicToggleAltGui => {
+                let swap = !self.swap_lalt_lgui();
+                self.set_swap_lalt_lgui(swap);
+                self.set_swap_ralt_rgui(swap);
+            }
+            KeyCode::MagicSwapLctlLGui => self.set_swap_lctl_lgui(true),
+            KeyCode::MagicUnswapLctlLGui => self.set_swap_lctl_lgui(false),
+            KeyCode::MagicSwapRctlRGui => self.set_swap_rctl_rgui(true),
+            KeyCode::MagicUnswapRctlRGui => self.set_swap_rctl_rgui(false),
+            KeyCode::MagicSwapCtlGui => {
+                self.set_swap_lctl_lgui(true);
+                self.set_swap_rctl_rgui(true);
+            }
+            KeyCode::MagicUnswapCtlGui => {
+                self.set_swap_lctl_lgui(false);
+                self.set_swap_rctl_rgui(false);
+            }
+            KeyCode::MagicToggleCtlGui => {
+                let swap = !self.swap_lctl_lgui();
+                self.set_swap_lctl_lgui(swap);
+                self.set_swap_rctl_rgui(swap);
+            }
+            KeyCode::MagicSwapEscapeCapsLock => self.set_swap_escape_caps(true),
+            KeyCode::MagicUnswapEscapeCapsLock => self.set_swap_escape_caps(false),
+            KeyCode::MagicToggleEscapeCapsLock => {
+                self.set_swap_escape_caps(!self.swap_escape_caps())
+            }
+            KeyCode::MagicClear => *self = MagicState::new(),
+            _ => warn!("Magic keycode {:?} is not supported", key),
+        }
+        *self != old
+    }
+}
+
+fn swap_bits(modifiers: u8, a: u8, b: u8) -> u8 {
+    let mut swapped = modifiers & !(a | b);
+    if modifiers & a != 0 {
+        swapped |= b;
+    }
+    if modifiers & b != 0 {
+        swapped |= a;
+    }
+    swapped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_magic_swap_ctrl_caps() {
+        let mut magic = MagicState::new();
+        assert_eq!(magic.remap_keycode(KeyCode::CapsLock), KeyCode::CapsLock);
+
+        assert!(magic.process(KeyCode::MagicSwapControlCapsLock));
+        assert!(!magic.process(KeyCode::MagicSwapControlCapsLock));
+        assert_eq!(magic.remap_keycode(KeyCode::CapsLock), KeyCode::LCtrl);
+        assert_eq!(magic.remap_keycode(KeyCode::LCtrl), KeyCode::CapsLock);
+        assert_eq!(magic.remap_keycode(KeyCode::A), KeyCode::A);
+
+        assert!(magic.process(KeyCode::MagicClear));
+        assert_eq!(magic.remap_keycode(KeyCode::CapsLock), KeyCode::CapsLock);
+    }
+
+    #[test]
+    fn test_magic_modifiers() {
+        let mut magic = MagicState::new();
+        magic.process(KeyCode::MagicSwapLaltLGui);
+        assert_eq!(magic.remap_modifiers(LALT), LGUI);
+        assert_eq!(magic.remap_modifiers(LGUI | RALT), LALT | RALT);
+
+        magic.process(KeyCode::MagicToggleGui);
+        // LAlt is swapped to LGui, which is disabled
+        assert_eq!(magic.remap_modifiers(LALT | LCTRL), LCTRL);
+        assert_eq!(magic.remap_modifiers(RGUI), 0);
+        // Disabled GUI can still be released
+        assert_eq!(magic.remap_released_modifiers(RGUI), RGUI);
+    }
+
+    #[test]
+    fn test_magic_state_from_config() {
+        let config = MagicConfig {
+            swap_escape_caps: true,
+            ..Default::default()
+        };
+        let magic = MagicState::from(config);
+        assert_eq!(magic.remap_keycode(KeyCode::CapsLock), KeyCode::Escape);
+        assert_eq!(magic.remap_keycode(KeyCode::Escape), KeyCode::CapsLock);
+        assert_eq!(MagicState::from_bits(magic.into_bits()), magic);
+    }
+}
@@ -1,15 +1,22 @@
 use crate::{
+    channel_stats::{record_blocked, StatsChannel},
     debounce::{DebounceState, DebouncerTrait},
     event::KeyEvent,
     keyboard::KEY_EVENT_CHANNEL,
-    CONNECTION_STATE,
+    latency_trace::record_scan,
+    BATTERY_SAVER, CONNECTION_STATE,
 };
 use core::future::Future;
-use embassy_time::{Instant, Timer};
+use embassy_time::{Duration, Instant, Timer};
 use embedded_hal::digital::{InputPin, OutputPin};
 #[cfg(feature = "async_matrix")]
 use {embassy_futures::select::select_slice, embedded_hal_async::digital::Wait, heapless::Vec};
 
+/// Default interval between two matrix scans
+const DEFAULT_SCAN_INTERVAL: Duration = Duration::from_micros(100);
+/// Minimal idle scan interval while the battery saver is on
+const BATTERY_SAVER_SCAN_INTERVAL: Duration = Duration::from_millis(5);
+
 /// MatrixTrait is the trait for keyboard matrix.
 ///
 /// The keyboard matrix is a 2D matrix of keys, the matrix does the scanning and saves the result to each key's `KeyState`.
@@ -114,8 +121,18 @@ pub struct Matrix<
     debouncer: D,
     /// Key state matrix
     key_states: [[KeyState; INPUT_PIN_NUM]; OUTPUT_PIN_NUM],
+    /// Disabled positions, which are skipped when scanning
+    disabled: [[bool; INPUT_PIN_NUM]; OUTPUT_PIN_NUM],
     /// Start scanning
     scan_start: Option<Instant>,
+    /// Scan interval while any key is pressed or debouncing
+    scan_fast_interval: Duration,
+    /// Scan interval while all keys are released
+    scan_idle_interval: Duration,
+    /// Time to wait before the first scan, so that the pins settle after boot
+    boot_settle: Option<Duration>,
+    /// Time to hold all output pins at the inactive level after an output pin is scanned
+    discharge: Duration,
 }
 
 impl<
@@ -138,7 +155,59 @@ impl<
             output_pins,
             debouncer,
             key_states: [[KeyState::new(); INPUT_PIN_NUM]; OUTPUT_PIN_NUM],
+            disabled: [[false; INPUT_PIN_NUM]; OUTPUT_PIN_NUM],
             scan_start: None,
+            scan_fast_interval: DEFAULT_SCAN_INTERVAL,
+            scan_idle_interval: DEFAULT_SCAN_INTERVAL,
+            boot_settle: None,
+            discharge: Duration::from_ticks(0),
+        }
+    }
+
+    /// Set the scan intervals. The fast interval is used while any key is pressed or debouncing,
+    /// the idle interval is used when all keys are released. `None` keeps the default interval.
+    pub fn set_scan_interval(&mut self, fast: Option<Duration>, idle: Option<Duration>) {
+        if let Some(fast) = fast {
+            self.scan_fast_interval = fast;
+        }
+        if let Some(idle) = idle {
+            self.scan_idle_interval = idle;
+        }
+    }
+
+    /// Set the time to wait before the first scan after boot.
+    ///
+    /// All keys are treated as released before the first scan, pins which are still settling could be read as
+    /// a bouncing press otherwise.
+    pub fn set_boot_settle(&mut self, settle: Option<Duration>) {
+        self.boot_settle = settle;
+    }
+
+    /// Set the time to hold all output pins at the inactive level after an output pin is scanned.
+    ///
+    /// On fast MCUs, the residual charge on the matrix lines might be read as a ghost key when the next output pin is selected.
+    /// The discharge time drains the charge through the pull resistors before the next pin is selected, default is 0.
+    pub fn set_discharge_time(&mut self, discharge: Option<Duration>) {
+        if let Some(discharge) = discharge {
+            self.discharge = discharge;
+        }
+    }
+
+    /// Disable the given matrix positions `(row, col)`.
+    ///
+    /// Disabled positions are skipped when scanning, so no key event is emitted for them.
+    pub fn disable_positions(&mut self, positions: &[(u8, u8)]) {
+        for &(row, col) in positions {
+            #[cfg(feature = "col2row")]
+            let (out_idx, in_idx) = (col as usize, row as usize);
+            #[cfg(not(feature = "col2row"))]
+            let (out_idx, in_idx) = (row as usize, col as usize);
+
+            if out_idx < OUTPUT_PIN_NUM && in_idx < INPUT_PIN_NUM {
+                self.disabled[out_idx][in_idx] = true;
+            } else {
+                warn!("Disabled position ({}, {}) is out of the matrix", row, col);
+            }
         }
     }
 }
@@ -193,17 +262,30 @@ impl<
 
     /// Do matrix scanning, the result is stored in matrix's key_state field.
     async fn scan(&mut self) {
+        if let Some(settle) = self.boot_settle.take() {
+            info!("Wait {}ms for matrix pins to settle", settle.as_millis());
+            Timer::after(settle).await;
+        }
+
         info!("Matrix scanning");
         loop {
             #[cfg(feature = "async_matrix")]
             self.wait_for_key().await;
 
+            // Whether any key is pressed or debouncing, which requires fast scanning
+            let mut active = false;
+
             // Scan matrix and send report
             for (out_idx, out_pin) in self.output_pins.iter_mut().enumerate() {
                 // Pull up output pin, wait 1us ensuring the change comes into effect
                 out_pin.set_high().ok();
                 Timer::after_micros(1).await;
                 for (in_idx, in_pin) in self.input_pins.iter_mut().enumerate() {
+                    // Skip disabled positions
+                    if self.disabled[out_idx][in_idx] {
+                        continue;
+                    }
+
                     // Check input pins and debounce
                     let debounce_state = self.debouncer.detect_change_with_debounce(
                         in_idx,
@@ -212,6 +294,12 @@ impl<
                         &self.key_states[out_idx][in_idx],
                     );
 
+                    if !matches!(debounce_state, DebounceState::Ignored)
+                        || self.key_states[out_idx][in_idx].pressed
+                    {
+                        active = true;
+                    }
+
                     match debounce_state {
                         DebounceState::Debounced => {
                             self.key_states[out_idx][in_idx].toggle_pressed();
@@ -222,6 +310,8 @@ impl<
                             let (row, col, key_state) =
                                 (out_idx, in_idx, self.key_states[out_idx][in_idx]);
 
+                            record_scan();
+                            record_blocked(StatsChannel::KeyEvent, KEY_EVENT_CHANNEL.is_full());
                             KEY_EVENT_CHANNEL
                                 .send(KeyEvent {
                                     row: row as u8,
@@ -240,9 +330,22 @@ impl<
                     }
                 }
                 out_pin.set_low().ok();
+
+                // Drain the residual charge before selecting the next output pin
+                if self.discharge.as_ticks() > 0 {
+                    Timer::after(self.discharge).await;
+                }
             }
 
-            embassy_time::Timer::after_micros(100).await;
+            // Scan fast while any key is active, so that the first press is debounced and the release is caught quickly
+            let interval = if active {
+                self.scan_fast_interval
+            } else if BATTERY_SAVER.load(core::sync::atomic::Ordering::Relaxed) {
+                self.scan_idle_interval.max(BATTERY_SAVER_SCAN_INTERVAL)
+            } else {
+                self.scan_idle_interval
+            };
+            embassy_time::Timer::after(interval).await;
         }
     }
 
@@ -0,0 +1,122 @@
+//! Tap-hold keys pressed while a real modifier is held are resolved as their hold action immediately,
+//! so deliberate modifier chords aren't delayed by the tap-hold resolution.
+
+use heapless::Vec;
+
+use crate::action::{Action, KeyAction};
+use crate::event::KeyEvent;
+
+/// Maximum number of keys which are tracked by `TapHoldConfig::disable_during_modifier` at the same time
+const MODIFIER_CHORD_KEY_NUM: usize = 8;
+
+/// State of `TapHoldConfig::disable_during_modifier`
+#[derive(Default)]
+pub(crate) struct ModifierChordState {
+    /// Positions of held real modifier keys, modifier tap-hold keys are not included
+    modifiers: Vec<(u8, u8), MODIFIER_CHORD_KEY_NUM>,
+    /// Positions of tap-hold keys which act as their hold action until released
+    holds: Vec<(u8, u8), MODIFIER_CHORD_KEY_NUM>,
+}
+
+impl ModifierChordState {
+    /// Resolve the key action of the key event.
+    ///
+    /// A tap-hold key pressed while a real modifier is held is resolved as its hold action, until it's released.
+    pub(crate) fn resolve(&mut self, key_event: KeyEvent, action: KeyAction) -> KeyAction {
+        let pos = (key_event.row, key_event.col);
+        let hold_action = match action {
+            KeyAction::TapHold(_, hold_action) => Some(hold_action),
+            KeyAction::LayerTapHold(_, layer_num) => Some(Action::LayerOn(layer_num)),
+            KeyAction::ModifierTapHold(_, modifier) => Some(Action::Modifier(modifier)),
+            _ => None,
+        };
+
+        match hold_action {
+            Some(hold_action) => {
+                if key_event.pressed {
+                    if !self.modifiers.is_empty() && self.holds.push(pos).is_ok() {
+                        return KeyAction::Single(hold_action);
+                    }
+                } else if let Some(i) = self.holds.iter().position(|&p| p == pos) {
+                    self.holds.swap_remove(i);
+                    return KeyAction::Single(hold_action);
+                }
+            }
+            None => {
+                let is_modifier = match action {
+                    KeyAction::Single(Action::Key(k)) => k.is_modifier(),
+                    KeyAction::Single(Action::Modifier(_)) => true,
+                    _ => false,
+                };
+                if is_modifier {
+                    if key_event.pressed {
+                        self.modifiers.push(pos).ok();
+                    } else if let Some(i) = self.modifiers.iter().position(|&p| p == pos) {
+                        self.modifiers.swap_remove(i);
+                    }
+                }
+            }
+        }
+        action
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::keycode::{KeyCode, ModifierCombination};
+
+    fn key_event(row: u8, col: u8, pressed: bool) -> KeyEvent {
+        KeyEvent { row, col, pressed }
+    }
+
+    #[test]
+    fn test_modifier_chord() {
+        let ctrl = KeyAction::Single(Action::Key(KeyCode::LCtrl));
+        let shift = ModifierCombination::new_from(false, false, false, true, false);
+        let hrm = KeyAction::ModifierTapHold(Action::Key(KeyCode::F), shift);
+        let lt = KeyAction::LayerTapHold(Action::Key(KeyCode::Space), 1);
+        let mut state = ModifierChordState::default();
+
+        // Without a real modifier held, tap-hold keys are untouched
+        assert!(matches!(
+            state.resolve(key_event(1, 3, true), hrm),
+            KeyAction::ModifierTapHold(_, _)
+        ));
+        assert!(matches!(
+            state.resolve(key_event(1, 3, false), hrm),
+            KeyAction::ModifierTapHold(_, _)
+        ));
+
+        // Hold a real Ctrl, tap-hold keys act as their hold action immediately
+        assert!(matches!(
+            state.resolve(key_event(3, 0, true), ctrl),
+            KeyAction::Single(_)
+        ));
+        assert!(matches!(
+            state.resolve(key_event(1, 3, true), hrm),
+            KeyAction::Single(Action::Modifier(m)) if m == shift
+        ));
+        assert!(matches!(
+            state.resolve(key_event(3, 4, true), lt),
+            KeyAction::Single(Action::LayerOn(1))
+        ));
+
+        // Release Ctrl, the resolved keys act as hold until they're released
+        state.resolve(key_event(3, 0, false), ctrl);
+        assert!(matches!(
+            state.resolve(key_event(1, 3, false), hrm),
+            KeyAction::Single(Action::Modifier(_))
+        ));
+        assert!(matches!(
+            state.resolve(key_event(3, 4, false), lt),
+            KeyAction::Single(Action::LayerOn(1))
+        ));
+
+        // Tap-hold works normally again
+        assert!(matches!(
+            state.resolve(key_event(1, 3, true), hrm),
+            KeyAction::ModifierTapHold(_, _)
+        ));
+    }
+}
@@ -0,0 +1,106 @@
+//! `Action::LayerMomentaryOrToggle`, which activates the layer while held and toggles it on by double tap.
+
+use embassy_time::{Duration, Instant};
+
+use crate::double_tap::DoubleTap;
+use crate::event::KeyEvent;
+
+/// State of `Action::LayerMomentaryOrToggle`
+#[derive(Default)]
+pub(crate) struct MomentaryOrToggleState {
+    /// Double tap detection of the key, which is identified by (position, layer)
+    tap: DoubleTap<((u8, u8), u8)>,
+    /// Layer which is toggled on by double tap
+    toggled: Option<u8>,
+}
+
+impl MomentaryOrToggleState {
+    pub(crate) fn press(&mut self, key_event: KeyEvent, layer_num: u8, now: Instant) {
+        self.tap
+            .press(((key_event.row, key_event.col), layer_num), now);
+    }
+
+    /// Another key is pressed, the held key is used as a momentary layer key and the last tap is cancelled
+    pub(crate) fn interrupt(&mut self, key_event: KeyEvent) {
+        if let Some((pos, _)) = self.tap.key() {
+            if pos != (key_event.row, key_event.col) {
+                self.tap.interrupt();
+            }
+        }
+    }
+
+    /// The key is released, returns whether the layer should stay activated
+    pub(crate) fn release(
+        &mut self,
+        key_event: KeyEvent,
+        layer_num: u8,
+        now: Instant,
+        tapping_term: Duration,
+        double_tap_timeout: Duration,
+    ) -> bool {
+        if self.toggled == Some(layer_num) {
+            // Pressing the key again turns the toggled layer off
+            self.toggled = None;
+            self.tap = DoubleTap::default();
+            return false;
+        }
+
+        let key = ((key_event.row, key_event.col), layer_num);
+        if self.tap.release(key, now, tapping_term, double_tap_timeout) {
+            self.toggled = Some(layer_num);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn key_event(row: u8, col: u8, pressed: bool) -> KeyEvent {
+        KeyEvent { row, col, pressed }
+    }
+
+    #[test]
+    fn test_momentary_or_toggle() {
+        let tapping_term = Duration::from_millis(250);
+        let double_tap_timeout = Duration::from_millis(200);
+        let ms = |t: u64| Instant::from_millis(t);
+        let release = key_event(3, 0, false);
+        let mut state = MomentaryOrToggleState::default();
+
+        // Hold: momentary layer, deactivated on release
+        state.press(key_event(3, 0, true), 1, ms(1000));
+        assert!(!state.release(release, 1, ms(1500), tapping_term, double_tap_timeout));
+
+        // A short press interrupted by another key is also momentary
+        state.press(key_event(3, 0, true), 1, ms(2000));
+        state.interrupt(key_event(0, 0, true));
+        assert!(!state.release(release, 1, ms(2100), tapping_term, double_tap_timeout));
+
+        // Single tap: nothing
+        state.press(key_event(3, 0, true), 1, ms(3000));
+        assert!(!state.release(release, 1, ms(3050), tapping_term, double_tap_timeout));
+        // Another tap after the double tap timeout is still a single tap
+        state.press(key_event(3, 0, true), 1, ms(3500));
+        assert!(!state.release(release, 1, ms(3550), tapping_term, double_tap_timeout));
+
+        // A key typed between the taps cancels the double tap
+        state.interrupt(key_event(0, 0, true));
+        state.interrupt(key_event(3, 0, true));
+        state.press(key_event(3, 0, true), 1, ms(3600));
+        assert!(!state.release(release, 1, ms(3650), tapping_term, double_tap_timeout));
+
+        // Double tap: the layer is toggled on
+        state.interrupt(key_event(3, 0, true));
+        state.press(key_event(3, 0, true), 1, ms(3750));
+        assert!(state.release(release, 1, ms(3800), tapping_term, double_tap_timeout));
+
+        // Press again, the toggled layer is turned off
+        state.interrupt(key_event(3, 0, true));
+        state.press(key_event(3, 0, true), 1, ms(5000));
+        assert!(!state.release(release, 1, ms(5050), tapping_term, double_tap_timeout));
+    }
+}
@@ -0,0 +1,101 @@
+//! Release guard of consumer and system control keys.
+//!
+//! Some hosts miss the release report of these keys, which keeps the key stuck on the host,
+//! so the release is sent again after a max hold time.
+
+use embassy_time::{Duration, Instant};
+
+use crate::usb::descriptor::CompositeReportType;
+
+/// Guard of consumer and system control keys which might be stuck on the host, if the release report is missed by the host
+#[derive(Default)]
+pub(crate) struct OtherKeyReleaseGuard {
+    /// Press time of the consumer key
+    media: Option<Instant>,
+    /// Press time of the system control key
+    system: Option<Instant>,
+}
+
+impl OtherKeyReleaseGuard {
+    pub(crate) fn press(&mut self, report_type: CompositeReportType, now: Instant) {
+        match report_type {
+            CompositeReportType::Media => self.media = Some(now),
+            CompositeReportType::System => self.system = Some(now),
+            _ => (),
+        }
+    }
+
+    /// Time when the guarded keys should be checked
+    pub(crate) fn deadline(&self, max_hold: Duration) -> Option<Instant> {
+        match (self.media, self.system) {
+            (Some(media), Some(system)) => Some(media.min(system) + max_hold),
+            (media, system) => media.or(system).map(|t| t + max_hold),
+        }
+    }
+
+    /// Check the guarded keys after `max_hold`, returns whether the release of (consumer, system control) should be sent again.
+    ///
+    /// Keys which are still held are checked again after another `max_hold`.
+    pub(crate) fn expire(
+        &mut self,
+        now: Instant,
+        max_hold: Duration,
+        media_held: bool,
+        system_held: bool,
+    ) -> (bool, bool) {
+        let expire = |pressed_at: &mut Option<Instant>, held: bool| match *pressed_at {
+            Some(t) if now.saturating_duration_since(t) >= max_hold => {
+                *pressed_at = if held { Some(now) } else { None };
+                !held
+            }
+            _ => false,
+        };
+        (
+            expire(&mut self.media, media_held),
+            expire(&mut self.system, system_held),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_other_key_release_guard() {
+        let max_hold = Duration::from_secs(10);
+        let ms = |t: u64| Instant::from_millis(t);
+        let mut guard = OtherKeyReleaseGuard::default();
+        assert_eq!(guard.deadline(max_hold), None);
+
+        // A tapped consumer key: its release is sent again after max hold
+        guard.press(CompositeReportType::Media, ms(1000));
+        assert_eq!(guard.deadline(max_hold), Some(ms(11000)));
+        assert_eq!(
+            guard.expire(ms(5000), max_hold, false, false),
+            (false, false)
+        );
+        assert_eq!(
+            guard.expire(ms(11000), max_hold, false, false),
+            (true, false)
+        );
+        assert_eq!(guard.deadline(max_hold), None);
+
+        // A held system control key is never released, it's checked again later
+        guard.press(CompositeReportType::System, ms(20000));
+        assert_eq!(
+            guard.expire(ms(30000), max_hold, false, true),
+            (false, false)
+        );
+        assert_eq!(guard.deadline(max_hold), Some(ms(40000)));
+        assert_eq!(
+            guard.expire(ms(40000), max_hold, false, false),
+            (false, true)
+        );
+        assert_eq!(guard.deadline(max_hold), None);
+
+        // Mouse reports aren't guarded
+        guard.press(CompositeReportType::Mouse, ms(50000));
+        assert_eq!(guard.deadline(max_hold), None);
+    }
+}
@@ -0,0 +1,89 @@
+//! Per-key min interval, which suppresses presses of a key coming faster than a human can type,
+//! such as the chatter of a worn switch.
+
+use embassy_time::{Duration, Instant};
+use heapless::Vec;
+
+use crate::event::KeyEvent;
+
+/// Maximum number of keys which are tracked by the per-key min interval at the same time
+const PRESS_INTERVAL_KEY_NUM: usize = 8;
+
+/// Suppresses presses of a key which come faster than `BehaviorConfig::per_key_min_interval`
+#[derive(Default)]
+pub(crate) struct PressIntervalFilter {
+    /// Recently reported presses, (position, time of the press)
+    recent: Vec<((u8, u8), Instant), PRESS_INTERVAL_KEY_NUM>,
+    /// Positions of keys whose press is suppressed, the release of them is suppressed as well
+    suppressed: Vec<(u8, u8), PRESS_INTERVAL_KEY_NUM>,
+}
+
+impl PressIntervalFilter {
+    /// Filter the key event by the time of last reported press of the same key.
+    ///
+    /// Returns whether the key event should be processed.
+    pub(crate) fn filter(
+        &mut self,
+        key_event: KeyEvent,
+        now: Instant,
+        min_interval: Duration,
+    ) -> bool {
+        let pos = (key_event.row, key_event.col);
+        if key_event.pressed {
+            // Forget presses which are out of the min interval
+            self.recent.retain(|(_, t)| {
+                now.checked_duration_since(*t)
+                    .is_some_and(|elapsed| elapsed < min_interval)
+            });
+            let recently_pressed = self.recent.iter().any(|(p, _)| *p == pos);
+            if recently_pressed && self.suppressed.push(pos).is_ok() {
+                debug!(
+                    "Suppress press of ({}, {}) within min interval",
+                    pos.0, pos.1
+                );
+                return false;
+            }
+            if !recently_pressed {
+                if self.recent.is_full() {
+                    self.recent.remove(0);
+                }
+                self.recent.push((pos, now)).ok();
+            }
+        } else if let Some(i) = self.suppressed.iter().position(|&p| p == pos) {
+            self.suppressed.swap_remove(i);
+            return false;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn key_event(row: u8, col: u8, pressed: bool) -> KeyEvent {
+        KeyEvent { row, col, pressed }
+    }
+
+    #[test]
+    fn test_press_interval_filter() {
+        let min_interval = Duration::from_millis(30);
+        let ms = |t: u64| Instant::from_millis(t);
+        let mut filter = PressIntervalFilter::default();
+
+        // The first press and the release are processed
+        assert!(filter.filter(key_event(0, 1, true), ms(100), min_interval));
+        assert!(filter.filter(key_event(0, 1, false), ms(110), min_interval));
+
+        // Pressed again within the min interval, both the press and the release are suppressed
+        assert!(!filter.filter(key_event(0, 1, true), ms(120), min_interval));
+        // Other keys are not affected
+        assert!(filter.filter(key_event(2, 2, true), ms(125), min_interval));
+        assert!(filter.filter(key_event(2, 2, false), ms(126), min_interval));
+        assert!(!filter.filter(key_event(0, 1, false), ms(128), min_interval));
+
+        // Pressed after the min interval of the last reported press, processed normally
+        assert!(filter.filter(key_event(0, 1, true), ms(135), min_interval));
+        assert!(filter.filter(key_event(0, 1, false), ms(150), min_interval));
+    }
+}
@@ -0,0 +1,19 @@
+//! Keys which are repeated by the firmware while they're held.
+
+use embassy_time::{Duration, Instant};
+
+use crate::action::Action;
+use crate::event::KeyEvent;
+
+/// A held `KeyAction::Repeating` key
+#[derive(Clone, Copy)]
+pub(crate) struct RepeatingAction {
+    /// The key event which starts repeating
+    pub(crate) key_event: KeyEvent,
+    /// The action to be repeated
+    pub(crate) action: Action,
+    /// Repeat interval
+    pub(crate) interval: Duration,
+    /// Time of next repeat
+    pub(crate) next: Instant,
+}
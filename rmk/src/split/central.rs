@@ -11,17 +11,21 @@ use crate::action::KeyAction;
 #[cfg(feature = "_nrf_ble")]
 use crate::ble::nrf::initialize_nrf_ble_keyboard_and_run;
 use crate::config::RmkConfig;
-#[cfg(not(feature = "rapid_debouncer"))]
+#[cfg(not(any(feature = "rapid_debouncer", feature = "verified_debouncer")))]
 use crate::debounce::default_bouncer::DefaultDebouncer;
 #[cfg(feature = "rapid_debouncer")]
 use crate::debounce::fast_debouncer::RapidDebouncer;
+#[cfg(feature = "verified_debouncer")]
+use crate::debounce::verified_eager_debouncer::VerifiedEagerDebouncer;
 use crate::debounce::{DebounceState, DebouncerTrait};
+use crate::direct_pin::debouncer_index;
 use crate::event::KeyEvent;
 use crate::keyboard::{Keyboard, KEYBOARD_REPORT_CHANNEL, KEY_EVENT_CHANNEL};
 use crate::keymap::KeyMap;
 use crate::light::LightService;
 use crate::matrix::{KeyState, MatrixTrait};
 use crate::run_usb_keyboard;
+use crate::split::driver::MIRROR_INDICATORS;
 use crate::usb::KeyboardUsbDevice;
 use crate::via::process::VialService;
 
@@ -73,11 +77,21 @@ pub async fn run_rmk_split_central<
     // Create the debouncer, use COL2ROW by default
     #[cfg(all(feature = "col2row", feature = "rapid_debouncer"))]
     let debouncer: RapidDebouncer<CENTRAL_ROW, CENTRAL_COL> = RapidDebouncer::new();
+    #[cfg(all(feature = "col2row", feature = "verified_debouncer"))]
+    let debouncer: VerifiedEagerDebouncer<CENTRAL_ROW, CENTRAL_COL> = VerifiedEagerDebouncer::new();
     #[cfg(all(not(feature = "col2row"), feature = "rapid_debouncer"))]
     let debouncer: RapidDebouncer<CENTRAL_COL, CENTRAL_ROW> = RapidDebouncer::new();
-    #[cfg(all(feature = "col2row", not(feature = "rapid_debouncer")))]
+    #[cfg(all(not(feature = "col2row"), feature = "verified_debouncer"))]
+    let debouncer: VerifiedEagerDebouncer<CENTRAL_COL, CENTRAL_ROW> = VerifiedEagerDebouncer::new();
+    #[cfg(all(
+        feature = "col2row",
+        not(any(feature = "rapid_debouncer", feature = "verified_debouncer"))
+    ))]
     let debouncer: DefaultDebouncer<CENTRAL_ROW, CENTRAL_COL> = DefaultDebouncer::new();
-    #[cfg(all(not(feature = "col2row"), not(feature = "rapid_debouncer")))]
+    #[cfg(all(
+        not(feature = "col2row"),
+        not(any(feature = "rapid_debouncer", feature = "verified_debouncer"))
+    ))]
     let debouncer: DefaultDebouncer<CENTRAL_COL, CENTRAL_ROW> = DefaultDebouncer::new();
 
     // Keyboard matrix, use COL2ROW by default
@@ -158,13 +172,27 @@ pub async fn run_rmk_split_central_direct_pin<
     #[cfg(not(feature = "_esp_ble"))] spawner: Spawner,
 ) -> ! {
     info!("Debouncer");
-    // Create the debouncer, use COL2ROW by default
-    #[cfg(feature = "rapid_debouncer")]
+    // Create the debouncer, which follows the orientation of the normal matrix
+    #[cfg(all(feature = "col2row", feature = "rapid_debouncer"))]
+    let debouncer: RapidDebouncer<CENTRAL_ROW, CENTRAL_COL> = RapidDebouncer::new();
+    #[cfg(all(feature = "col2row", feature = "verified_debouncer"))]
+    let debouncer: VerifiedEagerDebouncer<CENTRAL_ROW, CENTRAL_COL> = VerifiedEagerDebouncer::new();
+    #[cfg(all(
+        feature = "col2row",
+        not(any(feature = "rapid_debouncer", feature = "verified_debouncer"))
+    ))]
+    let debouncer: DefaultDebouncer<CENTRAL_ROW, CENTRAL_COL> = DefaultDebouncer::new();
+    #[cfg(all(not(feature = "col2row"), feature = "rapid_debouncer"))]
     let debouncer: RapidDebouncer<CENTRAL_COL, CENTRAL_ROW> = RapidDebouncer::new();
-    #[cfg(not(feature = "rapid_debouncer"))]
+    #[cfg(all(not(feature = "col2row"), feature = "verified_debouncer"))]
+    let debouncer: VerifiedEagerDebouncer<CENTRAL_COL, CENTRAL_ROW> = VerifiedEagerDebouncer::new();
+    #[cfg(all(
+        not(feature = "col2row"),
+        not(any(feature = "rapid_debouncer", feature = "verified_debouncer"))
+    ))]
     let debouncer: DefaultDebouncer<CENTRAL_COL, CENTRAL_ROW> = DefaultDebouncer::new();
 
-    // Keyboard matrix, use COL2ROW by default
+    // Keyboard matrix
     let matrix = CentralDirectPinMatrix::<
         _,
         _,
@@ -222,6 +250,11 @@ pub async fn run_rmk_split_central_with_matrix<
     #[cfg(feature = "_nrf_ble")] central_addr: [u8; 6],
     #[cfg(not(feature = "_esp_ble"))] spawner: Spawner,
 ) -> ! {
+    MIRROR_INDICATORS.store(
+        keyboard_config.split_config.mirror_indicators,
+        core::sync::atomic::Ordering::Release,
+    );
+
     #[cfg(feature = "_nrf_ble")]
     let fut = initialize_nrf_ble_keyboard_and_run::<_, _, D, TOTAL_ROW, TOTAL_COL, NUM_LAYER>(
         matrix,
@@ -321,10 +354,15 @@ pub async fn initialize_usb_split_central_and_run<
             &keyboard_report_sender,
             keyboard_config.behavior_config,
         ),
-        KeyboardUsbDevice::new(usb_driver, keyboard_config.usb_config),
+        KeyboardUsbDevice::new(
+            usb_driver,
+            keyboard_config.usb_config,
+            keyboard_config.host_config.primary_device,
+        ),
         VialService::new(&keymap, keyboard_config.vial_config),
         LightService::from_config(keyboard_config.light_config),
     );
+    keyboard.set_consumer_max_hold(keyboard_config.host_config.consumer_max_hold);
 
     // Run usb keyboard
     run_usb_keyboard(
@@ -336,6 +374,7 @@ pub async fn initialize_usb_split_central_and_run<
         &mut light_service,
         &mut vial_service,
         &keyboard_report_receiver,
+        keyboard_config.host_config.report_coalesce,
     )
     .await
 }
@@ -634,9 +673,11 @@ impl<
                             direct_pin.is_high().ok().unwrap_or_default()
                         };
 
+                        let (in_idx, out_idx) =
+                            debouncer_index(row_idx, col_idx, cfg!(feature = "col2row"));
                         let debounce_state = self.debouncer.detect_change_with_debounce(
-                            col_idx,
-                            row_idx,
+                            in_idx,
+                            out_idx,
                             pin_state,
                             &self.key_states[row_idx][col_idx],
                         );
@@ -1,11 +1,23 @@
-use core::sync::atomic::Ordering;
+use core::sync::atomic::{AtomicBool, Ordering};
 
 ///! The abstracted driver layer of the split keyboard.
 ///!
 use super::SplitMessage;
+use crate::channel_stats::{record_blocked, StatsChannel};
+use crate::light::{LED_INDICATOR_STATE, SPLIT_LED_INDICATOR_CHANNEL};
 use crate::CONNECTION_STATE;
 use crate::{event::KeyEvent, keyboard::KEY_EVENT_CHANNEL};
-use embassy_futures::select::select;
+use embassy_futures::select::{select3, Either3};
+use heapless::Vec;
+
+/// Maximum number of out-of-order key events buffered in central
+const SPLIT_REORDER_BUFFER_SIZE: usize = 8;
+
+/// Time to wait for a missing key event before processing the buffered key events
+const SPLIT_REORDER_TIMEOUT_MS: u64 = 20;
+
+/// Whether the led indicator state from the host is forwarded to peripherals, set by the central
+pub(crate) static MIRROR_INDICATORS: AtomicBool = AtomicBool::new(false);
 
 #[derive(Debug, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -27,6 +39,89 @@ pub(crate) trait SplitWriter {
     async fn write(&mut self, message: &SplitMessage) -> Result<usize, SplitDriverError>;
 }
 
+/// Restore the order of key events from a peripheral, using the sequence number in `SplitMessage::Key`.
+///
+/// Key events which arrive earlier than expected are buffered until the missing ones arrive.
+/// If the buffer is full, or the missing key event doesn't arrive in time, the gap is skipped.
+pub(crate) struct KeyEventSequencer {
+    /// Sequence number of the next key event to be processed
+    next_seq: Option<u8>,
+    /// Buffered key events, with their sequence numbers
+    pending: Vec<(u8, KeyEvent), SPLIT_REORDER_BUFFER_SIZE>,
+}
+
+impl KeyEventSequencer {
+    pub(crate) fn new() -> Self {
+        Self {
+            next_seq: None,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Restart the sequence, the next pushed key event is processed first
+    pub(crate) fn reset(&mut self) {
+        self.next_seq = None;
+        self.pending.clear();
+    }
+
+    /// Whether there are buffered key events waiting for a missing one
+    pub(crate) fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Push a received key event.
+    ///
+    /// Call `pop` until it returns `None` after every push, which guarantees that there's always space in the buffer.
+    pub(crate) fn push(&mut self, seq: u8, event: KeyEvent) {
+        let next = *self.next_seq.get_or_insert(seq);
+        // Distance to the expected sequence number, handles the wrap-around
+        let distance = seq.wrapping_sub(next) as i8;
+        if distance < 0 {
+            if distance >= -(SPLIT_REORDER_BUFFER_SIZE as i8) {
+                warn!(
+                    "Stale split key event dropped, seq: {}, expected: {}",
+                    seq, next
+                );
+                return;
+            }
+            // Too far behind, the peripheral has probably restarted. Re-sync the sequence number
+            warn!(
+                "Split key event sequence re-synced, seq: {}, expected: {}",
+                seq, next
+            );
+            self.pending.clear();
+            self.next_seq = Some(seq);
+        }
+        if self.pending.push((seq, event)).is_err() {
+            error!("Split key event buffer is full, seq: {}", seq);
+        }
+    }
+
+    /// Pop the next key event in order.
+    ///
+    /// If `force` is true or the buffer is full, the missing key events are skipped.
+    pub(crate) fn pop(&mut self, force: bool) -> Option<KeyEvent> {
+        let next = self.next_seq?;
+        let idx = if let Some(idx) = self.pending.iter().position(|(s, _)| *s == next) {
+            idx
+        } else if (force || self.pending.is_full()) && !self.pending.is_empty() {
+            // Skip the gap, use the earliest buffered key event
+            let (idx, (seq, _)) = self
+                .pending
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, (s, _))| s.wrapping_sub(next))?;
+            warn!("Split key event {} is lost, skip to {}", next, seq);
+            idx
+        } else {
+            return None;
+        };
+        let (seq, event) = self.pending.swap_remove(idx);
+        self.next_seq = Some(seq.wrapping_add(1));
+        Some(event)
+    }
+}
+
 /// PeripheralMatrixMonitor runs in central.
 /// It reads split message from peripheral and updates key matrix cache of the peripheral.
 ///
@@ -46,6 +141,8 @@ pub(crate) struct PeripheralMatrixMonitor<
     receiver: R,
     /// Peripheral id
     id: usize,
+    /// Keep the order of key events from the peripheral
+    sequencer: KeyEventSequencer,
 }
 
 impl<
@@ -57,7 +154,50 @@ impl<
     > PeripheralMatrixMonitor<ROW, COL, ROW_OFFSET, COL_OFFSET, R>
 {
     pub(crate) fn new(receiver: R, id: usize) -> Self {
-        Self { receiver, id }
+        Self {
+            receiver,
+            id,
+            sequencer: KeyEventSequencer::new(),
+        }
+    }
+
+    /// Forward a key event from the peripheral to `KEY_EVENT_CHANNEL`
+    async fn forward_key_event(e: KeyEvent) {
+        // Check row/col
+        if e.row as usize > ROW || e.col as usize > COL {
+            error!("Invalid peripheral row/col: {} {}", e.row, e.col);
+            return;
+        }
+
+        if CONNECTION_STATE.load(core::sync::atomic::Ordering::Acquire) {
+            // Only when the connection is established, send the key event.
+            record_blocked(StatsChannel::KeyEvent, KEY_EVENT_CHANNEL.is_full());
+            KEY_EVENT_CHANNEL
+                .send(KeyEvent {
+                    row: e.row + ROW_OFFSET as u8,
+                    col: e.col + COL_OFFSET as u8,
+                    pressed: e.pressed,
+                })
+                .await;
+        } else {
+            warn!(
+                "Key event from peripheral is ignored because the connection is not established."
+            );
+        }
+    }
+
+    /// Send the led indicator state to the peripheral, if the indicator mirroring is enabled
+    async fn sync_led_indicator(&mut self, led_indicator: u8) {
+        if !MIRROR_INDICATORS.load(Ordering::Acquire) {
+            return;
+        }
+        if let Err(e) = self
+            .receiver
+            .write(&SplitMessage::LedIndicator(led_indicator))
+            .await
+        {
+            error!("SplitDriver write error: {:?}", e);
+        }
     }
 
     /// Run the monitor.
@@ -73,35 +213,66 @@ impl<
         {
             error!("SplitDriver write error: {:?}", e);
         }
+        self.sync_led_indicator(LED_INDICATOR_STATE.load(Ordering::Acquire))
+            .await;
+        let mut led_subscriber = SPLIT_LED_INDICATOR_CHANNEL.subscriber().ok();
+        if led_subscriber.is_none() {
+            warn!(
+                "Too many peripherals, led indicator isn't mirrored to peripheral {}",
+                self.id
+            );
+        }
         loop {
-            match select(self.receiver.read(), embassy_time::Timer::after_millis(500)).await {
-                embassy_futures::select::Either::First(read_result) => match read_result {
+            // If there're buffered key events, wait for the missing one for a short time only
+            let timeout = if self.sequencer.is_pending() {
+                SPLIT_REORDER_TIMEOUT_MS
+            } else {
+                500
+            };
+            let led_fut = async {
+                match led_subscriber.as_mut() {
+                    Some(s) => s.next_message_pure().await,
+                    None => core::future::pending().await,
+                }
+            };
+            match select3(
+                self.receiver.read(),
+                embassy_time::Timer::after_millis(timeout),
+                led_fut,
+            )
+            .await
+            {
+                Either3::First(read_result) => match read_result {
                     Ok(received_message) => {
                         debug!("Received peripheral message: {:?}", received_message);
-                        if let SplitMessage::Key(e) = received_message {
-                            // Check row/col
-                            if e.row as usize > ROW || e.col as usize > COL {
-                                error!("Invalid peripheral row/col: {} {}", e.row, e.col);
-                                continue;
+                        trace_event!("split message from peripheral", received_message);
+                        match received_message {
+                            SplitMessage::Key(e, seq) => {
+                                self.sequencer.push(seq, e);
+                                while let Some(e) = self.sequencer.pop(false) {
+                                    Self::forward_key_event(e).await;
+                                }
                             }
-
-                            if CONNECTION_STATE.load(core::sync::atomic::Ordering::Acquire) {
-                                // Only when the connection is established, send the key event.
-                                KEY_EVENT_CHANNEL
-                                    .send(KeyEvent {
-                                        row: e.row + ROW_OFFSET as u8,
-                                        col: e.col + COL_OFFSET as u8,
-                                        pressed: e.pressed,
-                                    })
-                                    .await;
-                            } else {
-                                warn!("Key event from peripheral is ignored because the connection is not established.");
+                            SplitMessage::KeySequenceReset => {
+                                // The peripheral has restarted or reconnected, process the key events of the last session first
+                                while let Some(e) = self.sequencer.pop(true) {
+                                    Self::forward_key_event(e).await;
+                                }
+                                self.sequencer.reset();
                             }
+                            _ => (),
                         }
                     }
                     Err(e) => error!("Peripheral message read error: {:?}", e),
                 },
-                embassy_futures::select::Either::Second(_) => {
+                Either3::Second(_) => {
+                    if self.sequencer.is_pending() {
+                        // The missing key events don't arrive in time, process the buffered ones
+                        while let Some(e) = self.sequencer.pop(true) {
+                            Self::forward_key_event(e).await;
+                        }
+                        continue;
+                    }
                     // Sync ConnectionState every 500ms
                     conn_state = CONNECTION_STATE.load(Ordering::Acquire);
                     if let Err(e) = self
@@ -112,7 +283,99 @@ impl<
                         error!("SplitDriver write error: {}", e);
                     };
                 }
+                Either3::Third(led_indicator) => self.sync_led_indicator(led_indicator).await,
             }
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn key_event(col: u8) -> KeyEvent {
+        KeyEvent {
+            row: 0,
+            col,
+            pressed: true,
+        }
+    }
+
+    /// Push a key event which uses the sequence number as its col, return cols of all popped key events
+    fn push(sequencer: &mut KeyEventSequencer, seq: u8) -> std::vec::Vec<u8> {
+        sequencer.push(seq, key_event(seq));
+        pop(sequencer, false)
+    }
+
+    fn pop(sequencer: &mut KeyEventSequencer, force: bool) -> std::vec::Vec<u8> {
+        let mut cols = std::vec::Vec::new();
+        while let Some(e) = sequencer.pop(force) {
+            cols.push(e.col);
+        }
+        cols
+    }
+
+    #[test]
+    fn test_sequencer_reorder() {
+        let mut sequencer = KeyEventSequencer::new();
+        assert_eq!(push(&mut sequencer, 0), [0]);
+        // 2 arrives earlier than 1
+        assert!(push(&mut sequencer, 2).is_empty());
+        assert!(sequencer.is_pending());
+        assert_eq!(push(&mut sequencer, 1), [1, 2]);
+        assert!(!sequencer.is_pending());
+    }
+
+    #[test]
+    fn test_sequencer_wrap_around() {
+        let mut sequencer = KeyEventSequencer::new();
+        assert_eq!(push(&mut sequencer, 254), [254]);
+        assert!(push(&mut sequencer, 0).is_empty());
+        assert_eq!(push(&mut sequencer, 255), [255, 0]);
+        assert_eq!(push(&mut sequencer, 1), [1]);
+    }
+
+    #[test]
+    fn test_sequencer_timeout() {
+        let mut sequencer = KeyEventSequencer::new();
+        assert_eq!(push(&mut sequencer, 0), [0]);
+        assert!(push(&mut sequencer, 2).is_empty());
+        // 1 doesn't arrive in time, skip it
+        assert_eq!(pop(&mut sequencer, true), [2]);
+        // The late key event is dropped
+        assert!(push(&mut sequencer, 1).is_empty());
+        assert_eq!(push(&mut sequencer, 3), [3]);
+    }
+
+    #[test]
+    fn test_sequencer_buffer_full() {
+        let mut sequencer = KeyEventSequencer::new();
+        assert_eq!(push(&mut sequencer, 0), [0]);
+        for seq in 2..SPLIT_REORDER_BUFFER_SIZE as u8 + 1 {
+            assert!(push(&mut sequencer, seq).is_empty());
+        }
+        // The buffer is full, 1 is skipped
+        assert_eq!(
+            push(&mut sequencer, SPLIT_REORDER_BUFFER_SIZE as u8 + 1),
+            (2..SPLIT_REORDER_BUFFER_SIZE as u8 + 2).collect::<std::vec::Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_sequencer_peripheral_restart() {
+        let mut sequencer = KeyEventSequencer::new();
+        for seq in 0..5 {
+            assert_eq!(push(&mut sequencer, seq), [seq]);
+        }
+        // Without a reset, the key events of the restarted peripheral look stale
+        assert!(push(&mut sequencer, 0).is_empty());
+
+        sequencer.reset();
+        assert_eq!(push(&mut sequencer, 0), [0]);
+        assert_eq!(push(&mut sequencer, 1), [1]);
+
+        // A peripheral which is far behind is re-synced even without a reset
+        assert_eq!(push(&mut sequencer, 200), [200]);
+        assert_eq!(push(&mut sequencer, 201), [201]);
+    }
+}
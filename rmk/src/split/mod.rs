@@ -20,11 +20,17 @@ pub const SPLIT_MESSAGE_MAX_SIZE: usize = SplitMessage::POSTCARD_MAX_SIZE + 4;
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, MaxSize)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub(crate) enum SplitMessage {
-    /// Key event from peripheral to central
-    Key(KeyEvent),
-    /// Led state, on/off, from central to peripheral
-    LedState(bool),
+    /// Key event from peripheral to central, with a sequence number.
+    /// The sequence number increases by 1 for every key event and wraps around at `u8::MAX`,
+    /// so that the central can process the key events in order.
+    Key(KeyEvent, u8),
+    /// Led indicator state from the host, in bits of the hid led report, from central to peripheral.
+    /// It's sent only when `mirror_indicators` is enabled in the central's `SplitConfig`
+    LedIndicator(u8),
     /// The central connection state, true if central has been connected to host.
     /// This message is sync from central to peripheral
     ConnectionState(bool),
+    /// Sent by the peripheral before its first key event after it starts or reconnects,
+    /// the central restarts the key event sequence of the peripheral
+    KeySequenceReset,
 }
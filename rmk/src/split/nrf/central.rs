@@ -8,6 +8,7 @@ use embassy_sync::{
 use nrf_softdevice::ble::{central, gatt_client, Address, AddressType};
 
 use crate::{
+    channel_stats::{record_dropped, StatsChannel},
     split::{
         driver::{PeripheralMatrixMonitor, SplitDriverError, SplitReader, SplitWriter},
         SplitMessage, SPLIT_MESSAGE_MAX_SIZE,
@@ -126,6 +127,7 @@ pub(crate) async fn run_ble_client(
                     Ok(split_message) => {
                         info!("Received split message from peripheral: {}", split_message);
                         if let Err(e) = receive_sender.try_send(split_message) {
+                            record_dropped(StatsChannel::SplitMessage);
                             error!("BLE_SYNC_CHANNEL send message error: {:?}", e);
                         }
                     }
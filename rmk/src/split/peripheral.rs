@@ -1,14 +1,19 @@
 use super::driver::{SplitReader, SplitWriter};
 use super::SplitMessage;
-#[cfg(not(feature = "rapid_debouncer"))]
+use crate::config::LightConfig;
+#[cfg(not(any(feature = "rapid_debouncer", feature = "verified_debouncer")))]
 use crate::debounce::default_bouncer::DefaultDebouncer;
 #[cfg(feature = "rapid_debouncer")]
 use crate::debounce::fast_debouncer::RapidDebouncer;
+#[cfg(feature = "verified_debouncer")]
+use crate::debounce::verified_eager_debouncer::VerifiedEagerDebouncer;
 use crate::debounce::DebouncerTrait;
 use crate::direct_pin::DirectPinMatrix;
 use crate::keyboard::KEY_EVENT_CHANNEL;
+use crate::light::{led_service_task, LedIndicator, LightService, LED_CHANNEL};
 use crate::matrix::{Matrix, MatrixTrait};
 use crate::CONNECTION_STATE;
+use core::future::Future;
 #[cfg(feature = "_nrf_ble")]
 use embassy_executor::Spawner;
 use embassy_futures::select::select;
@@ -48,11 +53,21 @@ pub async fn run_rmk_split_peripheral<
     // Create the debouncer, use COL2ROW by default
     #[cfg(all(feature = "col2row", feature = "rapid_debouncer"))]
     let debouncer = RapidDebouncer::<ROW, COL>::new();
-    #[cfg(all(feature = "col2row", not(feature = "rapid_debouncer")))]
+    #[cfg(all(feature = "col2row", feature = "verified_debouncer"))]
+    let debouncer = VerifiedEagerDebouncer::<ROW, COL>::new();
+    #[cfg(all(
+        feature = "col2row",
+        not(any(feature = "rapid_debouncer", feature = "verified_debouncer"))
+    ))]
     let debouncer = DefaultDebouncer::<ROW, COL>::new();
     #[cfg(all(not(feature = "col2row"), feature = "rapid_debouncer"))]
     let debouncer = RapidDebouncer::<COL, ROW>::new();
-    #[cfg(all(not(feature = "col2row"), not(feature = "rapid_debouncer")))]
+    #[cfg(all(not(feature = "col2row"), feature = "verified_debouncer"))]
+    let debouncer = VerifiedEagerDebouncer::<COL, ROW>::new();
+    #[cfg(all(
+        not(feature = "col2row"),
+        not(any(feature = "rapid_debouncer", feature = "verified_debouncer"))
+    ))]
     let debouncer = DefaultDebouncer::<COL, ROW>::new();
 
     // Keyboard matrix, use COL2ROW by default
@@ -105,10 +120,24 @@ pub async fn run_rmk_split_peripheral_direct_pin<
     #[cfg(not(feature = "_nrf_ble"))] serial: S,
     #[cfg(feature = "_nrf_ble")] spawner: Spawner,
 ) {
-    // Create the debouncer, use COL2ROW by default
-    #[cfg(feature = "rapid_debouncer")]
+    // Create the debouncer, which follows the orientation of the normal matrix
+    #[cfg(all(feature = "col2row", feature = "rapid_debouncer"))]
+    let debouncer = RapidDebouncer::<ROW, COL>::new();
+    #[cfg(all(feature = "col2row", feature = "verified_debouncer"))]
+    let debouncer = VerifiedEagerDebouncer::<ROW, COL>::new();
+    #[cfg(all(
+        feature = "col2row",
+        not(any(feature = "rapid_debouncer", feature = "verified_debouncer"))
+    ))]
+    let debouncer = DefaultDebouncer::<ROW, COL>::new();
+    #[cfg(all(not(feature = "col2row"), feature = "rapid_debouncer"))]
     let debouncer = RapidDebouncer::<COL, ROW>::new();
-    #[cfg(not(feature = "rapid_debouncer"))]
+    #[cfg(all(not(feature = "col2row"), feature = "verified_debouncer"))]
+    let debouncer = VerifiedEagerDebouncer::<COL, ROW>::new();
+    #[cfg(all(
+        not(feature = "col2row"),
+        not(any(feature = "rapid_debouncer", feature = "verified_debouncer"))
+    ))]
     let debouncer = DefaultDebouncer::<COL, ROW>::new();
 
     // Keyboard matrix
@@ -169,14 +198,36 @@ pub async fn run_rmk_split_peripheral_with_matrix<
     .await;
 }
 
+/// Run the split peripheral service together with indicator LEDs on the peripheral.
+///
+/// The LEDs mirror the led indicator state(capslock/numslock/scrolllock) of the host,
+/// which is forwarded by the central when `mirror_indicators` is enabled in its `SplitConfig`.
+///
+/// # Arguments
+///
+/// * `peripheral` - the split peripheral service, such as the future returned by `run_rmk_split_peripheral`
+/// * `light_config` - indicator LEDs on the peripheral
+pub async fn run_with_indicator_leds<Out: OutputPin>(
+    peripheral: impl Future<Output = ()>,
+    light_config: LightConfig<Out>,
+) {
+    let mut light_service = LightService::from_config(light_config);
+    select(peripheral, led_service_task(&mut light_service)).await;
+}
+
 /// The split peripheral instance.
 pub(crate) struct SplitPeripheral<S: SplitWriter + SplitReader> {
     split_driver: S,
+    /// Sequence number of the next key event sent to central, `None` if no key event has been sent yet
+    seq: Option<u8>,
 }
 
 impl<S: SplitWriter + SplitReader> SplitPeripheral<S> {
     pub(crate) fn new(split_driver: S) -> Self {
-        Self { split_driver }
+        Self {
+            split_driver,
+            seq: None,
+        }
     }
 
     /// Run the peripheral keyboard service.
@@ -187,14 +238,23 @@ impl<S: SplitWriter + SplitReader> SplitPeripheral<S> {
         loop {
             match select(self.split_driver.read(), KEY_EVENT_CHANNEL.receive()).await {
                 embassy_futures::select::Either::First(m) => match m {
-                    // Currently only handle the central state message
-                    Ok(split_message) => match split_message {
-                        SplitMessage::ConnectionState(state) => {
-                            info!("Received connection state update: {}", state);
-                            CONNECTION_STATE.store(state, core::sync::atomic::Ordering::Release);
+                    Ok(split_message) => {
+                        trace_event!("split message from central", split_message);
+                        match split_message {
+                            SplitMessage::ConnectionState(state) => {
+                                info!("Received connection state update: {}", state);
+                                CONNECTION_STATE
+                                    .store(state, core::sync::atomic::Ordering::Release);
+                            }
+                            SplitMessage::LedIndicator(bits) => {
+                                // If there's no indicator LED on the peripheral, the state is just dropped
+                                if LED_CHANNEL.try_send(LedIndicator::from_bits(bits)).is_err() {
+                                    debug!("LED channel is full, led indicator is dropped");
+                                }
+                            }
+                            _ => (),
                         }
-                        _ => (),
-                    },
+                    }
                     Err(e) => {
                         error!("Split message read error: {:?}", e);
                     }
@@ -202,8 +262,35 @@ impl<S: SplitWriter + SplitReader> SplitPeripheral<S> {
                 embassy_futures::select::Either::Second(e) => {
                     // Only send the key event if the connection is established
                     if CONNECTION_STATE.load(core::sync::atomic::Ordering::Acquire) {
+                        trace_event!("key event to central", e);
                         info!("Writing split message to central");
-                        self.split_driver.write(&SplitMessage::Key(e)).await.ok();
+                        let (seq, synced) = match self.seq {
+                            Some(seq) => (seq, true),
+                            None => {
+                                // Restart the sequence in central, which may still expect the sequence number of the last session
+                                match self
+                                    .split_driver
+                                    .write(&SplitMessage::KeySequenceReset)
+                                    .await
+                                {
+                                    Ok(_) => (0, true),
+                                    Err(e) => {
+                                        error!("Split key sequence reset write error: {:?}", e);
+                                        (0, false)
+                                    }
+                                }
+                            }
+                        };
+                        self.split_driver
+                            .write(&SplitMessage::Key(e, seq))
+                            .await
+                            .ok();
+                        // If the reset isn't received by central, it's sent again before the next key event
+                        self.seq = if synced {
+                            Some(seq.wrapping_add(1))
+                        } else {
+                            None
+                        };
                     }
                 }
             }
@@ -5,6 +5,7 @@ use crate::config::StorageConfig;
 use byteorder::{BigEndian, ByteOrder};
 use core::fmt::Debug;
 use core::ops::Range;
+use core::sync::atomic::Ordering;
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::channel::Channel;
 use embedded_storage::nor_flash::NorFlash;
@@ -17,10 +18,12 @@ use sequential_storage::{
 #[cfg(feature = "_nrf_ble")]
 use {crate::ble::nrf::bonder::BondInfo, core::mem};
 
+use crate::input_device::rotary_encoder::{ENCODER_RESOLUTION, MAX_ENCODER_NUM};
 use crate::keyboard_macro::MACRO_SPACE_SIZE;
+use crate::magic::SAVED_MAGIC_STATE;
 use crate::{
     action::KeyAction,
-    via::keycode_convert::{from_via_keycode, to_via_keycode},
+    via::keycode_convert::{from_via_keycode, to_via_keycode, DEFAULT_KEYMAP_ONLY_KEYCODE},
 };
 
 use self::eeconfig::EeKeymapConfig;
@@ -39,6 +42,9 @@ pub(crate) enum FlashOperationMessage {
     // Current active BLE profile number
     #[cfg(feature = "_nrf_ble")]
     ActiveBleProfile(u8),
+    // Adapted tx power of the given BLE profile, in dBm
+    #[cfg(feature = "_nrf_ble")]
+    ProfileTxPower(u8, i8),
     // Clear the storage
     Reset,
     // Clear info of given slot number
@@ -57,6 +63,13 @@ pub(crate) enum FlashOperationMessage {
     },
     // Current saved connection type
     ConnectionType(u8),
+    // Runtime resolution of an encoder
+    EncoderResolution {
+        id: u8,
+        resolution: u8,
+    },
+    // State of magic keycodes
+    MagicState(u16),
 }
 
 #[repr(u32)]
@@ -69,6 +82,10 @@ pub(crate) enum StorageKeys {
     KeymapKeys,
     MacroData,
     ConnectionType,
+    EncoderConfig,
+    MagicState,
+    #[cfg(feature = "_nrf_ble")]
+    ProfileTxPower = 0xED,
     #[cfg(feature = "_nrf_ble")]
     ActiveBleProfile = 0xEE,
     #[cfg(feature = "_nrf_ble")]
@@ -85,6 +102,10 @@ impl StorageKeys {
             4 => Some(StorageKeys::LayoutConfig),
             5 => Some(StorageKeys::KeymapKeys),
             6 => Some(StorageKeys::MacroData),
+            8 => Some(StorageKeys::EncoderConfig),
+            9 => Some(StorageKeys::MagicState),
+            #[cfg(feature = "_nrf_ble")]
+            0xED => Some(StorageKeys::ProfileTxPower),
             #[cfg(feature = "_nrf_ble")]
             0xEF => Some(StorageKeys::BleBondInfo),
             _ => None,
@@ -100,16 +121,31 @@ pub(crate) enum StorageData {
     KeymapKey(KeymapKey),
     MacroData([u8; MACRO_SPACE_SIZE]),
     ConnectionType(u8),
+    // Encoder id, resolution
+    EncoderConfig(u8, u8),
+    MagicState(u16),
     #[cfg(feature = "_nrf_ble")]
     BondInfo(BondInfo),
     #[cfg(feature = "_nrf_ble")]
     ActiveBleProfile(u8),
+    // Profile, tx power in dBm
+    #[cfg(feature = "_nrf_ble")]
+    ProfileTxPower(u8, i8),
 }
 
 pub(crate) fn get_bond_info_key(slot_num: u8) -> u32 {
     0x2000 + slot_num as u32
 }
 
+#[cfg(feature = "_nrf_ble")]
+pub(crate) fn get_profile_tx_power_key(profile: u8) -> u32 {
+    0x2100 + profile as u32
+}
+
+pub(crate) fn get_encoder_config_key(id: u8) -> u32 {
+    0x3000 + id as u32
+}
+
 pub(crate) fn get_keymap_key<const ROW: usize, const COL: usize, const NUM_LAYER: usize>(
     row: usize,
     col: usize,
@@ -148,7 +184,11 @@ impl Value<'_> for StorageData {
             }
             StorageData::KeymapKey(k) => {
                 buffer[0] = StorageKeys::KeymapKeys as u8;
-                BigEndian::write_u16(&mut buffer[1..3], to_via_keycode(k.action));
+                let via_keycode = match k.action {
+                    Some(action) => to_via_keycode(action),
+                    None => DEFAULT_KEYMAP_ONLY_KEYCODE,
+                };
+                BigEndian::write_u16(&mut buffer[1..3], via_keycode);
                 buffer[3] = k.layer as u8;
                 buffer[4] = k.col as u8;
                 buffer[5] = k.row as u8;
@@ -167,6 +207,17 @@ impl Value<'_> for StorageData {
                 buffer[1] = *ty;
                 Ok(2)
             }
+            StorageData::EncoderConfig(id, resolution) => {
+                buffer[0] = StorageKeys::EncoderConfig as u8;
+                buffer[1] = *id;
+                buffer[2] = *resolution;
+                Ok(3)
+            }
+            StorageData::MagicState(state) => {
+                buffer[0] = StorageKeys::MagicState as u8;
+                BigEndian::write_u16(&mut buffer[1..3], *state);
+                Ok(3)
+            }
             #[cfg(feature = "_nrf_ble")]
             StorageData::BondInfo(b) => {
                 if buffer.len() < 121 {
@@ -186,6 +237,13 @@ impl Value<'_> for StorageData {
                 buffer[1] = *slot_num;
                 Ok(2)
             }
+            #[cfg(feature = "_nrf_ble")]
+            StorageData::ProfileTxPower(profile, dbm) => {
+                buffer[0] = StorageKeys::ProfileTxPower as u8;
+                buffer[1] = *profile;
+                buffer[2] = *dbm as u8;
+                Ok(3)
+            }
         }
     }
 
@@ -224,7 +282,12 @@ impl Value<'_> for StorageData {
                     }))
                 }
                 StorageKeys::KeymapKeys => {
-                    let action = from_via_keycode(BigEndian::read_u16(&buffer[1..3]));
+                    let via_keycode = BigEndian::read_u16(&buffer[1..3]);
+                    let action = if via_keycode == DEFAULT_KEYMAP_ONLY_KEYCODE {
+                        None
+                    } else {
+                        Some(from_via_keycode(via_keycode))
+                    };
                     let layer = buffer[3] as usize;
                     let col = buffer[4] as usize;
                     let row = buffer[5] as usize;
@@ -246,6 +309,18 @@ impl Value<'_> for StorageData {
                     Ok(StorageData::MacroData(buf))
                 }
                 StorageKeys::ConnectionType => Ok(StorageData::ConnectionType(buffer[1])),
+                StorageKeys::EncoderConfig => {
+                    if buffer.len() < 3 {
+                        return Err(SerializationError::InvalidData);
+                    }
+                    Ok(StorageData::EncoderConfig(buffer[1], buffer[2]))
+                }
+                StorageKeys::MagicState => {
+                    if buffer.len() < 3 {
+                        return Err(SerializationError::InvalidData);
+                    }
+                    Ok(StorageData::MagicState(BigEndian::read_u16(&buffer[1..3])))
+                }
                 #[cfg(feature = "_nrf_ble")]
                 StorageKeys::BleBondInfo => {
                     // Make `transmute_copy` happy, because the compiler doesn't know the size of buffer
@@ -257,6 +332,13 @@ impl Value<'_> for StorageData {
                 }
                 #[cfg(feature = "_nrf_ble")]
                 StorageKeys::ActiveBleProfile => Ok(StorageData::ActiveBleProfile(buffer[1])),
+                #[cfg(feature = "_nrf_ble")]
+                StorageKeys::ProfileTxPower => {
+                    if buffer.len() < 3 {
+                        return Err(SerializationError::InvalidData);
+                    }
+                    Ok(StorageData::ProfileTxPower(buffer[1], buffer[2] as i8))
+                }
             }
         } else {
             Err(SerializationError::Custom(1))
@@ -275,10 +357,14 @@ impl StorageData {
             }
             StorageData::MacroData(_) => StorageKeys::MacroData as u32,
             StorageData::ConnectionType(_) => StorageKeys::ConnectionType as u32,
+            StorageData::EncoderConfig(id, _) => get_encoder_config_key(*id),
+            StorageData::MagicState(_) => StorageKeys::MagicState as u32,
             #[cfg(feature = "_nrf_ble")]
             StorageData::BondInfo(b) => get_bond_info_key(b.slot_num),
             #[cfg(feature = "_nrf_ble")]
             StorageData::ActiveBleProfile(_) => StorageKeys::ActiveBleProfile as u32,
+            #[cfg(feature = "_nrf_ble")]
+            StorageData::ProfileTxPower(profile, _) => get_profile_tx_power_key(*profile),
         }
     }
 }
@@ -301,7 +387,8 @@ pub(crate) struct KeymapKey {
     row: usize,
     col: usize,
     layer: usize,
-    action: KeyAction,
+    /// `None` if the saved action can only be set in the default keymap, the default keymap is kept for it
+    action: Option<KeyAction>,
 }
 
 pub(crate) struct Storage<
@@ -365,23 +452,43 @@ impl<F: AsyncNorFlash, const ROW: usize, const COL: usize, const NUM_LAYER: usiz
         #[cfg(not(feature = "_nrf_ble"))]
         let start_addr = config.start_addr;
 
+        let sector_size = if config.sector_size == 0 {
+            F::ERASE_SIZE
+        } else {
+            config.sector_size
+        };
+        assert!(
+            sector_size % F::ERASE_SIZE == 0,
+            "Storage's sector size MUST BE a multiplier of flash's erase size"
+        );
+        let storage_size = config.num_sectors as usize * sector_size;
+        assert!(
+            storage_size <= flash.capacity(),
+            "Storage size exceeds the flash capacity"
+        );
+
         // Check storage setting
         info!(
             "Flash capacity {} KB, RMK use {} KB({} sectors) starting from 0x{:X} as storage",
             flash.capacity() / 1024,
-            (F::ERASE_SIZE * config.num_sectors as usize) / 1024,
+            storage_size / 1024,
             config.num_sectors,
             config.start_addr,
         );
         let storage_range = if start_addr == 0 {
-            (flash.capacity() - config.num_sectors as usize * F::ERASE_SIZE) as u32
-                ..flash.capacity() as u32
+            // Align the start of the storage to the sector size
+            let end = flash.capacity() / sector_size * sector_size;
+            (end - storage_size) as u32..end as u32
         } else {
             assert!(
-                start_addr % F::ERASE_SIZE == 0,
+                start_addr % sector_size == 0,
                 "Storage's start addr MUST BE a multiplier of sector size"
             );
-            start_addr as u32..(start_addr + config.num_sectors as usize * F::ERASE_SIZE) as u32
+            assert!(
+                start_addr + storage_size <= flash.capacity(),
+                "Storage's end addr exceeds the flash capacity"
+            );
+            start_addr as u32..(start_addr + storage_size) as u32
         };
 
         let mut storage = Self {
@@ -419,6 +526,9 @@ impl<F: AsyncNorFlash, const ROW: usize, const COL: usize, const NUM_LAYER: usiz
             }
         }
 
+        storage.read_encoder_config().await;
+        storage.read_magic_state().await;
+
         storage
     }
 
@@ -480,7 +590,7 @@ impl<F: AsyncNorFlash, const ROW: usize, const COL: usize, const NUM_LAYER: usiz
                         row: row as usize,
                         col: col as usize,
                         layer: layer as usize,
-                        action,
+                        action: Some(action),
                     });
                     let key = get_keymap_key::<ROW, COL, NUM_LAYER>(
                         row as usize,
@@ -508,6 +618,30 @@ impl<F: AsyncNorFlash, const ROW: usize, const COL: usize, const NUM_LAYER: usiz
                     )
                     .await
                 }
+                FlashOperationMessage::MagicState(state) => {
+                    let data = StorageData::MagicState(state);
+                    store_item::<u32, StorageData, _>(
+                        &mut self.flash,
+                        self.storage_range.clone(),
+                        &mut storage_cache,
+                        &mut self.buffer,
+                        &data.key(),
+                        &data,
+                    )
+                    .await
+                }
+                FlashOperationMessage::EncoderResolution { id, resolution } => {
+                    let data = StorageData::EncoderConfig(id, resolution);
+                    store_item::<u32, StorageData, _>(
+                        &mut self.flash,
+                        self.storage_range.clone(),
+                        &mut storage_cache,
+                        &mut self.buffer,
+                        &data.key(),
+                        &data,
+                    )
+                    .await
+                }
                 #[cfg(feature = "_nrf_ble")]
                 FlashOperationMessage::ActiveBleProfile(profile) => {
                     let data = StorageData::ActiveBleProfile(profile);
@@ -522,6 +656,19 @@ impl<F: AsyncNorFlash, const ROW: usize, const COL: usize, const NUM_LAYER: usiz
                     .await
                 }
                 #[cfg(feature = "_nrf_ble")]
+                FlashOperationMessage::ProfileTxPower(profile, dbm) => {
+                    let data = StorageData::ProfileTxPower(profile, dbm);
+                    store_item::<u32, StorageData, _>(
+                        &mut self.flash,
+                        self.storage_range.clone(),
+                        &mut storage_cache,
+                        &mut self.buffer,
+                        &data.key(),
+                        &data,
+                    )
+                    .await
+                }
+                #[cfg(feature = "_nrf_ble")]
                 FlashOperationMessage::ClearSlot(key) => {
                     info!("Clearing bond info slot_num: {}", key);
                     // Remove item in `sequential-storage` is quite expensive, so just override the item with `removed = true`
@@ -583,7 +730,9 @@ impl<F: AsyncNorFlash, const ROW: usize, const COL: usize, const NUM_LAYER: usiz
                         assert!(key.layer < NUM_LAYER);
                         assert!(key.row < ROW);
                         assert!(key.col < COL);
-                        keymap[key.layer][key.row][key.col] = key.action;
+                        if let Some(action) = key.action {
+                            keymap[key.layer][key.row][key.col] = action;
+                        }
                     }
                     _ => continue,
                 }
@@ -593,6 +742,38 @@ impl<F: AsyncNorFlash, const ROW: usize, const COL: usize, const NUM_LAYER: usiz
         Ok(())
     }
 
+    /// Read the saved encoder resolutions and apply them
+    pub(crate) async fn read_encoder_config(&mut self) {
+        for id in 0..MAX_ENCODER_NUM as u8 {
+            if let Ok(Some(StorageData::EncoderConfig(_, resolution))) =
+                fetch_item::<u32, StorageData, _>(
+                    &mut self.flash,
+                    self.storage_range.clone(),
+                    &mut NoCache::new(),
+                    &mut self.buffer,
+                    &get_encoder_config_key(id),
+                )
+                .await
+            {
+                ENCODER_RESOLUTION[id as usize].store(resolution, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub(crate) async fn read_magic_state(&mut self) {
+        if let Ok(Some(StorageData::MagicState(state))) = fetch_item::<u32, StorageData, _>(
+            &mut self.flash,
+            self.storage_range.clone(),
+            &mut NoCache::new(),
+            &mut self.buffer,
+            &(StorageKeys::MagicState as u32),
+        )
+        .await
+        {
+            SAVED_MAGIC_STATE.store(state as u32, Ordering::Relaxed);
+        }
+    }
+
     pub(crate) async fn read_macro_cache(&mut self, macro_cache: &mut [u8]) -> Result<(), ()> {
         // Read storage and send back from send_channel
         let read_data = fetch_item::<u32, StorageData, _>(
@@ -654,7 +835,7 @@ impl<F: AsyncNorFlash, const ROW: usize, const COL: usize, const NUM_LAYER: usiz
                         row,
                         col,
                         layer,
-                        action: *action,
+                        action: Some(*action),
                     });
 
                     let key = get_keymap_key::<ROW, COL, NUM_LAYER>(row, col, layer);
@@ -723,3 +904,57 @@ const fn get_buffer_size() -> usize {
         buffer_size + 32 - remainder
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::Action;
+    use crate::keycode::KeyCode;
+
+    fn round_trip(action: KeyAction) -> Option<KeyAction> {
+        let mut buffer = [0_u8; 8];
+        let data = StorageData::KeymapKey(KeymapKey {
+            row: 1,
+            col: 2,
+            layer: 3,
+            action: Some(action),
+        });
+        let len = data.serialize_into(&mut buffer).unwrap();
+        match StorageData::deserialize_from(&buffer[..len]).unwrap() {
+            StorageData::KeymapKey(k) => {
+                assert_eq!((k.row, k.col, k.layer), (1, 2, 3));
+                k.action
+            }
+            _ => panic!("Deserialized storage data is not a keymap key"),
+        }
+    }
+
+    #[test]
+    fn test_keymap_key_round_trip() {
+        let a = KeyAction::Single(Action::Key(KeyCode::A));
+        assert_eq!(round_trip(a), Some(a));
+        let lt = KeyAction::LayerTapHold(Action::Key(KeyCode::Space), 2);
+        assert_eq!(round_trip(lt), Some(lt));
+        let key_lock = KeyAction::Single(Action::KeyLock);
+        assert_eq!(round_trip(key_lock), Some(key_lock));
+        let game_mode = KeyAction::Single(Action::GameModeToggle);
+        assert_eq!(round_trip(game_mode), Some(game_mode));
+        let sticky = KeyAction::Single(Action::StickyLayer(2));
+        assert_eq!(round_trip(sticky), Some(sticky));
+        let momentary_or_toggle = KeyAction::Single(Action::LayerMomentaryOrToggle(1));
+        assert_eq!(round_trip(momentary_or_toggle), Some(momentary_or_toggle));
+        #[cfg(feature = "gamepad")]
+        {
+            let button = KeyAction::Single(Action::GamepadButton(31));
+            assert_eq!(round_trip(button), Some(button));
+            let axis = KeyAction::Single(Action::GamepadAxis(3, false));
+            assert_eq!(round_trip(axis), Some(axis));
+        }
+
+        // Actions which can only be set in the default keymap are kept in the default keymap
+        let repeating = KeyAction::Repeating(Action::Key(KeyCode::AudioVolUp), 200);
+        assert_eq!(round_trip(repeating), None);
+        let on_release = KeyAction::OnRelease(Action::Key(KeyCode::Macro0));
+        assert_eq!(round_trip(on_release), None);
+    }
+}
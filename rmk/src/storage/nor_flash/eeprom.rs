@@ -0,0 +1,274 @@
+use embedded_storage::Storage as EepromStorage;
+use embedded_storage_async::nor_flash::{
+    ErrorType, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash,
+};
+
+/// Erased value of a nor flash, the erase of the eeprom is emulated by writing this value
+const ERASED_BYTE: u8 = 0xFF;
+
+/// Size of the chunk which is used when emulating erase
+const ERASE_CHUNK_SIZE: usize = 32;
+
+/// Error of [`EepromFlash`]
+#[derive(Debug)]
+pub enum EepromFlashError<E> {
+    /// The offset or the length isn't aligned to `WRITE_SIZE`/`ERASE_SIZE`
+    NotAligned,
+    /// The accessed range is out of the eeprom
+    OutOfBounds,
+    /// Error of the underlying eeprom
+    Eeprom(E),
+}
+
+impl<E: core::fmt::Debug> NorFlashError for EepromFlashError<E> {
+    fn kind(&self) -> NorFlashErrorKind {
+        match self {
+            EepromFlashError::NotAligned => NorFlashErrorKind::NotAligned,
+            EepromFlashError::OutOfBounds => NorFlashErrorKind::OutOfBounds,
+            EepromFlashError::Eeprom(_) => NorFlashErrorKind::Other,
+        }
+    }
+}
+
+/// Adapter which makes a byte-addressable external storage(for example, an I2C/SPI EEPROM
+/// which implements `embedded_storage::Storage`) usable as RMK's storage backend.
+///
+/// EEPROMs don't have an erase operation, so the erase is emulated by filling the range with `0xFF`.
+/// The emulated sector size is `SECTOR_SIZE`, which should be a multiple of the page size of the eeprom.
+///
+/// External nor flash chips which already implement `NorFlash` don't need this adapter,
+/// pass them to `run_rmk`(blocking) or `run_rmk_with_async_flash`(async) directly.
+pub struct EepromFlash<S: EepromStorage, const SECTOR_SIZE: usize> {
+    eeprom: S,
+}
+
+impl<S: EepromStorage, const SECTOR_SIZE: usize> EepromFlash<S, SECTOR_SIZE> {
+    pub fn new(eeprom: S) -> Self {
+        assert!(
+            SECTOR_SIZE > 0 && SECTOR_SIZE % ERASE_CHUNK_SIZE == 0,
+            "Sector size of eeprom MUST BE a multiple of 32"
+        );
+        Self { eeprom }
+    }
+
+    /// Consume the adapter, return the underlying eeprom
+    pub fn release(self) -> S {
+        self.eeprom
+    }
+
+    /// Check whether the range is inside the eeprom
+    fn check_bounds(&self, offset: u32, len: usize) -> Result<(), EepromFlashError<S::Error>> {
+        match (offset as usize).checked_add(len) {
+            Some(end) if end <= self.eeprom.capacity() => Ok(()),
+            _ => Err(EepromFlashError::OutOfBounds),
+        }
+    }
+}
+
+impl<S: EepromStorage, const SECTOR_SIZE: usize> ErrorType for EepromFlash<S, SECTOR_SIZE>
+where
+    S::Error: core::fmt::Debug,
+{
+    type Error = EepromFlashError<S::Error>;
+}
+
+impl<S: EepromStorage, const SECTOR_SIZE: usize> ReadNorFlash for EepromFlash<S, SECTOR_SIZE>
+where
+    S::Error: core::fmt::Debug,
+{
+    const READ_SIZE: usize = 1;
+
+    async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        self.check_bounds(offset, bytes.len())?;
+        self.eeprom
+            .read(offset, bytes)
+            .map_err(EepromFlashError::Eeprom)
+    }
+
+    fn capacity(&self) -> usize {
+        // Only full sectors are usable
+        self.eeprom.capacity() / SECTOR_SIZE * SECTOR_SIZE
+    }
+}
+
+impl<S: EepromStorage, const SECTOR_SIZE: usize> NorFlash for EepromFlash<S, SECTOR_SIZE>
+where
+    S::Error: core::fmt::Debug,
+{
+    const WRITE_SIZE: usize = 1;
+    const ERASE_SIZE: usize = SECTOR_SIZE;
+
+    async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.check_bounds(offset, bytes.len())?;
+        self.eeprom
+            .write(offset, bytes)
+            .map_err(EepromFlashError::Eeprom)
+    }
+
+    async fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        if from > to || from as usize % SECTOR_SIZE != 0 || to as usize % SECTOR_SIZE != 0 {
+            return Err(EepromFlashError::NotAligned);
+        }
+        self.check_bounds(from, (to - from) as usize)?;
+
+        let erased = [ERASED_BYTE; ERASE_CHUNK_SIZE];
+        let mut offset = from;
+        while offset < to {
+            self.eeprom
+                .write(offset, &erased)
+                .map_err(EepromFlashError::Eeprom)?;
+            offset += ERASE_CHUNK_SIZE as u32;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use embassy_futures::block_on;
+    use embedded_storage::ReadStorage;
+
+    /// Eeprom in memory, which records every write
+    struct MockEeprom {
+        data: std::vec::Vec<u8>,
+        writes: std::vec::Vec<(u32, usize)>,
+        fail: bool,
+    }
+
+    impl MockEeprom {
+        fn new(capacity: usize) -> Self {
+            Self {
+                data: std::vec![0; capacity],
+                writes: std::vec::Vec::new(),
+                fail: false,
+            }
+        }
+    }
+
+    impl ReadStorage for MockEeprom {
+        type Error = ();
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            let offset = offset as usize;
+            bytes.copy_from_slice(self.data.get(offset..offset + bytes.len()).ok_or(())?);
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            self.data.len()
+        }
+    }
+
+    impl EepromStorage for MockEeprom {
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            if self.fail {
+                return Err(());
+            }
+            let offset = offset as usize;
+            self.data
+                .get_mut(offset..offset + bytes.len())
+                .ok_or(())?
+                .copy_from_slice(bytes);
+            self.writes.push((offset as u32, bytes.len()));
+            Ok(())
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_sector_size_not_aligned() {
+        EepromFlash::<_, 48>::new(MockEeprom::new(256));
+    }
+
+    #[test]
+    fn test_capacity() {
+        // Only full sectors are usable
+        let flash = EepromFlash::<_, 64>::new(MockEeprom::new(200));
+        assert_eq!(ReadNorFlash::capacity(&flash), 192);
+    }
+
+    #[test]
+    fn test_read_write_bounds() {
+        let mut flash = EepromFlash::<_, 64>::new(MockEeprom::new(200));
+        block_on(async {
+            flash.write(198, &[1, 2]).await.unwrap();
+            let mut buf = [0; 2];
+            flash.read(198, &mut buf).await.unwrap();
+            assert_eq!(buf, [1, 2]);
+
+            // The range is checked against the eeprom before accessing it
+            assert!(matches!(
+                flash.write(199, &[1, 2]).await,
+                Err(EepromFlashError::OutOfBounds)
+            ));
+            assert!(matches!(
+                flash.read(199, &mut buf).await,
+                Err(EepromFlashError::OutOfBounds)
+            ));
+            assert!(matches!(
+                flash.read(u32::MAX, &mut buf).await,
+                Err(EepromFlashError::OutOfBounds)
+            ));
+        });
+        assert_eq!(flash.release().writes, [(198, 2)]);
+    }
+
+    #[test]
+    fn test_erase_alignment() {
+        let mut flash = EepromFlash::<_, 64>::new(MockEeprom::new(256));
+        block_on(async {
+            assert!(matches!(
+                flash.erase(32, 128).await,
+                Err(EepromFlashError::NotAligned)
+            ));
+            assert!(matches!(
+                flash.erase(64, 100).await,
+                Err(EepromFlashError::NotAligned)
+            ));
+            assert!(matches!(
+                flash.erase(128, 64).await,
+                Err(EepromFlashError::NotAligned)
+            ));
+            assert!(matches!(
+                flash.erase(192, 320).await,
+                Err(EepromFlashError::OutOfBounds)
+            ));
+        });
+        // Nothing is written by the rejected erases
+        assert!(flash.release().writes.is_empty());
+    }
+
+    #[test]
+    fn test_emulated_erase() {
+        let mut flash = EepromFlash::<_, 64>::new(MockEeprom::new(256));
+        block_on(async {
+            // An empty range is a no-op
+            flash.erase(64, 64).await.unwrap();
+            flash.erase(64, 192).await.unwrap();
+        });
+        let eeprom = flash.release();
+        // The erase is emulated by filling the range with 0xFF, chunk by chunk
+        assert_eq!(eeprom.writes, [(64, 32), (96, 32), (128, 32), (160, 32)]);
+        assert!(eeprom.data[..64].iter().all(|&b| b == 0));
+        assert!(eeprom.data[64..192].iter().all(|&b| b == ERASED_BYTE));
+        assert!(eeprom.data[192..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn test_eeprom_error() {
+        let mut eeprom = MockEeprom::new(256);
+        eeprom.fail = true;
+        let mut flash = EepromFlash::<_, 64>::new(eeprom);
+        block_on(async {
+            assert!(matches!(
+                flash.erase(0, 64).await,
+                Err(EepromFlashError::Eeprom(()))
+            ));
+            assert!(matches!(
+                flash.write(0, &[1]).await,
+                Err(EepromFlashError::Eeprom(()))
+            ));
+        });
+    }
+}
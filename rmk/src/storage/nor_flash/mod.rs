@@ -1,2 +1,3 @@
+pub mod eeprom;
 #[cfg(feature = "_esp_ble")]
 pub mod esp_partition;
@@ -1,7 +1,5 @@
 use ssmarshal::serialize;
-use usbd_hid::descriptor::{
-    generator_prelude::*, MediaKeyboardReport, MouseReport, SystemControlReport,
-};
+use usbd_hid::descriptor::{generator_prelude::*, MouseReport, SystemControlReport};
 
 /// KeyboardReport describes a report and its companion descriptor that can be
 /// used to send keyboard button presses to a host and receive the status of the
@@ -45,6 +43,82 @@ pub(crate) struct ViaReport {
     pub(crate) output_data: [u8; 32],
 }
 
+/// GamepadReport describes a gamepad with 32 buttons and 4 axes(X, Y, Z, Rz).
+/// It's sent via a dedicated hid interface, so no report id is used.
+///
+/// Usage 0x05 is gamepad, 0x32 is Z and 0x35 is Rz in generic desktop page.
+#[cfg(feature = "gamepad")]
+#[gen_hid_descriptor(
+    (collection = APPLICATION, usage_page = GENERIC_DESKTOP, usage = 0x05) = {
+        (usage_page = BUTTON, usage_min = BUTTON_1, usage_max = 0x20) = {
+            #[packed_bits 32] #[item_settings data,variable,absolute] buttons=input;
+        };
+        (usage_page = GENERIC_DESKTOP,) = {
+            (usage = X,) = {
+                #[item_settings data,variable,absolute] x=input;
+            };
+            (usage = Y,) = {
+                #[item_settings data,variable,absolute] y=input;
+            };
+            (usage = 0x32,) = {
+                #[item_settings data,variable,absolute] z=input;
+            };
+            (usage = 0x35,) = {
+                #[item_settings data,variable,absolute] rz=input;
+            };
+        };
+    }
+)]
+#[derive(Default, Clone, Copy)]
+pub struct GamepadReport {
+    pub(crate) buttons: u32,
+    pub(crate) x: i8,
+    pub(crate) y: i8,
+    pub(crate) z: i8,
+    pub(crate) rz: i8,
+}
+
+#[cfg(feature = "gamepad")]
+impl GamepadReport {
+    /// Set the value of an axis, 0 for X, 1 for Y, 2 for Z, 3 for Rz
+    pub(crate) fn set_axis(&mut self, axis: u8, value: i8) {
+        match axis {
+            0 => self.x = value,
+            1 => self.y = value,
+            2 => self.z = value,
+            3 => self.rz = value,
+            _ => warn!("Invalid gamepad axis: {}", axis),
+        }
+    }
+}
+
+/// BatteryReport reports the battery strength(usage 0x20) in generic device controls page(0x06).
+/// It's sent via a dedicated hid interface, so no report id is used.
+///
+/// The level is scaled to the logical range 0 ~ 255.
+#[cfg(feature = "usb_battery")]
+#[gen_hid_descriptor(
+    (collection = APPLICATION, usage_page = 0x06, usage = 0x20) = {
+        (usage = 0x20,) = {
+            #[item_settings data,variable,absolute] level=input;
+        };
+    }
+)]
+#[derive(Default, Clone, Copy)]
+pub struct BatteryReport {
+    pub(crate) level: u8,
+}
+
+#[cfg(feature = "usb_battery")]
+impl BatteryReport {
+    /// Create a battery report from the battery level in percent
+    pub(crate) fn from_percent(percent: u8) -> Self {
+        Self {
+            level: (percent.min(100) as u16 * 255 / 100) as u8,
+        }
+    }
+}
+
 /// Predefined report ids for composite hid report.
 /// Should be same with `#[gen_hid_descriptor]`
 /// DO NOT EDIT
@@ -69,6 +143,21 @@ impl CompositeReportType {
     }
 }
 
+/// ConsumerReport describes a consumer control only interface, which is used when the keyboard interface is omitted.
+/// The report id is same with the consumer report in [`CompositeReport`], so that the media report can be sent in the same way.
+#[gen_hid_descriptor(
+    (collection = APPLICATION, usage_page = CONSUMER, usage = CONSUMER_CONTROL) = {
+        (report_id = 0x02,) = {
+            (usage_page = CONSUMER, usage_min = 0x00, usage_max = 0x514) = {
+            #[item_settings data,array,absolute,not_null] media_usage_id=input;
+            }
+        };
+    }
+)]
+pub(crate) struct ConsumerReport {
+    pub(crate) media_usage_id: u16,
+}
+
 /// A composite hid report which contains mouse, consumer, system reports.
 /// Report id is used to distinguish from them.
 #[gen_hid_descriptor(
@@ -124,6 +213,28 @@ pub struct CompositeReport {
 }
 
 impl CompositeReport {
+    /// Release a consumer usage, returns whether the report is changed.
+    ///
+    /// Only the held usage is cleared, so releasing another consumer key doesn't release the held one.
+    pub(crate) fn release_media(&mut self, usage_id: u16) -> bool {
+        if self.media_usage_id == usage_id {
+            self.media_usage_id = 0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Release a system control usage, returns whether the report is changed
+    pub(crate) fn release_system(&mut self, usage_id: u8) -> bool {
+        if self.system_usage_id == usage_id {
+            self.system_usage_id = 0;
+            true
+        } else {
+            false
+        }
+    }
+
     pub(crate) fn reset_mouse(&mut self) {
         self.x = 0;
         self.y = 0;
@@ -151,8 +262,9 @@ impl CompositeReport {
                 Ok(serialize(data, &mouse_report)?)
             }
             CompositeReportType::Media => {
-                let consumer_report = MediaKeyboardReport {
-                    usage_id: self.media_usage_id,
+                // Same layout as `MediaKeyboardReport`, which is also used by the consumer control only interface
+                let consumer_report = ConsumerReport {
+                    media_usage_id: self.media_usage_id,
                 };
                 Ok(serialize(data, &consumer_report)?)
             }
@@ -165,3 +277,29 @@ impl CompositeReport {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_hold_media_usage() {
+        let mut report = CompositeReport::default();
+        report.media_usage_id = 0xB3;
+        // Releasing a key which isn't held doesn't affect the held key
+        assert!(!report.release_media(0xE9));
+        assert_eq!(report.media_usage_id, 0xB3);
+        assert!(report.release_media(0xB3));
+        assert_eq!(report.media_usage_id, 0);
+    }
+
+    #[test]
+    fn test_hold_system_usage() {
+        let mut report = CompositeReport::default();
+        report.system_usage_id = 0x82;
+        assert!(!report.release_system(0x81));
+        assert_eq!(report.system_usage_id, 0x82);
+        assert!(report.release_system(0x82));
+        assert_eq!(report.system_usage_id, 0);
+    }
+}
@@ -1,6 +1,8 @@
 pub(crate) mod descriptor;
 
 use core::sync::atomic::{AtomicU8, Ordering};
+#[cfg(feature = "usb_battery")]
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
 use embassy_time::Timer;
 use embassy_usb::{
     class::hid::{Config, HidReaderWriter, HidWriter, ReportId, RequestHandler, State},
@@ -9,17 +11,44 @@ use embassy_usb::{
     Builder, Handler, UsbDevice,
 };
 use static_cell::StaticCell;
-use usbd_hid::descriptor::SerializedDescriptor;
+use usbd_hid::descriptor::{AsInputReport, SerializedDescriptor};
 
 use crate::{
-    config::KeyboardUsbConfig,
-    hid::{UsbHidReader, UsbHidReaderWriter, UsbHidWriter},
-    usb::descriptor::{CompositeReport, ViaReport},
+    config::{KeyboardUsbConfig, UsbPrimaryDevice},
+    hid::{
+        ConnectionType, ConnectionTypeWrapper, HidError, HidWriterWrapper, UsbHidReader,
+        UsbHidReaderWriter, UsbHidWriter,
+    },
+    usb::descriptor::{CompositeReport, CompositeReportType, ConsumerReport, ViaReport},
     CONNECTION_STATE,
 };
 
 pub(crate) static USB_STATE: AtomicU8 = AtomicU8::new(UsbState::Disabled as u8);
 
+/// Latest battery level in percent, which is reported to the host via the USB battery interface
+#[cfg(feature = "usb_battery")]
+pub(crate) static BATTERY_LEVEL_SIGNAL: Signal<CriticalSectionRawMutex, u8> = Signal::new();
+
+/// Update the battery level(in percent) which is reported to the host via USB.
+///
+/// The BLE battery service updates it automatically, call it when the battery level is read by other ways.
+#[cfg(feature = "usb_battery")]
+pub fn set_battery_level(percent: u8) {
+    BATTERY_LEVEL_SIGNAL.signal(percent);
+}
+
+/// Report the battery level to the host whenever it's updated
+#[cfg(feature = "usb_battery")]
+pub(crate) async fn usb_battery_task<W: HidWriterWrapper>(battery_hid_writer: &mut W) {
+    loop {
+        let percent = BATTERY_LEVEL_SIGNAL.wait().await;
+        let report = crate::usb::descriptor::BatteryReport::from_percent(percent);
+        if let Err(e) = battery_hid_writer.write_serialize(&report).await {
+            error!("Send battery report error: {:?}", e);
+        }
+    }
+}
+
 /// USB state
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -67,21 +96,63 @@ pub(crate) async fn wait_for_usb_enabled() {
     }
 }
 
+/// Writer of the hid interface which contains mouse, system control and consumer control reports.
+///
+/// If the primary device is consumer control, the interface declares the consumer control report only,
+/// so reports of other types are dropped instead of being sent with an undeclared report id.
+pub(crate) struct UsbOtherHidWriter<'d, D: Driver<'d>> {
+    writer: UsbHidWriter<'d, D, 9>,
+    primary_device: UsbPrimaryDevice,
+}
+
+impl<'d, D: Driver<'d>> ConnectionTypeWrapper for UsbOtherHidWriter<'d, D> {
+    fn get_conn_type(&self) -> ConnectionType {
+        ConnectionType::Usb
+    }
+}
+
+impl<'d, D: Driver<'d>> HidWriterWrapper for UsbOtherHidWriter<'d, D> {
+    async fn write_serialize<IR: AsInputReport>(&mut self, r: &IR) -> Result<(), HidError> {
+        self.writer.write_serialize(r).await
+    }
+
+    /// Write a report, the first byte is the report id
+    async fn write(&mut self, report: &[u8]) -> Result<(), HidError> {
+        if self.primary_device == UsbPrimaryDevice::Consumer
+            && report.first() != Some(&(CompositeReportType::Media as u8))
+        {
+            return Ok(());
+        }
+        self.writer.write(report).await
+    }
+}
+
 // In this case, report id should be used.
 // The keyboard usb device should have 3 hid instances:
 // 1. Boot keyboard: 1 endpoint in
 // 2. Other: Mouse + System control + Consumer control: 1 endpoint in
 // 3. Via: used to communicate with via: 2 endpoints(in/out)
+// If `gamepad` feature is enabled, there's an extra gamepad hid instance: 1 endpoint in
+// If `usb_battery` feature is enabled, there's an extra battery hid instance: 1 endpoint in
+// If the primary device is consumer control, the boot keyboard is omitted and the other hid instance contains consumer control only
 pub(crate) struct KeyboardUsbDevice<'d, D: Driver<'d>> {
     pub(crate) device: UsbDevice<'d, D>,
-    pub(crate) keyboard_hid_writer: UsbHidWriter<'d, D, 8>,
-    pub(crate) keyboard_hid_reader: UsbHidReader<'d, D, 1>,
-    pub(crate) other_hid_writer: UsbHidWriter<'d, D, 9>,
+    pub(crate) keyboard_hid_writer: Option<UsbHidWriter<'d, D, 8>>,
+    pub(crate) keyboard_hid_reader: Option<UsbHidReader<'d, D, 1>>,
+    pub(crate) other_hid_writer: UsbOtherHidWriter<'d, D>,
     pub(crate) via_hid: UsbHidReaderWriter<'d, D, 32, 32>,
+    #[cfg(feature = "gamepad")]
+    pub(crate) gamepad_hid_writer: UsbHidWriter<'d, D, 8>,
+    #[cfg(feature = "usb_battery")]
+    pub(crate) battery_hid_writer: UsbHidWriter<'d, D, 1>,
 }
 
 impl<D: Driver<'static>> KeyboardUsbDevice<'static, D> {
-    pub(crate) fn new(driver: D, keyboard_config: KeyboardUsbConfig<'static>) -> Self {
+    pub(crate) fn new(
+        driver: D,
+        keyboard_config: KeyboardUsbConfig<'static>,
+        primary_device: UsbPrimaryDevice,
+    ) -> Self {
         // Create embassy-usb Config
         let mut usb_config = embassy_usb::Config::new(keyboard_config.vid, keyboard_config.pid);
         usb_config.manufacturer = Some(keyboard_config.manufacturer);
@@ -120,23 +191,32 @@ impl<D: Driver<'static>> KeyboardUsbDevice<'static, D> {
         static request_handler: UsbRequestHandler = UsbRequestHandler {};
 
         // Initialize two hid interfaces: keyboard & via
-        static keyboard_request_handler: StaticCell<UsbRequestHandler> = StaticCell::new();
-        let keyboard_hid_config = Config {
-            report_descriptor: crate::usb::descriptor::KeyboardReport::desc(),
-            request_handler: Some(keyboard_request_handler.init(UsbRequestHandler {})),
-            poll_ms: 1,
-            max_packet_size: 64,
+        // The keyboard interface is omitted if the keyboard is a consumer control device
+        let keyboard_hid: Option<HidReaderWriter<'_, D, 1, 8>> = match primary_device {
+            UsbPrimaryDevice::Keyboard => {
+                static keyboard_request_handler: StaticCell<UsbRequestHandler> = StaticCell::new();
+                let keyboard_hid_config = Config {
+                    report_descriptor: crate::usb::descriptor::KeyboardReport::desc(),
+                    request_handler: Some(keyboard_request_handler.init(UsbRequestHandler {})),
+                    poll_ms: 1,
+                    max_packet_size: 64,
+                };
+                static KEYBOARD_HID_STATE: StaticCell<State> = StaticCell::new();
+                Some(HidReaderWriter::new(
+                    &mut builder,
+                    KEYBOARD_HID_STATE.init(State::new()),
+                    keyboard_hid_config,
+                ))
+            }
+            UsbPrimaryDevice::Consumer => None,
         };
-        static KEYBOARD_HID_STATE: StaticCell<State> = StaticCell::new();
-        let keyboard_hid: HidReaderWriter<'_, D, 1, 8> = HidReaderWriter::new(
-            &mut builder,
-            KEYBOARD_HID_STATE.init(State::new()),
-            keyboard_hid_config,
-        );
 
         static other_request_handler: StaticCell<UsbRequestHandler> = StaticCell::new();
         let other_hid_config = Config {
-            report_descriptor: CompositeReport::desc(),
+            report_descriptor: match primary_device {
+                UsbPrimaryDevice::Keyboard => CompositeReport::desc(),
+                UsbPrimaryDevice::Consumer => ConsumerReport::desc(),
+            },
             request_handler: Some(other_request_handler.init(UsbRequestHandler {})),
             poll_ms: 1,
             max_packet_size: 64,
@@ -159,15 +239,65 @@ impl<D: Driver<'static>> KeyboardUsbDevice<'static, D> {
         let via_hid: HidReaderWriter<'_, D, 32, 32> =
             HidReaderWriter::new(&mut builder, VIA_STATE.init(State::new()), via_config);
 
+        #[cfg(feature = "gamepad")]
+        let gamepad_hid: HidWriter<'_, D, 8> = {
+            static gamepad_request_handler: StaticCell<UsbRequestHandler> = StaticCell::new();
+            let gamepad_hid_config = Config {
+                report_descriptor: crate::usb::descriptor::GamepadReport::desc(),
+                request_handler: Some(gamepad_request_handler.init(UsbRequestHandler {})),
+                poll_ms: 1,
+                max_packet_size: 64,
+            };
+            static GAMEPAD_HID_STATE: StaticCell<State> = StaticCell::new();
+            HidWriter::new(
+                &mut builder,
+                GAMEPAD_HID_STATE.init(State::new()),
+                gamepad_hid_config,
+            )
+        };
+
+        #[cfg(feature = "usb_battery")]
+        let battery_hid: HidWriter<'_, D, 1> = {
+            static battery_request_handler: StaticCell<UsbRequestHandler> = StaticCell::new();
+            let battery_hid_config = Config {
+                report_descriptor: crate::usb::descriptor::BatteryReport::desc(),
+                request_handler: Some(battery_request_handler.init(UsbRequestHandler {})),
+                poll_ms: 255,
+                max_packet_size: 8,
+            };
+            static BATTERY_HID_STATE: StaticCell<State> = StaticCell::new();
+            HidWriter::new(
+                &mut builder,
+                BATTERY_HID_STATE.init(State::new()),
+                battery_hid_config,
+            )
+        };
+
         // Build usb device
         let usb = builder.build();
-        let (reader, writer) = keyboard_hid.split();
+        let (keyboard_hid_reader, keyboard_hid_writer) = match keyboard_hid {
+            Some(keyboard_hid) => {
+                let (reader, writer) = keyboard_hid.split();
+                (
+                    Some(UsbHidReader::new(reader)),
+                    Some(UsbHidWriter::new(writer)),
+                )
+            }
+            None => (None, None),
+        };
         Self {
             device: usb,
-            keyboard_hid_reader: UsbHidReader::new(reader),
-            keyboard_hid_writer: UsbHidWriter::new(writer),
-            other_hid_writer: UsbHidWriter::new(other_hid),
+            keyboard_hid_reader,
+            keyboard_hid_writer,
+            other_hid_writer: UsbOtherHidWriter {
+                writer: UsbHidWriter::new(other_hid),
+                primary_device,
+            },
             via_hid: UsbHidReaderWriter::new(via_hid),
+            #[cfg(feature = "gamepad")]
+            gamepad_hid_writer: UsbHidWriter::new(gamepad_hid),
+            #[cfg(feature = "usb_battery")]
+            battery_hid_writer: UsbHidWriter::new(battery_hid),
         }
     }
 }
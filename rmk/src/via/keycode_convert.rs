@@ -5,6 +5,12 @@ use crate::{
     keycode::{KeyCode, ModifierCombination},
 };
 
+/// Placeholder via keycode of actions which are too large to be represented as a via keycode, such as `KeyAction::Repeating`.
+///
+/// These actions can only be set in the default keymap, so a saved placeholder never overwrites the default keymap,
+/// and writing the placeholder back from the host leaves the current action unchanged.
+pub(crate) const DEFAULT_KEYMAP_ONLY_KEYCODE: u16 = 0x7F02;
+
 pub(crate) fn to_via_keycode(key_action: KeyAction) -> u16 {
     match key_action {
         KeyAction::No => 0x0000,
@@ -15,6 +21,8 @@ pub(crate) fn to_via_keycode(key_action: KeyAction) -> u16 {
                     k as u16 & 0xFF | 0x7700
                 } else if k.is_user() {
                     k as u16 & 0xF | 0x7E00
+                } else if k.is_magic() {
+                    k as u16 & 0xFF | 0x7000
                 } else {
                     k as u16
                 }
@@ -23,6 +31,18 @@ pub(crate) fn to_via_keycode(key_action: KeyAction) -> u16 {
             Action::LayerOn(l) => 0x5220 | l as u16,
             Action::DefaultLayer(l) => 0x5240 | l as u16,
             Action::LayerToggle(l) => 0x5260 | l as u16,
+            // RMK specific actions use the end of the QK_USER range
+            Action::KeyLock => 0x7F00,
+            Action::GameModeToggle => 0x7F01,
+            Action::StickyLayer(l) => 0x7F20 | l as u16,
+            Action::LayerMomentaryOrToggle(l) => 0x7F40 | l as u16,
+            // Gamepad buttons use QMK's joystick button keycodes
+            #[cfg(feature = "gamepad")]
+            Action::GamepadButton(b) => 0x7400 | (b as u16 & 0x1F),
+            #[cfg(feature = "gamepad")]
+            Action::GamepadAxis(axis, positive) => {
+                0x7F60 | ((axis as u16 & 0b11) << 1) | positive as u16
+            }
             _ => 0x0000,
         },
         KeyAction::Tap(_) => {
@@ -78,6 +98,8 @@ pub(crate) fn to_via_keycode(key_action: KeyAction) -> u16 {
             );
             0
         }
+        KeyAction::Repeating(_, _) => DEFAULT_KEYMAP_ONLY_KEYCODE,
+        KeyAction::OnRelease(_) => DEFAULT_KEYMAP_ONLY_KEYCODE,
     }
 }
 
@@ -147,8 +169,13 @@ pub(crate) fn from_via_keycode(via_keycode: u16) -> KeyAction {
             warn!("Tap dance {:#X} not supported", via_keycode);
             KeyAction::No
         }
-        0x7000..=0x701F => {
-            // TODO: QMK functions, such as swap ctrl/caps, gui on, haptic, music, clicky, combo, RGB, etc
+        0x7000..=0x7023 => {
+            // Magic keycodes, such as swap ctrl/caps, gui on
+            let keycode = via_keycode & 0xFF | 0x100;
+            KeyAction::Single(Action::Key(KeyCode::from_primitive(keycode)))
+        }
+        0x7024..=0x70FF => {
+            // TODO: QMK functions, such as haptic, music, clicky, combo, RGB, etc
             warn!("QMK functions {:#X} not supported", via_keycode);
             KeyAction::No
         }
@@ -156,6 +183,12 @@ pub(crate) fn from_via_keycode(via_keycode: u16) -> KeyAction {
             let keycode = via_keycode & 0xFF | 0x500;
             KeyAction::Single(Action::Key(KeyCode::from_primitive(keycode)))
         }
+        #[cfg(feature = "gamepad")]
+        0x7400..=0x741F => {
+            // Joystick button
+            let button = via_keycode as u8 & 0x1F;
+            KeyAction::Single(Action::GamepadButton(button))
+        }
         0x7800..=0x783F => {
             // TODO: backlight and rgb configuration
             warn!("Backlight and RGB configuration key not supported");
@@ -176,6 +209,28 @@ pub(crate) fn from_via_keycode(via_keycode: u16) -> KeyAction {
             let keycode = via_keycode & 0xFF | 0x840;
             KeyAction::Single(Action::Key(KeyCode::from_primitive(keycode)))
         }
+        0x7F00 => KeyAction::Single(Action::KeyLock),
+        0x7F01 => KeyAction::Single(Action::GameModeToggle),
+        0x7F20..=0x7F3F => {
+            // Sticky layer
+            let layer = via_keycode as u8 & 0x1F;
+            KeyAction::Single(Action::StickyLayer(layer))
+        }
+        0x7F40..=0x7F5F => {
+            // Momentary layer, toggled by double tap
+            let layer = via_keycode as u8 & 0x1F;
+            KeyAction::Single(Action::LayerMomentaryOrToggle(layer))
+        }
+        #[cfg(feature = "gamepad")]
+        0x7F60..=0x7F67 => {
+            // Gamepad axis, axis(2bits)|direction(1bit)
+            let axis = (via_keycode as u8 >> 1) & 0b11;
+            KeyAction::Single(Action::GamepadAxis(axis, via_keycode & 1 != 0))
+        }
+        DEFAULT_KEYMAP_ONLY_KEYCODE => {
+            warn!("Action which can only be set in the default keymap");
+            KeyAction::No
+        }
         _ => {
             warn!("Via keycode {:#X} is not processed", via_keycode);
             KeyAction::No
@@ -186,6 +241,7 @@ pub(crate) fn from_via_keycode(via_keycode: u16) -> KeyAction {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::keycode::ConsumerKey;
 
     #[test]
     fn test_convert_via_keycode_to_key_action() {
@@ -226,6 +282,13 @@ mod test {
             from_via_keycode(via_keycode)
         );
 
+        // Toggle escape/caps
+        let via_keycode = 0x7022;
+        assert_eq!(
+            KeyAction::Single(Action::Key(KeyCode::MagicToggleEscapeCapsLock)),
+            from_via_keycode(via_keycode)
+        );
+
         // LCtrl(A) -> WithModifier(A)
         let via_keycode = 0x104;
         assert_eq!(
@@ -335,6 +398,10 @@ mod test {
         let a = KeyAction::Single(Action::LayerOn(3));
         assert_eq!(0x5223, to_via_keycode(a));
 
+        // Swap ctrl/caps
+        let a = KeyAction::Single(Action::Key(KeyCode::MagicSwapControlCapsLock));
+        assert_eq!(0x7000, to_via_keycode(a));
+
         // OSL(3)
         let a = KeyAction::OneShot(Action::LayerOn(3));
         assert_eq!(0x5283, to_via_keycode(a));
@@ -409,4 +476,64 @@ mod test {
         );
         assert_eq!(0x2704, to_via_keycode(a));
     }
+
+    #[test]
+    fn test_convert_rmk_actions() {
+        // RMK specific actions round trip through via keycode
+        let actions = [
+            KeyAction::Single(Action::KeyLock),
+            KeyAction::Single(Action::GameModeToggle),
+            KeyAction::Single(Action::StickyLayer(3)),
+            KeyAction::Single(Action::LayerMomentaryOrToggle(1)),
+        ];
+        for action in actions {
+            assert_eq!(action, from_via_keycode(to_via_keycode(action)));
+        }
+        assert_eq!(0x7F00, to_via_keycode(KeyAction::Single(Action::KeyLock)));
+    }
+
+    #[cfg(feature = "gamepad")]
+    #[test]
+    fn test_convert_gamepad_actions() {
+        // JS_5
+        let a = KeyAction::Single(Action::GamepadButton(5));
+        assert_eq!(0x7405, to_via_keycode(a));
+        assert_eq!(a, from_via_keycode(0x7405));
+
+        for axis in 0..4 {
+            for positive in [true, false] {
+                let a = KeyAction::Single(Action::GamepadAxis(axis, positive));
+                assert_eq!(a, from_via_keycode(to_via_keycode(a)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_convert_consumer_keycode() {
+        // Application launch and application control keys round trip through via keycode
+        for via_keycode in 0xA8..=0xC8 {
+            let action = from_via_keycode(via_keycode);
+            match action {
+                KeyAction::Single(Action::Key(k)) => {
+                    assert!(k.is_consumer());
+                    assert_ne!(k.as_consumer_control_usage_id(), ConsumerKey::Zero);
+                }
+                _ => panic!("{:#X} is not a consumer key", via_keycode),
+            }
+            assert_eq!(via_keycode, to_via_keycode(action));
+        }
+
+        // Aliases
+        let a = KeyAction::Single(Action::Key(KeyCode::AlCalculator));
+        assert_eq!(0xB2, to_via_keycode(a));
+        assert_eq!(
+            KeyCode::AcSearch.as_consumer_control_usage_id(),
+            ConsumerKey::Search
+        );
+        assert_eq!(
+            KeyCode::AcZoomIn.as_consumer_control_usage_id(),
+            ConsumerKey::ZoomIn
+        );
+        assert_eq!(KeyCode::AcBack, KeyCode::WwwBack);
+    }
 }
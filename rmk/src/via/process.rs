@@ -1,15 +1,21 @@
 use super::{protocol::*, vial::process_vial};
 use crate::config::VialConfig;
 use crate::{
+    channel_stats::{record_dropped, StatsChannel},
     hid::{HidError, HidReaderWriterWrapper},
+    input_device::rotary_encoder::{
+        encoder_calibration_pulses, start_encoder_calibration, stop_encoder_calibration,
+        ENCODER_RESOLUTION, MAX_ENCODER_NUM,
+    },
     keyboard_macro::{MACRO_SPACE_SIZE, NUM_MACRO},
     keymap::KeyMap,
     storage::{FlashOperationMessage, FLASH_CHANNEL},
     usb::descriptor::ViaReport,
-    via::keycode_convert::{from_via_keycode, to_via_keycode},
+    via::keycode_convert::{from_via_keycode, to_via_keycode, DEFAULT_KEYMAP_ONLY_KEYCODE},
 };
 use byteorder::{BigEndian, ByteOrder, LittleEndian};
 use core::cell::RefCell;
+use core::sync::atomic::Ordering;
 use embassy_time::Instant;
 use num_enum::{FromPrimitive, TryFromPrimitive};
 
@@ -145,6 +151,10 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
                 let row = report.output_data[2];
                 let col = report.output_data[3];
                 let keycode = BigEndian::read_u16(&report.output_data[4..6]);
+                if keycode == DEFAULT_KEYMAP_ONLY_KEYCODE {
+                    // The action at this position can only be set in the default keymap, keep it
+                    return;
+                }
                 let action = from_via_keycode(keycode);
                 info!(
                     "Setting keycode: 0x{:X} at ({},{}), layer {} as {:?}",
@@ -169,16 +179,39 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
                 warn!("Dynamic keymap reset -- not supported")
             }
             ViaCommand::CustomSetValue => {
-                // backlight/rgblight/rgb matrix/led matrix/audio settings here
-                warn!("Custom set value -- not supported")
+                if report.output_data[1] == VIA_ENCODER_CHANNEL_ID {
+                    process_encoder_set_value(report).await;
+                } else if cfg!(feature = "latency_trace")
+                    && report.output_data[1] == VIA_LATENCY_CHANNEL_ID
+                {
+                    #[cfg(feature = "latency_trace")]
+                    crate::latency_trace::reset();
+                } else {
+                    // backlight/rgblight/rgb matrix/led matrix/audio settings here
+                    warn!("Custom set value -- not supported")
+                }
             }
             ViaCommand::CustomGetValue => {
-                // backlight/rgblight/rgb matrix/led matrix/audio settings here
-                warn!("Custom get value -- not supported")
+                if report.output_data[1] == VIA_ENCODER_CHANNEL_ID {
+                    process_encoder_get_value(report);
+                } else if cfg!(feature = "latency_trace")
+                    && report.output_data[1] == VIA_LATENCY_CHANNEL_ID
+                {
+                    #[cfg(feature = "latency_trace")]
+                    process_latency_get_value(report);
+                } else {
+                    // backlight/rgblight/rgb matrix/led matrix/audio settings here
+                    warn!("Custom get value -- not supported")
+                }
             }
             ViaCommand::CustomSave => {
-                // backlight/rgblight/rgb matrix/led matrix/audio settings here
-                warn!("Custom get value -- not supported")
+                if report.output_data[1] == VIA_ENCODER_CHANNEL_ID {
+                    // The encoder settings are saved when they're set, saving the channel finishes the calibration
+                    stop_encoder_calibration();
+                } else {
+                    // backlight/rgblight/rgb matrix/led matrix/audio settings here
+                    warn!("Custom get value -- not supported")
+                }
             }
             ViaCommand::EepromReset => {
                 warn!("Reseting storage..");
@@ -287,9 +320,12 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
                     .enumerate()
                     .for_each(|(i, a)| {
                         let via_keycode = LittleEndian::read_u16(&report.output_data[idx..idx + 2]);
+                        idx += 2;
+                        if via_keycode == DEFAULT_KEYMAP_ONLY_KEYCODE {
+                            return;
+                        }
                         let action: crate::action::KeyAction = from_via_keycode(via_keycode);
                         *a = action;
-                        idx += 2;
                         let current_offset = offset as usize + i;
                         let (row, col, layer) =
                             get_position_from_offset(current_offset, row_num, col_num);
@@ -303,6 +339,7 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
                             row: row as u8,
                             action,
                         }) {
+                            record_dropped(StatsChannel::FlashOperation);
                             error!("Send keymap setting command error")
                         }
                     });
@@ -327,6 +364,83 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize>
     }
 }
 
+/// Adjust the rotary encoder at runtime
+async fn process_encoder_set_value(report: &mut ViaReport) {
+    let id = report.output_data[3];
+    if id as usize >= MAX_ENCODER_NUM {
+        error!("Invalid encoder id: {}", id);
+        report.input_data[0] = ViaCommand::Unhandled as u8;
+        return;
+    }
+    match ViaEncoderValue::try_from_primitive(report.output_data[2]) {
+        Ok(ViaEncoderValue::Resolution) => {
+            let resolution = report.output_data[4];
+            info!("Setting resolution of encoder {}: {}", id, resolution);
+            ENCODER_RESOLUTION[id as usize].store(resolution, Ordering::Relaxed);
+            FLASH_CHANNEL
+                .send(FlashOperationMessage::EncoderResolution { id, resolution })
+                .await;
+        }
+        Ok(ViaEncoderValue::Calibration) => {
+            if report.output_data[4] != 0 {
+                info!("Start encoder calibration");
+                start_encoder_calibration();
+            } else {
+                info!("Stop encoder calibration");
+                stop_encoder_calibration();
+            }
+        }
+        Err(e) => error!("Invalid encoder value: {}", e.number),
+    }
+}
+
+/// Read the rotary encoder settings
+fn process_encoder_get_value(report: &mut ViaReport) {
+    let id = report.output_data[3];
+    if id as usize >= MAX_ENCODER_NUM {
+        error!("Invalid encoder id: {}", id);
+        report.input_data[0] = ViaCommand::Unhandled as u8;
+        return;
+    }
+    match ViaEncoderValue::try_from_primitive(report.output_data[2]) {
+        Ok(ViaEncoderValue::Resolution) => {
+            report.input_data[4] = ENCODER_RESOLUTION[id as usize].load(Ordering::Relaxed);
+        }
+        Ok(ViaEncoderValue::Calibration) => {
+            // The number of detents in a full rotation is given by the host
+            let detents = report.output_data[4];
+            let pulses = encoder_calibration_pulses(id);
+            let suggested = if detents == 0 {
+                0
+            } else {
+                (pulses / detents as u16).min(u8::MAX as u16) as u8
+            };
+            info!(
+                "Encoder {} calibration: {} pulses, suggested resolution: {}",
+                id, pulses, suggested
+            );
+            BigEndian::write_u16(&mut report.input_data[5..7], pulses);
+            report.input_data[7] = suggested;
+        }
+        Err(e) => error!("Invalid encoder value: {}", e.number),
+    }
+}
+
+/// Read the scan-to-report latency statistics
+#[cfg(feature = "latency_trace")]
+fn process_latency_get_value(report: &mut ViaReport) {
+    let stats = crate::latency_trace::stats().unwrap_or(crate::latency_trace::LatencyStats {
+        min: 0,
+        avg: 0,
+        max: 0,
+        count: 0,
+    });
+    BigEndian::write_u32(&mut report.input_data[2..6], stats.min);
+    BigEndian::write_u32(&mut report.input_data[6..10], stats.avg);
+    BigEndian::write_u32(&mut report.input_data[10..14], stats.max);
+    BigEndian::write_u32(&mut report.input_data[14..18], stats.count);
+}
+
 fn get_position_from_offset(
     offset: usize,
     max_row: usize,
@@ -43,3 +43,25 @@ pub(crate) enum ViaKeyboardInfo {
     FirmwareVersion = 0x04,
     DeviceIndication = 0x05,
 }
+
+/// Custom value channel id used by RMK to adjust the rotary encoders
+pub(crate) const VIA_ENCODER_CHANNEL_ID: u8 = 0x80;
+
+/// Custom value channel id used by RMK to report the scan-to-report latency.
+///
+/// `CustomGetValue` returns `[command, channel_id, min, avg, max, count]`, each value is a big endian u32 in microseconds.
+/// `CustomSetValue` resets the statistics. Only available when `latency_trace` feature is enabled.
+pub(crate) const VIA_LATENCY_CHANNEL_ID: u8 = 0x81;
+
+/// Values of the encoder channel, used in `CustomSetValue` and `CustomGetValue`.
+///
+/// Data layout: `[command, channel_id, value_id, encoder_id, value...]`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, TryFromPrimitive)]
+#[repr(u8)]
+pub(crate) enum ViaEncoderValue {
+    /// Resolution(pulses per step) of the encoder, 0 means using the default value in firmware
+    Resolution = 0x01,
+    /// Set: start the calibration if the value is non-zero, otherwise stop it. `CustomSave` of the channel stops it as well.
+    /// Get: the pulses counted and the suggested resolution, the calibration keeps running
+    Calibration = 0x02,
+}